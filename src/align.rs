@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::group::{EnteredGroup, Groups};
+use crate::menu::text_button;
+use crate::selection::Selected;
+use crate::table::TableHead;
+use crate::AppState;
+
+pub struct AlignPlugin;
+
+/// This plugin is responsible for aligning and distributing the selected tables with the buttons
+/// of a panel that is shown while more than one table is selected, and for the smart guides that
+/// show which tables line up while dragging.
+/// Groups are aligned as a whole
+impl Plugin for AlignPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SmartGuides>()
+            .add_systems(OnEnter(AppState::Running), spawn_align_panel)
+            .add_systems(
+                Update,
+                (show_align_panel, align_buttons, draw_smart_guides)
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// Lines between edges and centers of tables that line up with the dragged tables
+#[derive(Resource, Default)]
+pub struct SmartGuides(pub Vec<(Vec2, Vec2)>);
+
+/// The outline of a table in the world
+pub fn table_bounds(table_head: &TableHead, transform: &GlobalTransform) -> Rect {
+    let top_left = transform.translation().truncate();
+    let size = table_head.size();
+    Rect::from_corners(top_left, top_left + size * Vec2::new(1.0, -1.0))
+}
+
+/// Left, center and right of `rect`, or bottom, center and top
+fn lines_of(rect: Rect, axis: usize) -> [f32; 3] {
+    [rect.min[axis], rect.center()[axis], rect.max[axis]]
+}
+
+/// How far to move `moving` so that one of its edges or its center lines up with an edge or center
+/// of one of `others`, as long as that is closer than `distance`
+pub fn smart_snap(moving: Rect, others: &[Rect], distance: f32) -> Vec2 {
+    let mut offset = Vec2::ZERO;
+    for axis in 0..2 {
+        offset[axis] = others
+            .iter()
+            .flat_map(|other| lines_of(*other, axis))
+            .flat_map(|line| lines_of(moving, axis).map(move |edge| line - edge))
+            .filter(|offset| offset.abs() < distance)
+            .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .unwrap_or(0.0);
+    }
+    offset
+}
+
+/// Lines spanning `moving` and each of `others` along the edges and centers they share
+pub fn alignment_lines(moving: Rect, others: &[Rect]) -> Vec<(Vec2, Vec2)> {
+    let mut lines = vec![];
+    for other in others {
+        let span = moving.union(*other);
+        for axis in 0..2 {
+            for line in lines_of(*other, axis) {
+                if !lines_of(moving, axis)
+                    .iter()
+                    .any(|edge| (edge - line).abs() < 0.5)
+                {
+                    continue;
+                }
+                lines.push(if axis == 0 {
+                    (Vec2::new(line, span.min.y), Vec2::new(line, span.max.y))
+                } else {
+                    (Vec2::new(span.min.x, line), Vec2::new(span.max.x, line))
+                });
+            }
+        }
+    }
+    lines
+}
+
+fn draw_smart_guides(mut gizmos: Gizmos, smart_guides: Res<SmartGuides>) {
+    for (from, to) in &smart_guides.0 {
+        gizmos.line_2d(*from, *to, Color::srgb(0.9, 0.3, 0.7));
+    }
+}
+
+#[derive(Component)]
+struct AlignPanel;
+
+#[derive(Component, Clone, Copy)]
+enum AlignButton {
+    Left,
+    Center,
+    Right,
+    Top,
+    Middle,
+    Bottom,
+    DistributeHorizontally,
+    DistributeVertically,
+}
+
+fn spawn_align_panel(mut cmd: Commands) {
+    cmd.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(5.),
+                left: Val::Percent(50.),
+                flex_direction: FlexDirection::Row,
+                column_gap: Val::Px(3.),
+                padding: UiRect::all(Val::Px(5.)),
+                display: Display::None,
+                ..default()
+            },
+            background_color: Color::linear_rgb(0.1, 0.1, 0.1).into(),
+            ..default()
+        },
+        // Keeps clicks on the panel away from the canvas
+        Interaction::None,
+        AlignPanel,
+    ))
+    .with_children(|panel| {
+        for (label, button) in [
+            ("Left", AlignButton::Left),
+            ("Center", AlignButton::Center),
+            ("Right", AlignButton::Right),
+            ("Top", AlignButton::Top),
+            ("Middle", AlignButton::Middle),
+            ("Bottom", AlignButton::Bottom),
+            ("Distribute H", AlignButton::DistributeHorizontally),
+            ("Distribute V", AlignButton::DistributeVertically),
+        ] {
+            text_button(panel, label, button);
+        }
+    });
+}
+
+fn show_align_panel(
+    selected_q: Query<(), (With<TableHead>, With<Selected>)>,
+    mut panel_q: Query<&mut Style, With<AlignPanel>>,
+) {
+    let display = if selected_q.iter().count() > 1 {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in &mut panel_q {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+}
+
+fn align_buttons(
+    entered: Res<EnteredGroup>,
+    button_q: Query<(&Interaction, &AlignButton), Changed<Interaction>>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform), With<Selected>>,
+    groups: Groups,
+    mut transform_q: Query<&mut Transform>,
+) {
+    let Some(button) = button_q
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| *button)
+    else {
+        return;
+    };
+
+    // Tables of a group that was not entered move together with it
+    let mut items: HashMap<Entity, Rect> = HashMap::new();
+    for (id, table_head, transform) in &selected_q {
+        let item = groups
+            .group_of(id)
+            .filter(|group| entered.0 != Some(*group))
+            .unwrap_or(id);
+        let bounds = table_bounds(table_head, transform);
+        items
+            .entry(item)
+            .and_modify(|rect| *rect = rect.union(bounds))
+            .or_insert(bounds);
+    }
+    if items.len() < 2 {
+        return;
+    }
+    let all = items
+        .values()
+        .copied()
+        .reduce(|a, b| a.union(b))
+        .unwrap_or_default();
+
+    let mut offsets: Vec<(Entity, Vec2)> = match button {
+        AlignButton::Left => items
+            .iter()
+            .map(|(id, rect)| (*id, Vec2::X * (all.min.x - rect.min.x)))
+            .collect(),
+        AlignButton::Center => items
+            .iter()
+            .map(|(id, rect)| (*id, Vec2::X * (all.center().x - rect.center().x)))
+            .collect(),
+        AlignButton::Right => items
+            .iter()
+            .map(|(id, rect)| (*id, Vec2::X * (all.max.x - rect.max.x)))
+            .collect(),
+        AlignButton::Top => items
+            .iter()
+            .map(|(id, rect)| (*id, Vec2::Y * (all.max.y - rect.max.y)))
+            .collect(),
+        AlignButton::Middle => items
+            .iter()
+            .map(|(id, rect)| (*id, Vec2::Y * (all.center().y - rect.center().y)))
+            .collect(),
+        AlignButton::Bottom => items
+            .iter()
+            .map(|(id, rect)| (*id, Vec2::Y * (all.min.y - rect.min.y)))
+            .collect(),
+        AlignButton::DistributeHorizontally => distribute(&items, all, 0),
+        AlignButton::DistributeVertically => distribute(&items, all, 1),
+    };
+    offsets.retain(|(_, offset)| *offset != Vec2::ZERO);
+
+    for (id, offset) in offsets {
+        if let Ok(mut transform) = transform_q.get_mut(id) {
+            transform.translation += offset.extend(0.0);
+        }
+    }
+}
+
+/// Spaces the items evenly along `axis` between the outermost ones, which stay in place
+fn distribute(items: &HashMap<Entity, Rect>, all: Rect, axis: usize) -> Vec<(Entity, Vec2)> {
+    let mut sorted: Vec<_> = items.iter().collect();
+    sorted.sort_by(|(_, a), (_, b)| a.center()[axis].total_cmp(&b.center()[axis]));
+    let total: f32 = sorted.iter().map(|(_, rect)| rect.size()[axis]).sum();
+    let gap = (all.size()[axis] - total) / (sorted.len() - 1) as f32;
+
+    let mut at = all.min[axis];
+    sorted
+        .into_iter()
+        .map(|(id, rect)| {
+            let mut offset = Vec2::ZERO;
+            offset[axis] = at - rect.min[axis];
+            at += rect.size()[axis] + gap;
+            (*id, offset)
+        })
+        .collect()
+}
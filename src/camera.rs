@@ -0,0 +1,69 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::AppState;
+
+pub struct CameraPlugin;
+
+/// This plugin is responsible for moving around the canvas.
+/// Scrolling pans the camera, `Shift` scrolls sideways and `Ctrl` zooms around the cursor
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            pan_and_zoom
+                .run_if(on_event::<MouseWheel>())
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// Pixels scrolled per line, for mice that scroll by lines
+const LINE_HEIGHT: f32 = 20.0;
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 20.0;
+
+fn pan_and_zoom(
+    mut wheel: EventReader<MouseWheel>,
+    keys: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    let Ok((mut transform, mut projection)) = camera_q.get_single_mut() else {
+        return;
+    };
+    let zoom = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    let sideways = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    for event in wheel.read() {
+        let mut scrolled = Vec2::new(event.x, event.y);
+        if event.unit == MouseScrollUnit::Line {
+            scrolled *= LINE_HEIGHT;
+        }
+
+        if zoom {
+            let window = windows.single();
+            // The point under the cursor stays in place
+            let offset = window
+                .cursor_position()
+                .map(|cursor| (cursor - window.size() / 2.0) * Vec2::new(1.0, -1.0))
+                .unwrap_or(Vec2::ZERO);
+            let anchor = transform.translation.truncate() + offset * projection.scale;
+            projection.scale =
+                (projection.scale * 1.002f32.powf(-scrolled.y)).clamp(MIN_ZOOM, MAX_ZOOM);
+            let translation = anchor - offset * projection.scale;
+            transform.translation = translation.extend(transform.translation.z);
+        } else {
+            if sideways {
+                scrolled = Vec2::new(scrolled.y, scrolled.x);
+            }
+            transform.translation +=
+                (Vec2::new(-scrolled.x, scrolled.y) * projection.scale).extend(0.0);
+        }
+    }
+}
@@ -0,0 +1,654 @@
+use std::io::Cursor;
+
+use ab_glyph::{point, Font as _, FontArc, PxScale, ScaleFont};
+use bevy::color::Luminance;
+use bevy::prelude::*;
+
+/// Space around the chart and between the tables and the legend
+const MARGIN: f32 = 10.0;
+/// Pixels per world unit of PNG charts, so printed charts stay sharp
+const PNG_SCALE: f32 = 2.0;
+/// Width of a character of the Courier font of PDF charts, relative to the font size
+const COURIER_WIDTH: f32 = 0.6;
+
+const GRID_COLOR: Srgba = Srgba::rgb(0.2, 0.2, 0.2);
+
+/// A cell as it is drawn into a chart
+#[derive(Clone, Debug)]
+pub struct ChartCell {
+    pub fill: Color,
+    pub text: String,
+    /// The symbol of the palette color the cell is painted with
+    pub symbol: Option<char>,
+}
+
+/// A table as it is drawn into a chart
+#[derive(Clone, Debug)]
+pub struct ChartTable {
+    /// The top left of the table in the world
+    pub position: Vec2,
+    /// Offsets of the edges of the columns from the left of the table
+    pub column_edges: Vec<f32>,
+    /// Offsets of the edges of the rows from the top of the table
+    pub row_edges: Vec<f32>,
+    /// The cells by row, then by column
+    pub cells: Vec<Vec<ChartCell>>,
+    pub numbering: Option<ChartNumbering>,
+    /// The group the table is kept in, the tables of a group follow each other
+    pub group: Option<usize>,
+}
+impl ChartTable {
+    fn size(&self) -> Vec2 {
+        Vec2::new(
+            self.column_edges.last().copied().unwrap_or(0.0),
+            self.row_edges.last().copied().unwrap_or(0.0),
+        )
+    }
+}
+
+/// Row and column numbers drawn beside a table
+#[derive(Clone, Debug)]
+pub struct ChartNumbering {
+    /// The labeled rows with their numbers
+    pub rows: Vec<(usize, i32)>,
+    /// The labeled columns with their numbers
+    pub columns: Vec<(usize, i32)>,
+    /// Rows are numbered on the right instead of the left side
+    pub right: bool,
+    /// Columns are numbered below instead of above the table
+    pub bottom: bool,
+}
+
+/// A palette color used by the tables of a chart
+#[derive(Clone, Debug)]
+pub struct LegendEntry {
+    pub symbol: char,
+    pub color: Color,
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// What charts are drawn of, in chart units with the origin at the top left and y pointing down
+#[derive(Clone, Debug)]
+enum ChartItem {
+    Fill(Rect, Srgba),
+    Outline(Rect, Srgba),
+    /// Text in a single line, centered vertically in its rect
+    Text {
+        rect: Rect,
+        text: String,
+        size: f32,
+        color: Srgba,
+        align: Align,
+    },
+    /// The items up to the next [`ChartItem::EndGroup`] belong to a group of tables
+    BeginGroup,
+    EndGroup,
+}
+
+/// A printable chart of tables with the symbols of their cells and a legend below them,
+/// which can be written as SVG, PDF or PNG
+#[derive(Clone, Debug)]
+pub struct Chart {
+    size: Vec2,
+    items: Vec<ChartItem>,
+}
+impl Chart {
+    pub fn new(tables: &[ChartTable], legend: &[LegendEntry], font_size: f32) -> Self {
+        let number_size = Vec2::new(font_size * 3.0, font_size * 1.5);
+        let bounds = tables
+            .iter()
+            .map(|table| {
+                let size = table.size();
+                let mut rect = Rect::from_corners(
+                    table.position,
+                    table.position + size * Vec2::new(1.0, -1.0),
+                );
+                if let Some(numbering) = &table.numbering {
+                    if numbering.right {
+                        rect.max.x += number_size.x;
+                    } else {
+                        rect.min.x -= number_size.x;
+                    }
+                    if numbering.bottom {
+                        rect.min.y -= number_size.y;
+                    } else {
+                        rect.max.y += number_size.y;
+                    }
+                }
+                rect
+            })
+            .reduce(|a, b| a.union(b))
+            .unwrap_or_default();
+        // World positions have y pointing up, chart positions down
+        let to_chart = |world: Vec2| {
+            Vec2::new(world.x - bounds.min.x, bounds.max.y - world.y) + Vec2::splat(MARGIN)
+        };
+
+        let mut items = vec![];
+        let mut group = None;
+        for table in tables {
+            if table.group != group {
+                if group.is_some() {
+                    items.push(ChartItem::EndGroup);
+                }
+                if table.group.is_some() {
+                    items.push(ChartItem::BeginGroup);
+                }
+                group = table.group;
+            }
+            let origin = to_chart(table.position);
+            for (row, cells) in table.cells.iter().enumerate() {
+                for (column, cell) in cells.iter().enumerate() {
+                    let (Some(left), Some(right), Some(top), Some(bottom)) = (
+                        table.column_edges.get(column),
+                        table.column_edges.get(column + 1),
+                        table.row_edges.get(row),
+                        table.row_edges.get(row + 1),
+                    ) else {
+                        continue;
+                    };
+                    let rect = Rect::new(
+                        origin.x + left,
+                        origin.y + top,
+                        origin.x + right,
+                        origin.y + bottom,
+                    );
+                    items.extend(cell_items(rect, cell, font_size));
+                }
+            }
+            if let Some(numbering) = &table.numbering {
+                items.extend(number_items(
+                    table,
+                    numbering,
+                    origin,
+                    number_size,
+                    font_size,
+                ));
+            }
+        }
+        if group.is_some() {
+            items.push(ChartItem::EndGroup);
+        }
+
+        let mut size = bounds.size() + Vec2::splat(MARGIN * 2.0);
+        let row_height = font_size * 1.6;
+        let legend_top = size.y;
+        for (i, entry) in legend.iter().enumerate() {
+            let top = legend_top + i as f32 * row_height;
+            let swatch = Rect::new(MARGIN, top, MARGIN + row_height, top + row_height);
+            items.extend(cell_items(
+                swatch,
+                &ChartCell {
+                    fill: entry.color,
+                    text: String::new(),
+                    symbol: Some(entry.symbol),
+                },
+                font_size,
+            ));
+            items.push(ChartItem::Text {
+                rect: Rect::new(
+                    swatch.max.x + font_size * 0.5,
+                    top,
+                    swatch.max.x + font_size * 20.0,
+                    top + row_height,
+                ),
+                text: format!("{} ({})", entry.name, entry.count),
+                size: font_size,
+                color: Srgba::BLACK,
+                align: Align::Left,
+            });
+            size.x = size.x.max(swatch.max.x + font_size * 20.0 + MARGIN);
+        }
+        if !legend.is_empty() {
+            size.y += legend.len() as f32 * row_height + MARGIN;
+        }
+
+        Self { size, items }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let escape = |text: &str| {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        };
+        let color = |color: &Srgba| {
+            let [r, g, b, a] = color.to_u8_array();
+            format!(
+                "fill=\"#{r:02x}{g:02x}{b:02x}\" fill-opacity=\"{}\"",
+                a as f32 / 255.0
+            )
+        };
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\" font-family=\"monospace\">\n  \
+             <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n",
+            w = self.size.x,
+            h = self.size.y
+        );
+        for item in &self.items {
+            svg += &match item {
+                ChartItem::Fill(rect, fill) => format!(
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>\n",
+                    rect.min.x,
+                    rect.min.y,
+                    rect.width(),
+                    rect.height(),
+                    color(fill)
+                ),
+                ChartItem::Outline(rect, stroke) => {
+                    let [r, g, b, _] = stroke.to_u8_array();
+                    format!(
+                        "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                         stroke=\"#{r:02x}{g:02x}{b:02x}\" stroke-width=\"0.5\"/>\n",
+                        rect.min.x,
+                        rect.min.y,
+                        rect.width(),
+                        rect.height(),
+                    )
+                }
+                ChartItem::Text {
+                    rect,
+                    text,
+                    size,
+                    color: fill,
+                    align,
+                } => {
+                    let (x, anchor) = match align {
+                        Align::Left => (rect.min.x, "start"),
+                        Align::Center => (rect.center().x, "middle"),
+                        Align::Right => (rect.max.x, "end"),
+                    };
+                    format!(
+                        "  <text x=\"{x}\" y=\"{}\" font-size=\"{size}\" text-anchor=\"{anchor}\" \
+                         dominant-baseline=\"central\" {}>{}</text>\n",
+                        rect.center().y,
+                        color(fill),
+                        escape(text)
+                    )
+                }
+                ChartItem::BeginGroup => "  <g class=\"group\">\n".to_string(),
+                ChartItem::EndGroup => "  </g>\n".to_string(),
+            };
+        }
+        svg + "</svg>\n"
+    }
+
+    /// A single page PDF with the chart at its size in points, written with the Courier font
+    /// every PDF reader has, so no font has to be embedded
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let height = self.size.y;
+        let rgb = |color: &Srgba| format!("{:.3} {:.3} {:.3}", color.red, color.green, color.blue);
+        let mut content = String::new();
+        for item in &self.items {
+            content += &match item {
+                ChartItem::Fill(rect, fill) if fill.alpha > 0.0 => format!(
+                    "{} rg {} {} {} {} re f\n",
+                    rgb(fill),
+                    rect.min.x,
+                    height - rect.max.y,
+                    rect.width(),
+                    rect.height()
+                ),
+                ChartItem::Fill(..) => continue,
+                ChartItem::BeginGroup => "q\n".to_string(),
+                ChartItem::EndGroup => "Q\n".to_string(),
+                ChartItem::Outline(rect, stroke) => format!(
+                    "{} RG 0.5 w {} {} {} {} re S\n",
+                    rgb(stroke),
+                    rect.min.x,
+                    height - rect.max.y,
+                    rect.width(),
+                    rect.height()
+                ),
+                ChartItem::Text {
+                    rect,
+                    text,
+                    size,
+                    color,
+                    align,
+                } => {
+                    let width = text.chars().count() as f32 * size * COURIER_WIDTH;
+                    let x = match align {
+                        Align::Left => rect.min.x,
+                        Align::Center => rect.center().x - width / 2.0,
+                        Align::Right => rect.max.x - width,
+                    };
+                    // Courier capitals are about 0.6 of the font size high
+                    let baseline = height - rect.center().y - size * 0.3;
+                    format!(
+                        "BT /F1 {size} Tf {} rg {x} {baseline} Td ({}) Tj ET\n",
+                        rgb(color),
+                        pdf_string(text)
+                    )
+                }
+            };
+        }
+
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {height}] \
+                 /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>",
+                self.size.x
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+            format!(
+                "<< /Length {} >>\nstream\n{content}endstream",
+                content.len()
+            ),
+        ];
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf += &format!("{} 0 obj\n{object}\nendobj\n", i + 1);
+        }
+        let xref = pdf.len();
+        pdf += &format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            pdf += &format!("{offset:010} 00000 n \n");
+        }
+        pdf += &format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        );
+        pdf.into_bytes()
+    }
+
+    /// The chart as an image with [`PNG_SCALE`] pixels per unit, text is drawn with `font`
+    pub fn to_png(&self, font: &FontArc) -> Result<Vec<u8>, String> {
+        let size = (self.size * PNG_SCALE).ceil().as_uvec2();
+        let mut image = image::RgbaImage::from_pixel(size.x, size.y, image::Rgba([255; 4]));
+        let scaled = |rect: &Rect| Rect::from_corners(rect.min * PNG_SCALE, rect.max * PNG_SCALE);
+
+        for item in &self.items {
+            match item {
+                ChartItem::Fill(rect, fill) => {
+                    let rect = scaled(rect);
+                    fill_rect(&mut image, rect.min.round(), rect.max.round(), fill);
+                }
+                ChartItem::Outline(rect, stroke) => {
+                    let rect = scaled(rect);
+                    let (min, max) = (rect.min.round(), rect.max.round());
+                    for (from, to) in [
+                        (min, Vec2::new(max.x, min.y + 1.0)),
+                        (Vec2::new(min.x, max.y - 1.0), max),
+                        (min, Vec2::new(min.x + 1.0, max.y)),
+                        (Vec2::new(max.x - 1.0, min.y), max),
+                    ] {
+                        fill_rect(&mut image, from, to, stroke);
+                    }
+                }
+                ChartItem::BeginGroup | ChartItem::EndGroup => {}
+                ChartItem::Text {
+                    rect,
+                    text,
+                    size: font_size,
+                    color,
+                    align,
+                } => {
+                    let rect = scaled(rect);
+                    let font = font.as_scaled(PxScale::from(font_size * PNG_SCALE));
+                    let glyphs: Vec<_> = text.chars().map(|c| font.glyph_id(c)).collect();
+                    let width: f32 = glyphs.iter().map(|id| font.h_advance(*id)).sum();
+                    let mut x = match align {
+                        Align::Left => rect.min.x,
+                        Align::Center => rect.center().x - width / 2.0,
+                        Align::Right => rect.max.x - width,
+                    };
+                    let baseline = rect.center().y + (font.ascent() + font.descent()) / 2.0;
+                    for id in glyphs {
+                        let glyph = id.with_scale_and_position(font.scale(), point(x, baseline));
+                        x += font.h_advance(id);
+                        let Some(outlined) = font.outline_glyph(glyph) else {
+                            continue;
+                        };
+                        let bounds = outlined.px_bounds();
+                        outlined.draw(|gx, gy, coverage| {
+                            let px = bounds.min.x as i64 + gx as i64;
+                            let py = bounds.min.y as i64 + gy as i64;
+                            if let (Ok(px), Ok(py)) = (u32::try_from(px), u32::try_from(py)) {
+                                if px < size.x && py < size.y {
+                                    blend(image.get_pixel_mut(px, py), color, coverage);
+                                }
+                            }
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut png = Cursor::new(vec![]);
+        image
+            .write_to(&mut png, image::ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        Ok(png.into_inner())
+    }
+}
+
+/// The background of a cell, its symbol or otherwise its text, and its outline
+fn cell_items(rect: Rect, cell: &ChartCell, font_size: f32) -> Vec<ChartItem> {
+    let fill = cell.fill.to_srgba();
+    // Text on dark cells is white, on light or transparent cells black
+    let ink = if fill.alpha < 0.5 || fill.luminance() > 0.5 {
+        Srgba::BLACK
+    } else {
+        Srgba::WHITE
+    };
+    let mut items = vec![ChartItem::Fill(rect, fill)];
+    let text = match cell.symbol {
+        Some(symbol) => symbol.to_string(),
+        None => cell.text.clone(),
+    };
+    if !text.is_empty() {
+        items.push(ChartItem::Text {
+            rect,
+            text,
+            size: font_size,
+            color: ink,
+            align: Align::Center,
+        });
+    }
+    items.push(ChartItem::Outline(rect, GRID_COLOR));
+    items
+}
+
+/// The numbers of the rows and columns of a table whose top left is at `origin` in the chart,
+/// each in a rect of `number_size` beside its row or column
+fn number_items(
+    table: &ChartTable,
+    numbering: &ChartNumbering,
+    origin: Vec2,
+    number_size: Vec2,
+    font_size: f32,
+) -> Vec<ChartItem> {
+    let size = table.size();
+    let gap = font_size * 0.25;
+    let number = |rect: Rect, value: i32, align: Align| ChartItem::Text {
+        rect,
+        text: value.to_string(),
+        size: font_size,
+        color: Srgba::BLACK,
+        align,
+    };
+    let mut items = vec![];
+    for &(row, value) in &numbering.rows {
+        let (Some(top), Some(bottom)) = (table.row_edges.get(row), table.row_edges.get(row + 1))
+        else {
+            continue;
+        };
+        let (min_x, max_x, align) = if numbering.right {
+            let left = origin.x + size.x + gap;
+            (left, left + number_size.x, Align::Left)
+        } else {
+            let right = origin.x - gap;
+            (right - number_size.x, right, Align::Right)
+        };
+        let rect = Rect::new(min_x, origin.y + top, max_x, origin.y + bottom);
+        items.push(number(rect, value, align));
+    }
+    for &(column, value) in &numbering.columns {
+        let (Some(left), Some(right)) = (
+            table.column_edges.get(column),
+            table.column_edges.get(column + 1),
+        ) else {
+            continue;
+        };
+        let min_y = if numbering.bottom {
+            origin.y + size.y
+        } else {
+            origin.y - number_size.y
+        };
+        let rect = Rect::new(
+            origin.x + left,
+            min_y,
+            origin.x + right,
+            min_y + number_size.y,
+        );
+        items.push(number(rect, value, Align::Center));
+    }
+    items
+}
+
+/// Text as a PDF string, characters outside of Latin-1 become `?`
+fn pdf_string(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{c}"),
+            ' '..='~' => c.to_string(),
+            '\u{a0}'..='\u{ff}' => format!("\\{:03o}", c as u32),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+fn fill_rect(image: &mut image::RgbaImage, min: Vec2, max: Vec2, color: &Srgba) {
+    let size = UVec2::new(image.width(), image.height()).as_vec2();
+    let min = min.clamp(Vec2::ZERO, size).as_uvec2();
+    let max = max.clamp(Vec2::ZERO, size).as_uvec2();
+    for y in min.y..max.y {
+        for x in min.x..max.x {
+            blend(image.get_pixel_mut(x, y), color, 1.0);
+        }
+    }
+}
+
+/// Draws `color` over `pixel`, `coverage` is how much of the pixel it covers
+fn blend(pixel: &mut image::Rgba<u8>, color: &Srgba, coverage: f32) {
+    let alpha = color.alpha * coverage.clamp(0.0, 1.0);
+    let [r, g, b, _] = color.to_u8_array();
+    for (channel, new) in pixel.0.iter_mut().zip([r, g, b]) {
+        *channel = (*channel as f32 * (1.0 - alpha) + new as f32 * alpha).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(
+        position: Vec2,
+        numbering: Option<ChartNumbering>,
+        group: Option<usize>,
+    ) -> ChartTable {
+        let cell = |fill: Color, text: &str, symbol| ChartCell {
+            fill,
+            text: text.to_string(),
+            symbol,
+        };
+        ChartTable {
+            position,
+            column_edges: vec![0.0, 20.0, 40.0],
+            row_edges: vec![0.0, 20.0],
+            cells: vec![vec![
+                cell(Color::WHITE, "a(b)", None),
+                cell(Color::BLACK, "", Some('X')),
+            ]],
+            numbering,
+            group,
+        }
+    }
+
+    fn chart(numbering: Option<ChartNumbering>) -> Chart {
+        let legend = [LegendEntry {
+            symbol: 'X',
+            color: Color::BLACK,
+            name: "Black".to_string(),
+            count: 1,
+        }];
+        let table = table(Vec2::new(100.0, 50.0), numbering, None);
+        Chart::new(&[table], &legend, 10.0)
+    }
+
+    #[test]
+    fn svg_has_cells_symbols_and_legend() {
+        let svg = chart(None).to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<rect x=\"10\" y=\"10\" width=\"20\" height=\"20\" fill=\"#ffffff\""));
+        assert!(svg.contains(">a(b)</text>"));
+        assert_eq!(svg.matches(">X</text>").count(), 2);
+        assert!(svg.contains(">Black (1)</text>"));
+    }
+
+    #[test]
+    fn numbers_beside_the_table() {
+        let svg = chart(Some(ChartNumbering {
+            rows: vec![(0, 1)],
+            columns: vec![(0, 1), (1, 2)],
+            right: false,
+            bottom: false,
+        }))
+        .to_svg();
+        // The table moves right and down to make room for the numbers on its left and top
+        assert!(svg.contains("<rect x=\"40\" y=\"25\" width=\"20\" height=\"20\""));
+        assert!(svg.contains("<text x=\"37.5\" y=\"35\" font-size=\"10\" text-anchor=\"end\""));
+        assert!(svg.contains("<text x=\"70\" y=\"17.5\" font-size=\"10\" text-anchor=\"middle\""));
+        assert_eq!(svg.matches(">1</text>").count(), 2);
+    }
+
+    #[test]
+    fn groups_are_kept_together() {
+        let tables = [
+            table(Vec2::ZERO, None, Some(0)),
+            table(Vec2::new(0.0, -30.0), None, Some(0)),
+            table(Vec2::new(0.0, -60.0), None, None),
+        ];
+        let svg = Chart::new(&tables, &[], 10.0).to_svg();
+        let (group, after) = svg.split_once("  <g class=\"group\">\n").unwrap();
+        assert!(!group.contains("<text"));
+        let (group, after) = after.split_once("  </g>\n").unwrap();
+        assert_eq!(group.matches(">a(b)</text>").count(), 2);
+        assert_eq!(after.matches(">a(b)</text>").count(), 1);
+        assert!(!after.contains("<g"));
+    }
+
+    #[test]
+    fn pdf_cross_references_its_objects() {
+        let pdf = String::from_utf8(chart(None).to_pdf()).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert!(pdf.contains("(a\\(b\\)) Tj"));
+        let startxref: usize = pdf
+            .lines()
+            .skip_while(|line| *line != "startxref")
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(pdf[startxref..].starts_with("xref\n0 6\n"));
+        for (i, line) in pdf[startxref..].lines().skip(3).take(5).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::HashMap;
+
+use crate::files::FileOpened;
+use crate::group::{group_tables, Groups};
+use crate::layers::Locked;
+use crate::player::{User, UserConfig};
+use crate::selection::{Selected, SelectedCells, Selection};
+use crate::status::Status;
+use crate::table::{
+    resize_table, set_cell_text, spawn_table, table_at, Cell, CellContents, CellQueries, TableHead,
+};
+use crate::{ctrl_just_pressed, typing, AppState, MousePosQueries};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+pub struct ClipboardPlugin;
+
+/// This plugin is responsible for copying, cutting and pasting tables and ranges of cells.
+/// Copied cells are also put on the system clipboard as tab separated values, so they can be
+/// exchanged with spreadsheets. Opened CSV and TSV files become new tables, with their first line
+/// as a header row naming the columns
+impl Plugin for ClipboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Clipboard>()
+            .insert_non_send_resource(SystemClipboard::new())
+            .add_event::<PasteText>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        copy.run_if(
+                            ctrl_just_pressed(KeyCode::KeyC)
+                                .or_else(ctrl_just_pressed(KeyCode::KeyX)),
+                        ),
+                        cut.run_if(ctrl_just_pressed(KeyCode::KeyX)),
+                        request_paste.run_if(ctrl_just_pressed(KeyCode::KeyV)),
+                    )
+                        .run_if(not(typing)),
+                    receive_paste,
+                    paste.run_if(on_event::<PasteText>()),
+                    import_table.run_if(on_event::<FileOpened>()),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// A copied cell, cells copied from outside of the app only have text
+#[derive(Clone, Debug, Default)]
+struct CopiedCell {
+    text: String,
+    bg_color: Option<Handle<ColorMaterial>>,
+}
+
+/// Copied cells by row, then by column
+type CopiedCells = Vec<Vec<CopiedCell>>;
+
+#[derive(Clone, Debug)]
+struct CopiedTable {
+    /// Position of the table relative to the first copied table
+    offset: Vec2,
+    header_rows: u32,
+    header_columns: u32,
+    cells: CopiedCells,
+    /// Tables copied out of the same group are grouped again when they are pasted
+    group: Option<Entity>,
+}
+
+#[derive(Clone, Debug)]
+enum Copied {
+    /// Whole tables, `origin` is where the first of them was copied from
+    Tables {
+        origin: Vec2,
+        tables: Vec<CopiedTable>,
+    },
+    Cells(CopiedCells),
+}
+
+/// What was last copied inside of the app
+#[derive(Resource, Default)]
+pub struct Clipboard {
+    copied: Option<Copied>,
+    /// The text put on the system clipboard for `copied`, used to recognize it when pasting
+    tsv: String,
+}
+
+/// Text read from the system clipboard to be pasted
+#[derive(Event)]
+struct PasteText(String);
+
+/// Access to the clipboard of the operating system.
+/// It is kept around since on Linux the copied text is only available while it is alive
+struct SystemClipboard {
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    clipboard: Option<arboard::Clipboard>,
+    /// Reading the clipboard is asynchronous on the web, the text arrives here
+    #[cfg(target_arch = "wasm32")]
+    received: std::rc::Rc<std::cell::RefCell<Option<String>>>,
+}
+impl SystemClipboard {
+    fn new() -> Self {
+        Self {
+            #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+            clipboard: arboard::Clipboard::new()
+                .map_err(|e| warn!("The system clipboard is not available: {e}"))
+                .ok(),
+            #[cfg(target_arch = "wasm32")]
+            received: default(),
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+        if let Some(Err(e)) = self.clipboard.as_mut().map(|c| c.set_text(text)) {
+            warn!("Could not copy to the system clipboard: {e}");
+        }
+        #[cfg(target_arch = "wasm32")]
+        if let Some(window) = web_sys::window() {
+            let _ = window.navigator().clipboard().write_text(text);
+        }
+        #[cfg(not(any(
+            target_os = "windows",
+            target_os = "macos",
+            target_os = "linux",
+            target_arch = "wasm32"
+        )))]
+        let _ = text;
+    }
+
+    /// Reads the text on the system clipboard.
+    /// On the web this returns `None` and the text is received later by [`Self::take_received`]
+    fn request_text(&mut self) -> Option<String> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let Some(window) = web_sys::window() else {
+                return Some(String::new());
+            };
+            let promise = window.navigator().clipboard().read_text();
+            let received = self.received.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let text = wasm_bindgen_futures::JsFuture::from(promise)
+                    .await
+                    .ok()
+                    .and_then(|text| text.as_string());
+                *received.borrow_mut() = Some(text.unwrap_or_default());
+            });
+            None
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+            if let Some(Ok(text)) = self.clipboard.as_mut().map(|c| c.get_text()) {
+                return Some(text);
+            }
+            Some(String::new())
+        }
+    }
+
+    fn take_received(&mut self) -> Option<String> {
+        #[cfg(target_arch = "wasm32")]
+        return self.received.borrow_mut().take();
+        #[cfg(not(target_arch = "wasm32"))]
+        None
+    }
+}
+
+/// Cells as tab separated text like spreadsheets copy it
+fn to_tsv(cells: &CopiedCells) -> String {
+    to_separated(cells, '\t')
+}
+
+fn from_tsv(tsv: &str) -> CopiedCells {
+    from_separated(tsv, '\t')
+}
+
+/// Cells as text with `separator` between the cells of a row, like CSV or TSV. Text with the
+/// separator, line breaks or quotes is put in quotes, with its quotes doubled
+fn to_separated(cells: &CopiedCells, separator: char) -> String {
+    cells
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| {
+                    if cell.text.contains([separator, '\n', '\r', '"']) {
+                        format!("\"{}\"", cell.text.replace('"', "\"\""))
+                    } else {
+                        cell.text.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(&separator.to_string())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn from_separated(separated: &str, separator: char) -> CopiedCells {
+    let cell = |text: &mut String| CopiedCell {
+        text: std::mem::take(text),
+        bg_color: None,
+    };
+    let mut cells = vec![];
+    let mut row = vec![];
+    let mut text = String::new();
+    let mut quoted = false;
+    let mut chars = separated.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if_eq(&'"').is_some() {
+                    text.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if text.is_empty() => quoted = true,
+            _ if quoted => text.push(c),
+            _ if c == separator => row.push(cell(&mut text)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                row.push(cell(&mut text));
+                cells.push(std::mem::take(&mut row));
+            }
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() || !row.is_empty() {
+        row.push(cell(&mut text));
+        cells.push(row);
+    }
+    cells
+}
+
+/// Number of columns and rows of copied cells
+fn copied_size(cells: &CopiedCells) -> UVec2 {
+    UVec2::new(
+        cells.iter().map(Vec::len).max().unwrap_or(0) as u32,
+        cells.len() as u32,
+    )
+}
+
+/// The copied cell that lands on `cell` when pasting `cells` with their top left at `start`
+fn copied_at(cells: &CopiedCells, start: Cell, cell: Cell) -> Option<&CopiedCell> {
+    let row = cell.row.checked_sub(start.row)?;
+    let column = cell.column.checked_sub(start.column)?;
+    cells.get(row as usize)?.get(column as usize)
+}
+
+fn copy_cells(cell_q: &CellQueries, children: &[Entity], start: Cell, size: UVec2) -> CopiedCells {
+    let mut copied = vec![vec![CopiedCell::default(); size.x as usize]; size.y as usize];
+    for (cell, CellContents { text, bg_color }) in cell_q.read(children) {
+        let (Some(row), Some(column)) = (
+            cell.row.checked_sub(start.row),
+            cell.column.checked_sub(start.column),
+        ) else {
+            continue;
+        };
+        if let Some(copied) = copied
+            .get_mut(row as usize)
+            .and_then(|copied_row| copied_row.get_mut(column as usize))
+        {
+            *copied = CopiedCell {
+                text,
+                bg_color: Some(bg_color),
+            };
+        }
+    }
+    copied
+}
+
+/// Copies the selected range of cells, or all selected tables if no cells are selected
+fn copy(
+    mut clipboard: ResMut<Clipboard>,
+    mut system_clipboard: NonSendMut<SystemClipboard>,
+    selected_cells: Option<Res<SelectedCells>>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform, &Children), With<Selected>>,
+    cell_q: CellQueries,
+    groups: Groups,
+) {
+    let range = selected_cells.filter(|range| selected_q.contains(range.table));
+    let (copied, tsv) = if let Some(range) = range {
+        let Ok((.., children)) = selected_q.get(range.table) else {
+            return;
+        };
+        let cells = copy_cells(&cell_q, children, range.min(), range.size());
+        let tsv = to_tsv(&cells);
+        (Copied::Cells(cells), tsv)
+    } else {
+        let mut tables = selected_q
+            .iter()
+            .map(|(id, table_head, transform, children)| {
+                let size = UVec2::new(table_head.num_columns(), table_head.num_rows());
+                (
+                    transform.translation().truncate(),
+                    CopiedTable {
+                        offset: Vec2::ZERO,
+                        header_rows: table_head.header_rows,
+                        header_columns: table_head.header_columns,
+                        cells: copy_cells(&cell_q, children, Cell { row: 0, column: 0 }, size),
+                        group: groups.group_of(id),
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        let Some(&(origin, _)) = tables.first() else {
+            return;
+        };
+        for (pos, table) in &mut tables {
+            table.offset = *pos - origin;
+        }
+        let tsv = tables
+            .iter()
+            .map(|(_, table)| to_tsv(&table.cells))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let tables = tables.into_iter().map(|(_, table)| table).collect();
+        (Copied::Tables { origin, tables }, tsv)
+    };
+
+    system_clipboard.set_text(&tsv);
+    clipboard.copied = Some(copied);
+    clipboard.tsv = tsv;
+}
+
+/// Clears the selected range of cells, or deletes all selected tables if no cells are selected
+/// Clears the selected range of cells, or removes the selected tables, after they were copied.
+/// Locked tables are only copied
+fn cut(
+    mut cmd: Commands,
+    selected_cells: Option<Res<SelectedCells>>,
+    selected_q: Query<(Entity, &TableHead, &Children, Has<Locked>), With<Selected>>,
+    mut cell_q: CellQueries,
+    user_q: Query<&User>,
+    mut status: EventWriter<Status>,
+) {
+    let config = &user_q.single().current_config;
+
+    if let Some(range) = selected_cells.filter(|range| selected_q.contains(range.table)) {
+        let Ok((_, table_head, children, locked)) = selected_q.get(range.table) else {
+            return;
+        };
+        if locked {
+            status.send(Status::warning(
+                "The cells were copied but not cut, their table is locked",
+            ));
+            return;
+        }
+        cell_q.edit(children, |cell, mut bg_color, mut text| {
+            if range.contains(cell) {
+                *bg_color = CellContents::empty(table_head, &cell, config).bg_color;
+                set_cell_text(&mut text, String::new());
+            }
+        });
+        return;
+    }
+
+    cmd.remove_resource::<SelectedCells>();
+    let mut locked_tables = 0;
+    for (id, .., locked) in &selected_q {
+        if locked {
+            locked_tables += 1;
+        } else {
+            cmd.entity(id).despawn_recursive();
+        }
+    }
+    if locked_tables > 0 {
+        status.send(Status::warning(format!(
+            "{locked_tables} locked tables were copied but not cut"
+        )));
+    }
+}
+
+fn request_paste(
+    mut system_clipboard: NonSendMut<SystemClipboard>,
+    mut paste_events: EventWriter<PasteText>,
+) {
+    if let Some(text) = system_clipboard.request_text() {
+        paste_events.send(PasteText(text));
+    }
+}
+
+fn receive_paste(
+    mut system_clipboard: NonSendMut<SystemClipboard>,
+    mut paste_events: EventWriter<PasteText>,
+) {
+    if let Some(text) = system_clipboard.take_received() {
+        paste_events.send(PasteText(text));
+    }
+}
+
+/// Spawns a selected table at `pos` filled with `cells`
+fn spawn_copied_table(
+    cmd: &mut Commands,
+    pos: Vec2,
+    table_head: &TableHead,
+    cells: &CopiedCells,
+    config: &UserConfig,
+) -> Entity {
+    let start = Cell { row: 0, column: 0 };
+    let id = spawn_table(
+        cmd,
+        pos.extend(0.0),
+        table_head,
+        copied_size(cells),
+        config,
+        |table_head, cell| pasted_contents(table_head, cell, copied_at(cells, start, cell), config),
+    );
+    cmd.entity(id).insert(Selected);
+    id
+}
+
+fn pasted_contents(
+    table_head: &TableHead,
+    cell: Cell,
+    copied: Option<&CopiedCell>,
+    config: &UserConfig,
+) -> CellContents {
+    let mut contents = CellContents::empty(table_head, &cell, config);
+    if let Some(copied) = copied {
+        contents.text.clone_from(&copied.text);
+        if let Some(bg_color) = &copied.bg_color {
+            contents.bg_color = bg_color.clone();
+        }
+    }
+    contents
+}
+
+/// The contents of the last paste event
+#[derive(SystemParam)]
+struct PastedContents<'w, 's> {
+    paste_events: EventReader<'w, 's, PasteText>,
+    clipboard: Res<'w, Clipboard>,
+}
+impl PastedContents<'_, '_> {
+    fn read(&mut self) -> Option<Copied> {
+        let PasteText(text) = self.paste_events.read().last()?;
+        // Text that we did not copy ourselves comes from another app
+        if text.is_empty() || *text == self.clipboard.tsv {
+            self.clipboard.copied.clone()
+        } else {
+            Some(Copied::Cells(from_tsv(text)))
+        }
+    }
+}
+
+/// The tables that cells can be pasted into, locked tables are only found to tell why nothing
+/// was pasted into them
+#[derive(SystemParam)]
+struct PasteTargets<'w, 's> {
+    table_head_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static mut TableHead,
+            &'static GlobalTransform,
+            &'static Children,
+            Has<Locked>,
+        ),
+    >,
+    cell_q: CellQueries<'w, 's>,
+}
+
+/// Pastes tables at the cursor.
+/// Cells are pasted into the selected range or the table under the cursor, which grows to fit
+/// them, or otherwise into a new table at the cursor
+fn paste(
+    mut cmd: Commands,
+    mut pasted: PastedContents,
+    selection: Selection,
+    targets: PasteTargets,
+    user_q: Query<&User>,
+    mouse_q: MousePosQueries,
+    mut status: EventWriter<Status>,
+) {
+    let PasteTargets {
+        mut table_head_q,
+        mut cell_q,
+    } = targets;
+    let Some(copied) = pasted.read() else {
+        return;
+    };
+    let config = &user_q.single().current_config;
+    let mouse = mouse_q.mouse_pos();
+
+    let cells = match copied {
+        Copied::Tables { origin, tables } => {
+            let anchor = if mouse.is_nan() {
+                origin + config.cell_dimensions * Vec2::new(1.0, -1.0)
+            } else {
+                mouse
+            };
+            selection.clear(&mut cmd);
+            let mut groups: HashMap<Entity, Vec<(Entity, Vec3)>> = HashMap::new();
+            for table in tables {
+                let table_head = TableHead::new(table.header_rows, table.header_columns);
+                let pos = anchor + table.offset;
+                let id = spawn_copied_table(&mut cmd, pos, &table_head, &table.cells, config);
+                if let Some(group) = table.group {
+                    groups.entry(group).or_default().push((id, pos.extend(0.0)));
+                }
+            }
+            for tables in groups.values() {
+                group_tables(&mut cmd, tables);
+            }
+            return;
+        }
+        Copied::Cells(cells) => cells,
+    };
+
+    let target = selection
+        .cells
+        .as_ref()
+        .map(|range| (range.table, range.min()))
+        .or_else(|| {
+            table_at(
+                table_head_q
+                    .iter()
+                    .map(|(id, table_head, transform, ..)| (id, table_head, transform)),
+                mouse,
+            )
+        });
+    let Some((table, start)) = target else {
+        if !mouse.is_nan() {
+            selection.clear(&mut cmd);
+            let table_head = TableHead::new(config.header_rows, config.header_columns);
+            spawn_copied_table(&mut cmd, mouse, &table_head, &cells, config);
+        }
+        return;
+    };
+    let Ok((id, mut table_head, _, children, locked)) = table_head_q.get_mut(table) else {
+        return;
+    };
+    if locked {
+        status.send(Status::warning("Nothing was pasted, the table is locked"));
+        return;
+    }
+
+    cell_q.edit(children, |cell, mut bg_color, mut text| {
+        if let Some(copied) = copied_at(&cells, start, cell) {
+            set_cell_text(&mut text, copied.text.clone());
+            if let Some(copied_bg_color) = &copied.bg_color {
+                *bg_color = copied_bg_color.clone();
+            }
+        }
+    });
+
+    let pasted_size = copied_size(&cells);
+    let size = UVec2::new(start.column, start.row) + pasted_size;
+    let size = size.max(UVec2::new(table_head.num_columns(), table_head.num_rows()));
+    resize_table(
+        &mut cmd,
+        id,
+        &mut table_head,
+        IRect::from_corners(IVec2::ZERO, size.as_ivec2()),
+        config,
+        cell_q.entities(children),
+        |table_head, cell| {
+            pasted_contents(table_head, cell, copied_at(&cells, start, cell), config)
+        },
+    );
+
+    if pasted_size.cmpgt(UVec2::ZERO).all() {
+        cmd.insert_resource(SelectedCells {
+            table: id,
+            from: start,
+            to: Cell {
+                row: start.row + pasted_size.y - 1,
+                column: start.column + pasted_size.x - 1,
+            },
+        });
+    }
+}
+
+/// Opened CSV and TSV files become new tables, the first line is a header row naming the columns
+fn import_table(
+    mut cmd: Commands,
+    mut opened_events: EventReader<FileOpened>,
+    selection: Selection,
+    user_q: Query<&User>,
+    mouse_q: MousePosQueries,
+    mut status: EventWriter<Status>,
+) {
+    let config = &user_q.single().current_config;
+    for file in opened_events.read() {
+        let separator = match file.extension().as_str() {
+            "csv" => ',',
+            "tsv" => '\t',
+            _ => continue,
+        };
+        let Ok(text) = std::str::from_utf8(&file.bytes) else {
+            status.send(Status::warning(format!("{} is not a text file", file.name)));
+            continue;
+        };
+        let cells = from_separated(text.trim_start_matches('\u{feff}'), separator);
+        if cells.is_empty() {
+            status.send(Status::warning(format!("{} is empty", file.name)));
+            continue;
+        }
+
+        selection.clear(&mut cmd);
+        let table_head = TableHead::new(1, 0);
+        let pos = mouse_q.mouse_or_view_center();
+        spawn_copied_table(&mut cmd, pos, &table_head, &cells, config);
+        let size = copied_size(&cells);
+        status.send(Status::info(format!(
+            "Imported {} with {} columns and {} rows below the header",
+            file.name,
+            size.x,
+            size.y - 1
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(texts: &[&[&str]]) -> CopiedCells {
+        texts
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|text| CopiedCell {
+                        text: text.to_string(),
+                        bg_color: None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn texts(cells: &CopiedCells) -> Vec<Vec<&str>> {
+        cells
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn tsv_round_trip() {
+        let copied = cells(&[
+            &["plain", "", "with\ttab"],
+            &["two\nlines", "\"quoted\"", "say \"hi\""],
+            &["\r\n", "\"", "end"],
+        ]);
+        assert_eq!(texts(&from_tsv(&to_tsv(&copied))), texts(&copied));
+    }
+
+    #[test]
+    fn tsv_quotes_only_when_needed() {
+        let copied = cells(&[&["a", "b c"], &["d\te", "f\"g"]]);
+        assert_eq!(to_tsv(&copied), "a\tb c\n\"d\te\"\t\"f\"\"g\"");
+    }
+
+    #[test]
+    fn csv_with_header() {
+        let imported = from_separated("name,count\n\"Smith, J.\",3\nDoe,\n", ',');
+        assert_eq!(
+            texts(&imported),
+            [
+                vec!["name", "count"],
+                vec!["Smith, J.", "3"],
+                vec!["Doe", ""]
+            ]
+        );
+    }
+
+    #[test]
+    fn tsv_from_spreadsheet() {
+        let pasted = from_tsv("a\t\"b\r\nc\"\r\n\td\r\n");
+        assert_eq!(texts(&pasted), [vec!["a", "b\r\nc"], vec!["", "d"]]);
+    }
+}
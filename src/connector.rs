@@ -0,0 +1,333 @@
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+
+use crate::actions::{finish_actions, maintain_actions, Actions, Preview};
+use crate::align::table_bounds;
+use crate::layers::Pickable;
+use crate::player::{ArrowHead, Routing, Tool, User};
+use crate::status::Status;
+use crate::table::{table_at, Cell, TableHead};
+use crate::{
+    alt_pressed, shift_pressed, tool_selected, using_tool, AppState, CanvasSet, WhenActionDoneSet,
+};
+
+pub struct ConnectorPlugin;
+
+/// This plugin is responsible for connectors, arrows from one table to another drawn with
+/// `Tool::Connector`. Their ends stay on the tables when the tables move.
+/// Holding `Alt` attaches an end to the edge of the cell under the cursor instead of the table.
+/// While connecting, `R` cycles the routing, `A` the arrowhead at the end and `Shift+A` the one
+/// at the start
+impl Plugin for ConnectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                make_connector
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Connector))
+                    .after(maintain_actions),
+                cleanup_loose_connectors
+                    .in_set(WhenActionDoneSet)
+                    .before(finish_actions),
+                (
+                    cycle_routing.run_if(input_just_pressed(KeyCode::KeyR)),
+                    cycle_arrow_head.run_if(input_just_pressed(KeyCode::KeyA)),
+                )
+                    .run_if(tool_selected(Tool::Connector))
+                    .run_if(not(alt_pressed))
+                    .run_if(in_state(AppState::Running)),
+                draw_connectors.run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// What an end of a connector is attached to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    Table(Entity),
+    Cell(Entity, Cell),
+    /// Not attached yet, only while the connector is being drawn
+    Point(Vec2),
+}
+impl Anchor {
+    pub fn table(self) -> Option<Entity> {
+        match self {
+            Anchor::Table(table) | Anchor::Cell(table, _) => Some(table),
+            Anchor::Point(_) => None,
+        }
+    }
+
+    /// The anchor after the cells of `table` were renumbered by `shift` columns and rows,
+    /// anchors on cells that are gone are moved to the whole table
+    pub fn shifted(self, table: Entity, shift: IVec2) -> Self {
+        match self {
+            Anchor::Cell(id, cell) if id == table => {
+                let index = IVec2::new(cell.column as i32, cell.row as i32) + shift;
+                if index.cmplt(IVec2::ZERO).any() {
+                    Anchor::Table(table)
+                } else {
+                    Anchor::Cell(
+                        table,
+                        Cell {
+                            row: index.y as u32,
+                            column: index.x as u32,
+                        },
+                    )
+                }
+            }
+            _ => self,
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Connector {
+    pub from: Anchor,
+    pub to: Anchor,
+    pub routing: Routing,
+    /// Arrowheads at the start and the end
+    pub heads: [ArrowHead; 2],
+    pub color: Color,
+}
+
+/// Length of arrowheads
+const HEAD_SIZE: f32 = 10.0;
+
+/// What is under `pos`, the table or with `cell` the cell of it
+fn anchor_at<'a>(
+    tables: impl IntoIterator<Item = (Entity, &'a TableHead, &'a GlobalTransform)>,
+    pos: Vec2,
+    cell: bool,
+) -> Anchor {
+    match table_at(tables, pos) {
+        Some((table, hit)) if cell => Anchor::Cell(table, hit),
+        Some((table, _)) => Anchor::Table(table),
+        None => Anchor::Point(pos),
+    }
+}
+
+fn make_connector(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    keys: Res<ButtonInput<KeyCode>>,
+    user_q: Query<&User>,
+    table_q: Query<(Entity, &TableHead, &GlobalTransform), Pickable>,
+    mut connector_q: Query<&mut Connector, With<Preview>>,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    let cell = alt_pressed(keys);
+    let to = anchor_at(&table_q, actions.to, cell);
+
+    if let Ok(mut connector) = connector_q.get_single_mut() {
+        if connector.to != to {
+            connector.to = to;
+        }
+        return;
+    }
+
+    let from = anchor_at(&table_q, actions.from, cell);
+    if from.table().is_none() {
+        return;
+    }
+    let config = &user_q.single().current_config;
+    cmd.spawn((
+        Connector {
+            from,
+            to,
+            routing: config.routing,
+            heads: config.arrow_heads,
+            color: config.stroke_color,
+        },
+        Preview,
+    ));
+}
+
+/// Connectors have to end on another table, or on another cell of the same one
+fn cleanup_loose_connectors(
+    mut cmd: Commands,
+    connector_q: Query<(Entity, &Connector), With<Preview>>,
+) {
+    for (id, connector) in &connector_q {
+        if connector.to.table().is_none() || connector.to == connector.from {
+            cmd.entity(id).despawn();
+        }
+    }
+}
+
+/// The outline of what `anchor` is attached to, `None` if that is gone
+fn anchor_rect(anchor: Anchor, table_q: &Query<(&TableHead, &GlobalTransform)>) -> Option<Rect> {
+    match anchor {
+        Anchor::Point(pos) => Some(Rect::from_center_size(pos, Vec2::ZERO)),
+        Anchor::Table(table) => table_q
+            .get(table)
+            .ok()
+            .map(|(table_head, transform)| table_bounds(table_head, transform)),
+        Anchor::Cell(table, cell) => {
+            let (table_head, transform) = table_q.get(table).ok()?;
+            // The cell may have been removed by resizing the table
+            if cell.row >= table_head.num_rows() || cell.column >= table_head.num_columns() {
+                return Some(table_bounds(table_head, transform));
+            }
+            Some(Rect::from_center_size(
+                transform.translation().truncate() + table_head.cell_center(cell),
+                table_head.cell_size(cell),
+            ))
+        }
+    }
+}
+
+/// Where a line from the center of `rect` towards `target` leaves it
+fn edge_towards(rect: Rect, target: Vec2) -> Vec2 {
+    let direction = target - rect.center();
+    let half = rect.half_size();
+    let scale = (half.x / direction.x.abs()).min(half.y / direction.y.abs());
+    if scale.is_finite() && scale < 1.0 {
+        rect.center() + direction * scale
+    } else {
+        rect.center()
+    }
+}
+
+/// The points a connector between `from` and `to` passes through
+pub fn route(from: Rect, to: Rect, routing: Routing) -> Vec<Vec2> {
+    if routing == Routing::Straight {
+        return vec![
+            edge_towards(from, to.center()),
+            edge_towards(to, from.center()),
+        ];
+    }
+
+    // Elbows and curves leave and enter through the middle of the sides facing each other
+    let distance = to.center() - from.center();
+    let axis = if distance.x.abs() >= distance.y.abs() {
+        Vec2::X
+    } else {
+        Vec2::Y
+    };
+    let side = axis * distance.signum();
+    let start = from.center() + side * from.half_size();
+    let end = to.center() - side * to.half_size();
+
+    match routing {
+        Routing::Orthogonal => {
+            let middle = (start + end) / 2.0;
+            let across = Vec2::ONE - axis;
+            vec![
+                start,
+                start * across + middle * axis,
+                end * across + middle * axis,
+                end,
+            ]
+        }
+        _ => {
+            let reach = side * (end - start).abs().max_element() / 2.0;
+            let controls = [start, start + reach, end - reach, end];
+            (0..=24)
+                .map(|i| cubic_bezier(controls, i as f32 / 24.0))
+                .collect()
+        }
+    }
+}
+
+fn cubic_bezier([p0, p1, p2, p3]: [Vec2; 4], t: f32) -> Vec2 {
+    let u = 1.0 - t;
+    p0 * u * u * u + p1 * 3.0 * u * u * t + p2 * 3.0 * u * t * t + p3 * t * t * t
+}
+
+/// Draws `head` with its tip at `tip`, pointing in `direction`
+fn draw_head(gizmos: &mut Gizmos, head: ArrowHead, tip: Vec2, direction: Vec2, color: Color) {
+    let direction = direction.normalize_or_zero();
+    let back = tip - direction * HEAD_SIZE;
+    let wing = direction.perp() * HEAD_SIZE * 0.4;
+    match head {
+        ArrowHead::None => {}
+        ArrowHead::Arrow => {
+            gizmos.line_2d(tip, back + wing, color);
+            gizmos.line_2d(tip, back - wing, color);
+        }
+        ArrowHead::Triangle => {
+            gizmos.linestrip_2d([tip, back + wing, back - wing, tip], color);
+        }
+        ArrowHead::Circle => {
+            gizmos.circle_2d(tip - direction * HEAD_SIZE * 0.4, HEAD_SIZE * 0.4, color);
+        }
+    }
+}
+
+/// Connectors are drawn anew every frame, so they follow the tables they are attached to.
+/// Connectors whose tables were deleted are removed
+fn draw_connectors(
+    mut cmd: Commands,
+    mut gizmos: Gizmos,
+    connector_q: Query<(Entity, &Connector)>,
+    table_q: Query<(&TableHead, &GlobalTransform)>,
+) {
+    for (id, connector) in &connector_q {
+        let (Some(from), Some(to)) = (
+            anchor_rect(connector.from, &table_q),
+            anchor_rect(connector.to, &table_q),
+        ) else {
+            cmd.entity(id).despawn();
+            continue;
+        };
+        let points = route(from, to, connector.routing);
+        let color = connector.color;
+        gizmos.linestrip_2d(points.iter().copied(), color);
+
+        let n = points.len();
+        if n < 2 {
+            continue;
+        }
+        draw_head(
+            &mut gizmos,
+            connector.heads[0],
+            points[0],
+            points[0] - points[1],
+            color,
+        );
+        draw_head(
+            &mut gizmos,
+            connector.heads[1],
+            points[n - 1],
+            points[n - 1] - points[n - 2],
+            color,
+        );
+    }
+}
+
+fn cycle_routing(mut user_q: Query<&mut User>, mut status: EventWriter<Status>) {
+    let config = &mut user_q.single_mut().current_config;
+    config.routing = match config.routing {
+        Routing::Straight => Routing::Orthogonal,
+        Routing::Orthogonal => Routing::Curved,
+        Routing::Curved => Routing::Straight,
+    };
+    status.send(Status::info(format!(
+        "Connector routing: {:?}",
+        config.routing
+    )));
+}
+
+fn cycle_arrow_head(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut user_q: Query<&mut User>,
+    mut status: EventWriter<Status>,
+) {
+    let config = &mut user_q.single_mut().current_config;
+    let end = if shift_pressed(keys) { 0 } else { 1 };
+    let head = &mut config.arrow_heads[end];
+    *head = match *head {
+        ArrowHead::None => ArrowHead::Arrow,
+        ArrowHead::Arrow => ArrowHead::Triangle,
+        ArrowHead::Triangle => ArrowHead::Circle,
+        ArrowHead::Circle => ArrowHead::None,
+    };
+    let [start, end] = config.arrow_heads;
+    status.send(Status::info(format!(
+        "Arrowheads: {start:?} at the start, {end:?} at the end"
+    )));
+}
@@ -0,0 +1,430 @@
+use crate::files::{FileOpened, SaveFile};
+use crate::group::{group_tables, Groups};
+use crate::layers::{InLayer, Layer, Layers, Stacked};
+use crate::paint::{cell_color, color_key, PaintMaterials};
+use crate::placed_image::{spawn_image, texture_of, PlacedImage};
+use crate::player::User;
+use crate::status::Status;
+use crate::table::{spawn_table, CellContents, CellQueries, TableHead};
+use crate::{ctrl_just_pressed, typing, AppState};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct DocumentPlugin;
+
+/// This plugin is responsible for saving the document to a file and opening it again.
+/// `Ctrl+S` saves the layers, with their names, visibility, locks and order, the tables on
+/// them with their groups and the placed images, which are embedded in the file. Opening a
+/// `.pattern` file replaces the layers, tables and images with the saved ones
+impl Plugin for DocumentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                save_document
+                    .run_if(ctrl_just_pressed(KeyCode::KeyS))
+                    .run_if(not(typing)),
+                open_document.run_if(on_event::<FileOpened>()),
+            )
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+const DOCUMENT_EXTENSION: &str = "pattern";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedCell {
+    text: String,
+    /// The color the cell is painted with as sRGB bytes, cells that were never painted have none
+    color: Option<[u8; 4]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedTable {
+    layer: u32,
+    /// The top left of the table, its depth keeps the order of the tables on a layer
+    position: [f32; 3],
+    header_rows: u32,
+    header_columns: u32,
+    column_widths: Vec<f32>,
+    row_heights: Vec<f32>,
+    /// The cells by row, then by column
+    cells: Vec<Vec<SavedCell>>,
+    /// Tables with the same group are grouped together
+    #[serde(default)]
+    group: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedImage {
+    name: String,
+    /// The image file encoded as base64
+    data: String,
+    layer: u32,
+    /// The top left of the image
+    position: [f32; 3],
+    /// The shown part of the image in pixels, as the minimum and maximum corners
+    crop: [f32; 4],
+    size: [f32; 2],
+    opacity: f32,
+    keep_aspect: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Document {
+    /// The layers from the bottom to the top
+    layers: Vec<Layer>,
+    active_layer: u32,
+    tables: Vec<SavedTable>,
+    #[serde(default)]
+    images: Vec<SavedImage>,
+}
+impl Document {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(bytes).map_err(|e| e.to_string())
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// The items of the canvas that are saved in a document
+#[derive(SystemParam)]
+struct DocumentContents<'w, 's> {
+    table_head_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static TableHead,
+            &'static GlobalTransform,
+            &'static Children,
+            Option<&'static InLayer>,
+        ),
+    >,
+    image_q: Query<
+        'w,
+        's,
+        (
+            &'static PlacedImage,
+            &'static GlobalTransform,
+            Option<&'static InLayer>,
+        ),
+    >,
+    groups: Groups<'w, 's>,
+    cell_q: CellQueries<'w, 's>,
+}
+
+fn save_document(
+    layers: Res<Layers>,
+    contents: DocumentContents,
+    materials: Res<Assets<ColorMaterial>>,
+    user_q: Query<&User>,
+    mut save_events: EventWriter<SaveFile>,
+) {
+    let DocumentContents {
+        table_head_q,
+        image_q,
+        groups,
+        cell_q,
+    } = contents;
+    let config = &user_q.single().current_config;
+    let mut saved_groups = vec![];
+    let tables = table_head_q
+        .iter()
+        .map(|(id, table_head, transform, children, in_layer)| {
+            let mut cells: Vec<Vec<SavedCell>> = (0..table_head.num_rows())
+                .map(|_| {
+                    (0..table_head.num_columns())
+                        .map(|_| SavedCell {
+                            text: String::new(),
+                            color: None,
+                        })
+                        .collect()
+                })
+                .collect();
+            for (cell, contents) in cell_q.read(children) {
+                let painted = contents.bg_color != config.table_bg_color
+                    && contents.bg_color != config.table_header_bg_color;
+                if let Some(saved) = cells
+                    .get_mut(cell.row as usize)
+                    .and_then(|row| row.get_mut(cell.column as usize))
+                {
+                    *saved = SavedCell {
+                        text: contents.text,
+                        color: painted
+                            .then(|| color_key(cell_color(&contents.bg_color, &materials))),
+                    };
+                }
+            }
+            SavedTable {
+                layer: in_layer.map_or(layers.active, |in_layer| in_layer.0),
+                position: transform.translation().to_array(),
+                header_rows: table_head.header_rows,
+                header_columns: table_head.header_columns,
+                column_widths: table_head.column_widths().to_vec(),
+                row_heights: table_head.row_heights().to_vec(),
+                cells,
+                group: groups.group_of(id).map(|group| {
+                    let saved = saved_groups.iter().position(|g| *g == group);
+                    saved.unwrap_or_else(|| {
+                        saved_groups.push(group);
+                        saved_groups.len() - 1
+                    }) as u32
+                }),
+            }
+        })
+        .collect();
+    let images = image_q
+        .iter()
+        .map(|(image, transform, in_layer)| SavedImage {
+            name: image.name.clone(),
+            data: STANDARD.encode(&image.bytes),
+            layer: in_layer.map_or(layers.active, |in_layer| in_layer.0),
+            position: transform.translation().to_array(),
+            crop: [
+                image.crop.min.x,
+                image.crop.min.y,
+                image.crop.max.x,
+                image.crop.max.y,
+            ],
+            size: image.size.to_array(),
+            opacity: image.opacity,
+            keep_aspect: image.keep_aspect,
+        })
+        .collect();
+    let document = Document {
+        layers: layers.layers.clone(),
+        active_layer: layers.active,
+        tables,
+        images,
+    };
+    save_events.send(SaveFile {
+        name: "document".to_string(),
+        extension: DOCUMENT_EXTENSION,
+        bytes: document.to_json().into_bytes(),
+    });
+}
+
+/// The assets that the items of an opened document use
+#[derive(SystemParam)]
+struct DocumentAssets<'w> {
+    materials: PaintMaterials<'w>,
+    images: ResMut<'w, Assets<Image>>,
+}
+
+/// Replaces the tables, images and layers with those of an opened document. Everything else
+/// on the canvas stays, on the active layer if its own layer is gone
+fn open_document(
+    mut cmd: Commands,
+    mut opened_events: EventReader<FileOpened>,
+    mut layers: ResMut<Layers>,
+    mut stacked_q: Query<
+        (
+            Entity,
+            Option<&mut InLayer>,
+            Has<TableHead>,
+            Has<PlacedImage>,
+        ),
+        Stacked,
+    >,
+    assets: DocumentAssets,
+    user_q: Query<&User>,
+    mut status: EventWriter<Status>,
+) {
+    let DocumentAssets {
+        mut materials,
+        mut images,
+    } = assets;
+    let config = &user_q.single().current_config;
+    for file in opened_events.read() {
+        if file.extension() != DOCUMENT_EXTENSION {
+            continue;
+        }
+        let document = match Document::parse(&file.bytes) {
+            Ok(document) => document,
+            Err(e) => {
+                status.send(Status::warning(format!(
+                    "Could not open {}: {e}",
+                    file.name
+                )));
+                continue;
+            }
+        };
+
+        layers.replace(document.layers, document.active_layer);
+        for (id, in_layer, is_table, is_image) in &mut stacked_q {
+            if is_table || is_image {
+                cmd.entity(id).despawn_recursive();
+            } else if let Some(mut in_layer) = in_layer {
+                if layers.get(in_layer.0).is_none() {
+                    in_layer.0 = layers.active;
+                }
+            }
+        }
+
+        let mut grouped: Vec<(u32, Vec<(Entity, Vec3)>)> = vec![];
+        for table in &document.tables {
+            let template = TableHead::new(table.header_rows, table.header_columns)
+                .with_column_widths(table.column_widths.clone())
+                .with_row_heights(table.row_heights.clone());
+            let size = UVec2::new(
+                table.column_widths.len() as u32,
+                table.row_heights.len() as u32,
+            );
+            let id = spawn_table(
+                &mut cmd,
+                Vec3::from_array(table.position),
+                &template,
+                size,
+                config,
+                |table_head, cell| {
+                    let mut contents = CellContents::empty(table_head, &cell, config);
+                    let saved = table
+                        .cells
+                        .get(cell.row as usize)
+                        .and_then(|row| row.get(cell.column as usize));
+                    if let Some(saved) = saved {
+                        contents.text.clone_from(&saved.text);
+                        if let Some([r, g, b, a]) = saved.color {
+                            contents.bg_color = materials.get_or_add(Color::srgba_u8(r, g, b, a));
+                        }
+                    }
+                    contents
+                },
+            );
+            let layer = if layers.get(table.layer).is_some() {
+                table.layer
+            } else {
+                layers.active
+            };
+            cmd.entity(id).insert(InLayer(layer));
+            if let Some(group) = table.group {
+                let position = Vec3::from_array(table.position);
+                match grouped.iter_mut().find(|(saved, _)| *saved == group) {
+                    Some((_, tables)) => tables.push((id, position)),
+                    None => grouped.push((group, vec![(id, position)])),
+                }
+            }
+        }
+        for (_, tables) in &grouped {
+            group_tables(&mut cmd, tables);
+        }
+
+        for saved in document.images {
+            let source = STANDARD
+                .decode(&saved.data)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    let source = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+                    Ok((bytes, source.to_rgba8()))
+                });
+            let (bytes, source) = match source {
+                Ok(source) => source,
+                Err(e) => {
+                    status.send(Status::warning(format!(
+                        "Could not open the image {}: {e}",
+                        saved.name
+                    )));
+                    continue;
+                }
+            };
+            let [min_x, min_y, max_x, max_y] = saved.crop;
+            let image = PlacedImage {
+                name: saved.name,
+                bytes,
+                pixels: UVec2::from(source.dimensions()).as_vec2(),
+                crop: Rect::new(min_x, min_y, max_x, max_y),
+                size: Vec2::from_array(saved.size),
+                opacity: saved.opacity,
+                keep_aspect: saved.keep_aspect,
+            };
+            let texture = images.add(texture_of(&source));
+            let id = spawn_image(&mut cmd, image, texture, Vec3::from_array(saved.position));
+            let layer = if layers.get(saved.layer).is_some() {
+                saved.layer
+            } else {
+                layers.active
+            };
+            cmd.entity(id).insert(InLayer(layer));
+        }
+        status.send(Status::info(format!(
+            "Opened {} with {} tables on {} layers",
+            file.name,
+            document.tables.len(),
+            layers.layers.len()
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(id: u32, name: &str) -> Layer {
+        Layer {
+            id,
+            name: name.to_string(),
+            visible: id != 3,
+            locked: id == 5,
+        }
+    }
+
+    #[test]
+    fn document_round_trip() {
+        let document = Document {
+            layers: vec![layer(5, "Grid"), layer(3, "Notes")],
+            active_layer: 3,
+            tables: vec![SavedTable {
+                layer: 5,
+                position: [10.0, -20.0, 2.0],
+                header_rows: 1,
+                header_columns: 0,
+                column_widths: vec![40.0, 60.0],
+                row_heights: vec![20.0],
+                cells: vec![vec![
+                    SavedCell {
+                        text: "a".to_string(),
+                        color: None,
+                    },
+                    SavedCell {
+                        text: String::new(),
+                        color: Some([255, 0, 0, 255]),
+                    },
+                ]],
+                group: Some(0),
+            }],
+            images: vec![SavedImage {
+                name: "photo.png".to_string(),
+                data: STANDARD.encode([137, 80, 78, 71]),
+                layer: 3,
+                position: [-5.0, 5.0, 1.0],
+                crop: [0.0, 2.0, 16.0, 8.0],
+                size: [32.0, 12.0],
+                opacity: 0.5,
+                keep_aspect: false,
+            }],
+        };
+        let json = document.to_json();
+        assert_eq!(Document::parse(json.as_bytes()), Ok(document));
+    }
+
+    #[test]
+    fn replaced_layers_keep_their_order_and_new_ids_stay_unique() {
+        let mut layers = Layers::default();
+        layers.replace(vec![layer(5, "Grid"), layer(3, "Notes")], 3);
+        assert_eq!(layers.active, 3);
+        assert_eq!(layers.index(5), Some(0));
+        assert_eq!(layers.add(), 6);
+
+        layers.replace(vec![layer(2, "Grid")], 7);
+        assert_eq!(layers.active, 2);
+        layers.replace(vec![], 0);
+        assert_eq!(layers.layers.len(), 1);
+    }
+}
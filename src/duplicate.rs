@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use crate::align::table_bounds;
+use crate::group::{group_tables, Groups};
+use crate::menu::{text_button, Sidebar};
+use crate::player::{User, UserConfig};
+use crate::selection::{Selected, SelectedCells};
+use crate::status::Status;
+use crate::table::{spawn_table, Cell, CellContents, CellQueries, TableHead};
+use crate::{alt_pressed, ctrl_just_pressed, shift_pressed, typing, AppState, MousePosQueries};
+use bevy::prelude::*;
+
+pub struct DuplicatePlugin;
+
+/// This plugin is responsible for duplicating the selected tables, once with `Ctrl+D`,
+/// in a grid with `Ctrl+Shift+D`, or around the cursor with `Ctrl+Alt+D`.
+/// `Alt+,` and `Alt+.` change the number of columns of the grid, with `Shift` its rows, and
+/// `Alt+-` and `Alt+=` the gap between its tables. `Alt+P` cycles the number of tables around
+/// the cursor, `Shift+Alt+P` pins the center of those arrays where the cursor is, or unpins it.
+/// The settings are also shown and changed in a panel in the sidebar
+impl Plugin for DuplicatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ArraySettings>()
+            .add_systems(
+                Update,
+                (
+                    duplicate
+                        .run_if(not(shift_pressed))
+                        .run_if(not(alt_pressed)),
+                    grid_array.run_if(shift_pressed).run_if(not(alt_pressed)),
+                    radial_array.run_if(alt_pressed),
+                )
+                    .run_if(ctrl_just_pressed(KeyCode::KeyD))
+                    .run_if(not(typing))
+                    .run_if(in_state(AppState::Running)),
+            )
+            .add_systems(
+                Update,
+                (
+                    change_array_settings
+                        .run_if(alt_pressed)
+                        .run_if(not(typing)),
+                    radial_center_guide,
+                    (build_array_panel, array_buttons, update_array_labels).chain(),
+                )
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// How the selected tables are repeated by the array commands
+#[derive(Resource, Debug)]
+pub struct ArraySettings {
+    /// Number of columns and rows of a grid array, including the original
+    pub count: UVec2,
+    /// Horizontal and vertical space between the tables of a grid array
+    pub gap: Vec2,
+    /// Number of tables in a radial array, including the original
+    pub radial_count: u32,
+    /// Where radial arrays are centered, they are centered on the cursor unless it is pinned
+    pub center: Option<Vec2>,
+}
+impl Default for ArraySettings {
+    fn default() -> Self {
+        Self {
+            count: UVec2::new(3, 3),
+            gap: Vec2::splat(20.0),
+            radial_count: 6,
+            center: None,
+        }
+    }
+}
+
+impl ArraySettings {
+    /// Adds `by` columns, or rows along `axis` 1, to grid arrays, keeping at least one
+    fn change_count(&mut self, axis: usize, by: i32) {
+        let count = &mut self.count[axis];
+        *count = count.saturating_add_signed(by).max(1);
+    }
+
+    fn change_gap(&mut self, by: f32) {
+        self.gap = (self.gap + by).max(Vec2::ZERO);
+    }
+
+    /// Steps `by` through [`RADIAL_COUNTS`], wrapping around at either end
+    fn cycle_radial_count(&mut self, by: i32) {
+        let n = RADIAL_COUNTS.len() as i32;
+        let i = RADIAL_COUNTS
+            .iter()
+            .position(|count| *count == self.radial_count)
+            .map_or(if by > 0 { -1 } else { 0 }, |i| i as i32);
+        self.radial_count = RADIAL_COUNTS[(i + by).rem_euclid(n) as usize];
+    }
+
+    fn describe_center(&self) -> String {
+        match self.center {
+            Some(center) => format!("({:.0}, {:.0})", center.x, center.y),
+            None => "the cursor".to_owned(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Grid arrays of {} by {} tables, {} apart, radial arrays of {} around {}",
+            self.count.x,
+            self.count.y,
+            self.gap.x,
+            self.radial_count,
+            self.describe_center()
+        )
+    }
+}
+
+/// How much `Alt+-` and `Alt+=` change the gap of grid arrays
+const GAP_STEP: f32 = 10.0;
+
+/// The numbers of tables in radial arrays that `Alt+P` cycles through
+const RADIAL_COUNTS: [u32; 7] = [2, 3, 4, 5, 6, 8, 12];
+
+fn change_array_settings(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_q: MousePosQueries,
+    mut settings: ResMut<ArraySettings>,
+    mut status: EventWriter<Status>,
+) {
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keys.any_just_pressed([KeyCode::Comma, KeyCode::Period]) {
+        let change = if keys.just_pressed(KeyCode::Period) {
+            1
+        } else {
+            -1
+        };
+        settings.change_count(usize::from(shift), change);
+    } else if keys.just_pressed(KeyCode::Equal) {
+        settings.change_gap(GAP_STEP);
+    } else if keys.just_pressed(KeyCode::Minus) {
+        settings.change_gap(-GAP_STEP);
+    } else if keys.just_pressed(KeyCode::KeyP) && shift {
+        settings.center = match settings.center {
+            Some(_) => None,
+            None => Some(mouse_q.mouse_pos()).filter(|pos| !pos.is_nan()),
+        };
+    } else if keys.just_pressed(KeyCode::KeyP) {
+        settings.cycle_radial_count(1);
+    } else {
+        return;
+    }
+    status.send(Status::info(settings.describe()));
+}
+
+#[derive(Component, Clone, Copy)]
+enum ArrayLabel {
+    Columns,
+    Rows,
+    Gap,
+    RadialCount,
+    Center,
+}
+impl ArrayLabel {
+    fn text(&self, settings: &ArraySettings) -> String {
+        match self {
+            ArrayLabel::Columns => format!("Columns: {}", settings.count.x),
+            ArrayLabel::Rows => format!("Rows: {}", settings.count.y),
+            ArrayLabel::Gap => format!("Gap: {}", settings.gap.x),
+            ArrayLabel::RadialCount => format!("Around: {}", settings.radial_count),
+            ArrayLabel::Center => format!("Center: {}", settings.describe_center()),
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+enum ArrayButton {
+    Columns(i32),
+    Rows(i32),
+    Gap(f32),
+    RadialCount(i32),
+    Unpin,
+}
+
+/// Adds the array settings panel to the sidebar whenever the sidebar is spawned
+fn build_array_panel(
+    mut cmd: Commands,
+    settings: Res<ArraySettings>,
+    sidebar_q: Query<Entity, Added<Sidebar>>,
+) {
+    let Ok(sidebar) = sidebar_q.get_single() else {
+        return;
+    };
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        color: Color::WHITE,
+        ..default()
+    };
+    let row_style = Style {
+        flex_direction: FlexDirection::Row,
+        align_items: AlignItems::Center,
+        column_gap: Val::Px(5.),
+        ..default()
+    };
+    let panel = cmd
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(3.),
+                    padding: UiRect::all(Val::Px(5.)),
+                    ..default()
+                },
+                background_color: Color::linear_rgb(0.1, 0.1, 0.1).into(),
+                ..default()
+            },
+            Interaction::None,
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section("Arrays", text_style.clone()));
+            for (label, less, more) in [
+                (
+                    ArrayLabel::Columns,
+                    ArrayButton::Columns(-1),
+                    ArrayButton::Columns(1),
+                ),
+                (
+                    ArrayLabel::Rows,
+                    ArrayButton::Rows(-1),
+                    ArrayButton::Rows(1),
+                ),
+                (
+                    ArrayLabel::Gap,
+                    ArrayButton::Gap(-GAP_STEP),
+                    ArrayButton::Gap(GAP_STEP),
+                ),
+                (
+                    ArrayLabel::RadialCount,
+                    ArrayButton::RadialCount(-1),
+                    ArrayButton::RadialCount(1),
+                ),
+            ] {
+                panel
+                    .spawn(NodeBundle {
+                        style: row_style.clone(),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            TextBundle::from_section(label.text(&settings), text_style.clone()),
+                            label,
+                        ));
+                        text_button(row, "-", less);
+                        text_button(row, "+", more);
+                    });
+            }
+            panel
+                .spawn(NodeBundle {
+                    style: row_style,
+                    ..default()
+                })
+                .with_children(|row| {
+                    let label = ArrayLabel::Center;
+                    row.spawn((
+                        TextBundle::from_section(label.text(&settings), text_style.clone()),
+                        label,
+                    ));
+                    text_button(row, "Unpin", ArrayButton::Unpin);
+                });
+        })
+        .id();
+    cmd.entity(sidebar).add_child(panel);
+}
+
+fn array_buttons(
+    button_q: Query<(&Interaction, &ArrayButton), Changed<Interaction>>,
+    mut settings: ResMut<ArraySettings>,
+) {
+    for (interaction, button) in &button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            ArrayButton::Columns(by) => settings.change_count(0, by),
+            ArrayButton::Rows(by) => settings.change_count(1, by),
+            ArrayButton::Gap(by) => settings.change_gap(by),
+            ArrayButton::RadialCount(by) => settings.cycle_radial_count(by),
+            ArrayButton::Unpin => settings.center = None,
+        }
+    }
+}
+
+fn update_array_labels(settings: Res<ArraySettings>, mut label_q: Query<(&mut Text, &ArrayLabel)>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (mut text, label) in &mut label_q {
+        text.sections[0].value = label.text(&settings);
+    }
+}
+
+/// Marks the pinned center of radial arrays while tables are selected
+fn radial_center_guide(
+    mut gizmos: Gizmos,
+    settings: Res<ArraySettings>,
+    projection_q: Query<&OrthographicProjection>,
+    selected_q: Query<(), (With<TableHead>, With<Selected>)>,
+) {
+    let Some(center) = settings.center else {
+        return;
+    };
+    if selected_q.is_empty() {
+        return;
+    }
+    let radius = 6.0 * projection_q.single().scale;
+    let color = Color::srgb(1.0, 0.4, 0.4);
+    gizmos.circle_2d(center, radius, color);
+    for arm in [Vec2::X, Vec2::Y] {
+        gizmos.line_2d(
+            center - arm * radius * 2.0,
+            center + arm * radius * 2.0,
+            color,
+        );
+    }
+}
+
+/// Spawns independent copies of a table at each of `translations`
+fn copy_table(
+    cmd: &mut Commands,
+    table_head: &TableHead,
+    children: &Children,
+    cell_q: &CellQueries,
+    config: &UserConfig,
+    translations: impl IntoIterator<Item = Vec3>,
+) -> Vec<Entity> {
+    let contents: HashMap<Cell, CellContents> = cell_q.read(children).collect();
+    let size = UVec2::new(table_head.num_columns(), table_head.num_rows());
+
+    translations
+        .into_iter()
+        .map(|translation| {
+            spawn_table(
+                cmd,
+                translation,
+                table_head,
+                size,
+                config,
+                |new_table_head, cell| {
+                    contents
+                        .get(&cell)
+                        .cloned()
+                        .unwrap_or_else(|| CellContents::empty(new_table_head, &cell, config))
+                },
+            )
+        })
+        .collect()
+}
+
+/// Groups the copies of grouped tables like the originals, `copies` holds the group of each
+/// copied table with its copies and their positions in the world. The `i`th copies of the tables
+/// of a group make up a new group
+fn group_copies(
+    cmd: &mut Commands,
+    copies: impl IntoIterator<Item = (Option<Entity>, Vec<(Entity, Vec3)>)>,
+) {
+    let mut grouped: HashMap<(Entity, usize), Vec<(Entity, Vec3)>> = HashMap::new();
+    for (group, copies) in copies {
+        let Some(group) = group else {
+            continue;
+        };
+        for (i, copy) in copies.into_iter().enumerate() {
+            grouped.entry((group, i)).or_default().push(copy);
+        }
+    }
+    for tables in grouped.values() {
+        group_tables(cmd, tables);
+    }
+}
+
+/// The bounds of the selected tables, grouped tables are arrayed together so the bounds of the
+/// selected tables of a group are keyed by the group
+fn array_items<'a>(
+    selected: impl IntoIterator<Item = (Entity, &'a TableHead, &'a GlobalTransform)>,
+    groups: &Groups,
+) -> HashMap<Entity, Rect> {
+    let mut items: HashMap<Entity, Rect> = HashMap::new();
+    for (id, table_head, transform) in selected {
+        let item = groups.group_of(id).unwrap_or(id);
+        let bounds = table_bounds(table_head, transform);
+        items
+            .entry(item)
+            .and_modify(|rect| *rect = rect.union(bounds))
+            .or_insert(bounds);
+    }
+    items
+}
+
+/// Copies every selected table once, offset by one cell, and selects the copies instead.
+/// Copies of grouped tables are grouped like the originals
+fn duplicate(
+    mut cmd: Commands,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform, &Children), With<Selected>>,
+    cell_q: CellQueries,
+    user_q: Query<&User>,
+    groups: Groups,
+) {
+    let config = &user_q.single().current_config;
+    let offset = (config.cell_dimensions * Vec2::new(1.0, -1.0)).extend(0.0);
+
+    cmd.remove_resource::<SelectedCells>();
+    let mut copies = vec![];
+    for (id, table_head, transform, children) in &selected_q {
+        cmd.entity(id).remove::<Selected>();
+        let translation = transform.translation() + offset;
+        copies.push((
+            groups.group_of(id),
+            copy_and_select(
+                &mut cmd,
+                table_head,
+                children,
+                &cell_q,
+                config,
+                vec![translation],
+            ),
+        ));
+    }
+    group_copies(&mut cmd, copies);
+}
+
+/// Repeats every selected table in a grid of [`ArraySettings::count`] tables, spaced by
+/// [`ArraySettings::gap`]. The selected tables of a group are repeated together
+fn grid_array(
+    mut cmd: Commands,
+    settings: Res<ArraySettings>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform, &Children), With<Selected>>,
+    cell_q: CellQueries,
+    user_q: Query<&User>,
+    groups: Groups,
+) {
+    let config = &user_q.single().current_config;
+    let items = array_items(
+        selected_q
+            .iter()
+            .map(|(id, table_head, transform, _)| (id, table_head, transform)),
+        &groups,
+    );
+
+    let mut copies = vec![];
+    for (id, table_head, transform, children) in &selected_q {
+        let group = groups.group_of(id);
+        let size = items
+            .get(&group.unwrap_or(id))
+            .map_or(table_head.size(), Rect::size);
+        let step = (size + settings.gap) * Vec2::new(1.0, -1.0);
+        let translations: Vec<Vec3> = (0..settings.count.y)
+            .flat_map(|row| (0..settings.count.x).map(move |column| UVec2::new(column, row)))
+            .skip(1)
+            .map(|index| transform.translation() + (index.as_vec2() * step).extend(0.0))
+            .collect();
+        copies.push((
+            group,
+            copy_and_select(
+                &mut cmd,
+                table_head,
+                children,
+                &cell_q,
+                config,
+                translations,
+            ),
+        ));
+    }
+    group_copies(&mut cmd, copies);
+}
+
+/// Repeats every selected table [`ArraySettings::radial_count`] times around the cursor, or
+/// [`ArraySettings::center`] when it is pinned, keeping the distance between the center of the
+/// table and the center of the array. The selected tables of a group are repeated together
+fn radial_array(
+    mut cmd: Commands,
+    settings: Res<ArraySettings>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform, &Children), With<Selected>>,
+    cell_q: CellQueries,
+    user_q: Query<&User>,
+    mouse_q: MousePosQueries,
+    groups: Groups,
+) {
+    let center = settings.center.unwrap_or_else(|| mouse_q.mouse_pos());
+    if center.is_nan() || settings.radial_count == 0 {
+        return;
+    }
+    let config = &user_q.single().current_config;
+    let items = array_items(
+        selected_q
+            .iter()
+            .map(|(id, table_head, transform, _)| (id, table_head, transform)),
+        &groups,
+    );
+
+    let mut copies = vec![];
+    for (id, table_head, transform, children) in &selected_q {
+        let group = groups.group_of(id);
+        let item_center = items
+            .get(&group.unwrap_or(id))
+            .copied()
+            .unwrap_or_else(|| table_bounds(table_head, transform))
+            .center();
+        let translations: Vec<Vec3> = (1..settings.radial_count)
+            .map(|i| {
+                let rotation = Vec2::from_angle(TAU * i as f32 / settings.radial_count as f32);
+                let offset = center + rotation.rotate(item_center - center) - item_center;
+                transform.translation() + offset.extend(0.0)
+            })
+            .collect();
+        copies.push((
+            group,
+            copy_and_select(
+                &mut cmd,
+                table_head,
+                children,
+                &cell_q,
+                config,
+                translations,
+            ),
+        ));
+    }
+    group_copies(&mut cmd, copies);
+}
+
+/// Copies a table to each of `translations` and selects the copies, which are returned with
+/// their positions
+fn copy_and_select(
+    cmd: &mut Commands,
+    table_head: &TableHead,
+    children: &Children,
+    cell_q: &CellQueries,
+    config: &UserConfig,
+    translations: Vec<Vec3>,
+) -> Vec<(Entity, Vec3)> {
+    let copies = copy_table(
+        cmd,
+        table_head,
+        children,
+        cell_q,
+        config,
+        translations.clone(),
+    );
+    for copy in &copies {
+        cmd.entity(*copy).insert(Selected);
+    }
+    copies.into_iter().zip(translations).collect()
+}
@@ -0,0 +1,395 @@
+use std::ops::Range;
+
+use bevy::ecs::system::SystemParam;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::text::TextLayoutInfo;
+
+use crate::player::{Tool, User};
+use crate::{typing, AppState};
+
+pub struct EditingPlugin;
+
+/// This plugin is responsible for editing text on the canvas, the text of text boxes and sticky
+/// notes as well as the text of table cells.
+/// While typing, arrow keys, `Home` and `End` move the caret, with `Shift` they select,
+/// `Ctrl+A` selects everything, `Escape` or switching to another tool stops editing.
+/// Keyboard shortcuts are ignored while typing, so that typing does not trigger them
+impl Plugin for EditingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextEditing>().add_systems(
+            Update,
+            (
+                stop_editing_on_tool_change,
+                (type_text, draw_caret).chain().run_if(typing),
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// The text that is being edited, with the caret and the selection in it
+#[derive(Resource, Default, Debug)]
+pub struct TextEditing {
+    /// The entity with the edited `Text`, which has a single section
+    pub target: Option<Entity>,
+    /// Byte index of the caret in the text
+    pub caret: usize,
+    /// Where the selection started, everything between it and the caret is selected
+    pub anchor: usize,
+}
+impl TextEditing {
+    /// Starts editing `target` with the caret at `caret`
+    pub fn start(&mut self, target: Entity, caret: usize) {
+        self.target = Some(target);
+        self.caret = caret;
+        self.anchor = caret;
+    }
+
+    pub fn stop(&mut self) {
+        self.target = None;
+    }
+
+    fn selection(&self) -> Range<usize> {
+        self.caret.min(self.anchor)..self.caret.max(self.anchor)
+    }
+}
+
+/// Width of a space, relative to the font size, spaces have no glyphs to measure
+const SPACE_WIDTH: f32 = 0.3;
+/// Height of a line, relative to the font size, for text that is too short to measure
+const LINE_HEIGHT: f32 = 1.2;
+
+/// Where the characters of a laid out text are in the world
+pub struct Layout {
+    /// Byte index of the character of every glyph, with the rect it is drawn in
+    glyphs: Vec<(usize, Rect)>,
+    top_left: Vec2,
+    line_height: f32,
+    space_width: f32,
+}
+impl Layout {
+    /// The line `y` is in, counted from the top
+    fn line_at(&self, y: f32) -> i32 {
+        ((self.top_left.y - y) / self.line_height).floor() as i32
+    }
+
+    /// Vertical center of `line`
+    fn line_center(&self, line: i32) -> f32 {
+        self.top_left.y - (line as f32 + 0.5) * self.line_height
+    }
+
+    /// Horizontal position and line of the caret at `index` in `text`
+    fn caret(&self, text: &str, index: usize) -> (f32, i32) {
+        let next = self.glyphs.iter().find(|(i, _)| *i >= index);
+        if let Some((_, rect)) = next.filter(|(i, _)| !text[index..*i].contains('\n')) {
+            return (rect.min.x, self.line_at(rect.center().y));
+        }
+        let previous = self.glyphs.iter().rev().find(|(i, _)| *i < index);
+        let Some((i, rect)) = previous else {
+            let lines = text[..index].matches('\n').count() as i32;
+            return (self.top_left.x, lines);
+        };
+        let line = self.line_at(rect.center().y);
+        let end = i + text[*i..].chars().next().map_or(0, char::len_utf8);
+        let after = &text[end.min(index)..index];
+        if after.contains('\n') {
+            (self.top_left.x, line + after.matches('\n').count() as i32)
+        } else {
+            (rect.max.x + after.len() as f32 * self.space_width, line)
+        }
+    }
+
+    /// Index in `text` of the caret position closest to `pos`
+    pub fn index_at(&self, text: &str, pos: Vec2) -> usize {
+        if pos.y > self.top_left.y {
+            return 0;
+        }
+        let line = self.line_at(pos.y);
+        let closest = self
+            .glyphs
+            .iter()
+            .filter(|(_, rect)| self.line_at(rect.center().y) == line)
+            .min_by(|(_, a), (_, b)| {
+                (a.center().x - pos.x)
+                    .abs()
+                    .total_cmp(&(b.center().x - pos.x).abs())
+            });
+        if let Some((i, rect)) = closest {
+            return if pos.x < rect.center().x {
+                *i
+            } else {
+                i + text[*i..].chars().next().map_or(0, char::len_utf8)
+            };
+        }
+        // Lines without glyphs are empty, or the text ends before them
+        let mut line_start = 0;
+        for (i, text_line) in text.split_inclusive('\n').enumerate() {
+            if i as i32 == line {
+                return line_start;
+            }
+            line_start += text_line.len();
+        }
+        text.len()
+    }
+
+    /// The outline of the text, at least one line high so empty text can be clicked
+    pub fn bounds(&self, logical_size: Vec2) -> Rect {
+        let size = logical_size.max(Vec2::new(self.line_height / 2.0, self.line_height));
+        Rect::from_corners(self.top_left, self.top_left + size * Vec2::new(1.0, -1.0))
+    }
+}
+
+/// Queries to find out where text is drawn
+#[derive(SystemParam)]
+pub struct TextLayouts<'w, 's> {
+    windows: Query<'w, 's, &'static Window>,
+    layout_q: Query<
+        'w,
+        's,
+        (
+            &'static TextLayoutInfo,
+            &'static GlobalTransform,
+            &'static Anchor,
+        ),
+    >,
+}
+impl TextLayouts<'_, '_> {
+    /// The layout of the text of `id`, drawn with `font_size`
+    pub fn get(&self, id: Entity, font_size: f32) -> Option<(Layout, Vec2)> {
+        let (info, transform, anchor) = self.layout_q.get(id).ok()?;
+        // Glyphs are laid out in physical pixels
+        let scale_factor = self.windows.get_single().map_or(1.0, Window::scale_factor);
+        let size = info.logical_size;
+        let bottom_left = transform.translation().truncate() + size * (-anchor.as_vec() - 0.5);
+        let lines = (size.y / (font_size * LINE_HEIGHT)).round().max(1.0);
+        let line_height = if size.y > 0.0 {
+            size.y / lines
+        } else {
+            font_size * LINE_HEIGHT
+        };
+        let glyphs = info
+            .glyphs
+            .iter()
+            .map(|glyph| {
+                let center = bottom_left + glyph.position / scale_factor;
+                (
+                    glyph.byte_index,
+                    Rect::from_center_size(center, glyph.size / scale_factor),
+                )
+            })
+            .collect();
+        let layout = Layout {
+            glyphs,
+            top_left: bottom_left + Vec2::Y * size.y,
+            line_height,
+            space_width: font_size * SPACE_WIDTH,
+        };
+        Some((layout, size))
+    }
+}
+
+fn stop_editing_on_tool_change(
+    mut editing: ResMut<TextEditing>,
+    user_q: Query<&User>,
+    mut last_tool: Local<Option<Tool>>,
+) {
+    let tool = user_q.single().current_tool;
+    if last_tool.replace(tool).is_some_and(|last| last != tool) {
+        editing.stop();
+    }
+}
+
+/// Index of the character boundary before `index`
+fn previous_char(text: &str, index: usize) -> usize {
+    text[..index]
+        .char_indices()
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+/// Index of the character boundary after `index`
+fn next_char(text: &str, index: usize) -> usize {
+    text[index..]
+        .chars()
+        .next()
+        .map_or(index, |c| index + c.len_utf8())
+}
+
+fn type_text(
+    mut events: EventReader<KeyboardInput>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut editing: ResMut<TextEditing>,
+    mut text_q: Query<&mut Text>,
+    layouts: TextLayouts,
+) {
+    let Some(target) = editing.target else {
+        return;
+    };
+    let Ok(mut text) = text_q.get_mut(target) else {
+        editing.stop();
+        return;
+    };
+    let Some(section) = text.sections.first() else {
+        return;
+    };
+    let mut value = section.value.clone();
+    let font_size = section.style.font_size;
+    let ctrl = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    // The text may have changed since the caret was placed
+    editing.caret = editing.caret.min(value.len());
+    editing.anchor = editing.anchor.min(value.len());
+
+    let mut edited = false;
+    for event in events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        let selection = editing.selection();
+        let insert = |editing: &mut TextEditing, value: &mut String, inserted: &str| {
+            value.replace_range(selection.clone(), inserted);
+            editing.start(target, selection.start + inserted.len());
+        };
+        let move_caret = |editing: &mut TextEditing, to: usize| {
+            editing.caret = to;
+            if !shift {
+                editing.anchor = to;
+            }
+        };
+        match &event.logical_key {
+            Key::Character(c) if ctrl && c.eq_ignore_ascii_case("a") => {
+                editing.anchor = 0;
+                editing.caret = value.len();
+            }
+            Key::Character(c) if !ctrl => insert(&mut editing, &mut value, c),
+            Key::Space => insert(&mut editing, &mut value, " "),
+            Key::Enter => insert(&mut editing, &mut value, "\n"),
+            Key::Backspace | Key::Delete if !selection.is_empty() => {
+                insert(&mut editing, &mut value, "");
+            }
+            Key::Backspace if editing.caret > 0 => {
+                let start = previous_char(&value, editing.caret);
+                value.replace_range(start..editing.caret, "");
+                editing.start(target, start);
+            }
+            Key::Delete if editing.caret < value.len() => {
+                let caret = editing.caret;
+                value.replace_range(caret..next_char(&value, caret), "");
+                editing.start(target, caret);
+            }
+            Key::ArrowLeft if !shift && !selection.is_empty() => {
+                move_caret(&mut editing, selection.start);
+            }
+            Key::ArrowRight if !shift && !selection.is_empty() => {
+                move_caret(&mut editing, selection.end);
+            }
+            Key::ArrowLeft => {
+                let to = previous_char(&value, editing.caret);
+                move_caret(&mut editing, to);
+            }
+            Key::ArrowRight => {
+                let to = next_char(&value, editing.caret);
+                move_caret(&mut editing, to);
+            }
+            Key::Home => {
+                let to = value[..editing.caret].rfind('\n').map_or(0, |i| i + 1);
+                move_caret(&mut editing, to);
+            }
+            Key::End => {
+                let to = value[editing.caret..]
+                    .find('\n')
+                    .map_or(value.len(), |i| editing.caret + i);
+                move_caret(&mut editing, to);
+            }
+            Key::ArrowUp | Key::ArrowDown => {
+                let Some((layout, _)) = layouts.get(target, font_size) else {
+                    continue;
+                };
+                let (x, line) = layout.caret(&value, editing.caret);
+                let line = if event.logical_key == Key::ArrowUp {
+                    line - 1
+                } else {
+                    line + 1
+                };
+                let to = if line < 0 {
+                    0
+                } else {
+                    layout.index_at(&value, Vec2::new(x, layout.line_center(line)))
+                };
+                move_caret(&mut editing, to);
+            }
+            Key::Escape => {
+                editing.stop();
+                return;
+            }
+            _ => continue,
+        }
+        edited = true;
+    }
+
+    if edited && text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+/// Draws the blinking caret and outlines the selected characters line by line
+fn draw_caret(
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+    editing: Res<TextEditing>,
+    text_q: Query<&Text>,
+    layouts: TextLayouts,
+) {
+    let Some(target) = editing.target else {
+        return;
+    };
+    let Some(section) = text_q
+        .get(target)
+        .ok()
+        .and_then(|text| text.sections.first())
+    else {
+        return;
+    };
+    let Some((layout, _)) = layouts.get(target, section.style.font_size) else {
+        return;
+    };
+    let value = &section.value;
+    let color = Color::srgb(0.3, 0.5, 0.9);
+
+    let selection = editing.selection();
+    let mut lines: Vec<(i32, Rect)> = vec![];
+    for (_, rect) in layout.glyphs.iter().filter(|(i, _)| selection.contains(i)) {
+        let line = layout.line_at(rect.center().y);
+        match lines.iter_mut().find(|(l, _)| *l == line) {
+            Some((_, span)) => *span = span.union(*rect),
+            None => lines.push((line, *rect)),
+        }
+    }
+    for (line, span) in lines {
+        let center = Vec2::new(span.center().x, layout.line_center(line));
+        gizmos.rect_2d(
+            center,
+            0.0,
+            Vec2::new(span.width(), layout.line_height),
+            color.with_alpha(0.6),
+        );
+    }
+
+    if time.elapsed_seconds() % 1.0 < 0.5 {
+        let caret = editing.caret.min(value.len());
+        let (x, line) = layout.caret(value, caret);
+        let y = layout.line_center(line);
+        let half = layout.line_height / 2.0;
+        gizmos.line_2d(Vec2::new(x, y - half), Vec2::new(x, y + half), color);
+    }
+}
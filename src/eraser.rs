@@ -0,0 +1,289 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::actions::{maintain_actions, Actions};
+use crate::layers::{InLayer, Pickable};
+use crate::line::{spawn_polyline, Polyline};
+use crate::paint::PaintMaterials;
+use crate::pen::{spawn_stroke, Stroke};
+use crate::player::{Tool, User};
+use crate::shape::{Shape, ShapeKind};
+use crate::status::Status;
+use crate::table::{set_cell_text, CellContents, CellQueries, TableHead};
+use crate::{ctrl_pressed, tool_selected, using_tool, AppState, CanvasSet, MousePosQueries};
+
+pub struct EraserPlugin;
+
+/// This plugin is responsible for `Tool::Eraser`, which erases whatever it is dragged across.
+/// Strokes and lines are cut where they are erased, shapes are deleted and cells of tables are
+/// cleared.
+/// `[` and `]` change the radius of the eraser, which is shown around the cursor
+impl Plugin for EraserPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                erase
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Eraser))
+                    .after(maintain_actions),
+                eraser_outline
+                    .in_set(CanvasSet)
+                    .run_if(tool_selected(Tool::Eraser)),
+                change_eraser_radius
+                    .run_if(tool_selected(Tool::Eraser))
+                    .run_if(not(ctrl_pressed))
+                    .run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// Distance from `pos` to the line segment from `a` to `b`
+pub fn distance_to_segment(pos: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let line = b - a;
+    let t = if line == Vec2::ZERO {
+        0.0
+    } else {
+        ((pos - a).dot(line) / line.length_squared()).clamp(0.0, 1.0)
+    };
+    pos.distance(a + line * t)
+}
+
+/// Adds points along `points` so that none are further than `step` apart
+fn densify(points: &[Vec2], step: f32) -> Vec<Vec2> {
+    let mut dense = vec![];
+    for pair in points.windows(2) {
+        let samples = (pair[0].distance(pair[1]) / step).ceil().max(1.0) as u32;
+        dense.extend((0..samples).map(|i| pair[0].lerp(pair[1], i as f32 / samples as f32)));
+    }
+    dense.extend(points.last());
+    dense
+}
+
+/// The runs of `points` that are left when everything within `reach` of `samples` is erased,
+/// `None` when nothing is erased
+fn cut(points: &[Vec2], samples: &[Vec2], reach: f32, step: f32) -> Option<Vec<Vec<Vec2>>> {
+    let hit = points.windows(2).any(|pair| {
+        samples
+            .iter()
+            .any(|pos| distance_to_segment(*pos, pair[0], pair[1]) < reach)
+    }) || (points.len() == 1
+        && samples.iter().any(|pos| pos.distance(points[0]) < reach));
+    if !hit {
+        return None;
+    }
+
+    let mut runs: Vec<Vec<Vec2>> = vec![vec![]];
+    for point in densify(points, step) {
+        if samples.iter().any(|pos| pos.distance(point) < reach) {
+            if runs.last().is_some_and(|run| !run.is_empty()) {
+                runs.push(vec![]);
+            }
+        } else if let Some(run) = runs.last_mut() {
+            run.push(point);
+        }
+    }
+    runs.retain(|run| run.len() > 1);
+    Some(runs)
+}
+
+/// Everything the eraser can erase, items on locked layers are left alone
+#[derive(SystemParam)]
+struct ErasableItems<'w, 's> {
+    stroke_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static mut Stroke,
+            &'static Transform,
+            Option<&'static InLayer>,
+        ),
+        Pickable,
+    >,
+    line_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static mut Polyline,
+            &'static Transform,
+            Option<&'static InLayer>,
+        ),
+        Pickable,
+    >,
+    shape_q: Query<'w, 's, (Entity, &'static Shape, &'static GlobalTransform), Pickable>,
+    table_head_q: Query<
+        'w,
+        's,
+        (
+            &'static TableHead,
+            &'static GlobalTransform,
+            &'static Children,
+        ),
+        Pickable,
+    >,
+    cell_queries: CellQueries<'w, 's>,
+}
+
+fn erase(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    user_q: Query<&User>,
+    mut last_pos: Local<Option<Vec2>>,
+    items: ErasableItems,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: PaintMaterials,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        *last_pos = None;
+        return;
+    }
+    let ErasableItems {
+        mut stroke_q,
+        mut line_q,
+        shape_q,
+        table_head_q,
+        mut cell_queries,
+    } = items;
+    let config = &user_q.single().current_config;
+    let radius = config.eraser_radius;
+
+    // Sample the path since the last frame, so fast drags do not skip anything
+    let from = last_pos.unwrap_or(actions.to);
+    let steps = (from.distance(actions.to) / (radius / 2.0)).ceil().max(1.0) as u32;
+    let samples: Vec<Vec2> = (0..=steps)
+        .map(|i| from.lerp(actions.to, i as f32 / steps as f32))
+        .collect();
+    *last_pos = Some(actions.to);
+
+    for (id, mut stroke, transform, in_layer) in &mut stroke_q {
+        let reach = radius + stroke.width / 2.0;
+        // The stroke is cut into the runs of points that are left
+        let Some(runs) = cut(&stroke.points, &samples, reach, radius / 2.0) else {
+            continue;
+        };
+        let mut runs = runs.into_iter();
+        let Some(first) = runs.next() else {
+            cmd.entity(id).despawn();
+            continue;
+        };
+        stroke.points = first;
+        for run in runs {
+            let piece = Stroke {
+                points: run,
+                width: stroke.width,
+                color: stroke.color,
+            };
+            let id = spawn_stroke(
+                &mut cmd,
+                piece,
+                transform.translation.z,
+                &mut meshes,
+                &mut materials,
+            );
+            if let Some(in_layer) = in_layer {
+                cmd.entity(id).insert(*in_layer);
+            }
+        }
+    }
+
+    for (id, mut line, transform, in_layer) in &mut line_q {
+        let reach = radius + line.width / 2.0;
+        let Some(runs) = cut(&line.points, &samples, reach, radius / 2.0) else {
+            continue;
+        };
+        let mut runs = runs.into_iter();
+        let Some(first) = runs.next() else {
+            cmd.entity(id).despawn();
+            continue;
+        };
+        line.points = first;
+        for run in runs {
+            let piece = Polyline {
+                points: run,
+                width: line.width,
+                color: line.color,
+                dash: line.dash,
+            };
+            let id = spawn_polyline(
+                &mut cmd,
+                piece,
+                transform.translation.z,
+                &mut meshes,
+                &mut materials,
+            );
+            if let Some(in_layer) = in_layer {
+                cmd.entity(id).insert(*in_layer);
+            }
+        }
+    }
+
+    for (id, shape, transform) in &shape_q {
+        let center = transform.translation().truncate();
+        let reach = shape.size / 2.0 + radius;
+        let hit = samples.iter().any(|pos| {
+            let local = (*pos - center).abs();
+            match shape.kind {
+                ShapeKind::Rectangle => local.x <= reach.x && local.y <= reach.y,
+                ShapeKind::Ellipse => (local / reach).length() <= 1.0,
+            }
+        });
+        if hit {
+            cmd.entity(id).despawn_recursive();
+        }
+    }
+
+    for (table_head, transform, children) in &table_head_q {
+        let translation = transform.translation().truncate();
+        cell_queries.edit(children, |cell, mut bg_color, mut text| {
+            let rect = Rect::from_center_size(
+                translation + table_head.cell_center(cell),
+                table_head.cell_size(cell),
+            );
+            let hit = samples
+                .iter()
+                .any(|pos| pos.clamp(rect.min, rect.max).distance(*pos) < radius);
+            if !hit {
+                return;
+            }
+            let empty = CellContents::empty(table_head, &cell, config);
+            if *bg_color != empty.bg_color {
+                *bg_color = empty.bg_color;
+            }
+            if text.sections.first().is_some_and(|s| !s.value.is_empty()) {
+                set_cell_text(&mut text, empty.text);
+            }
+        });
+    }
+}
+
+fn eraser_outline(mut gizmos: Gizmos, mouse_q: MousePosQueries, user_q: Query<&User>) {
+    let pos = mouse_q.mouse_pos();
+    if pos.is_nan() {
+        return;
+    }
+    let radius = user_q.single().current_config.eraser_radius;
+    gizmos.circle_2d(pos, radius, Color::srgb(0.9, 0.9, 0.9));
+}
+
+fn change_eraser_radius(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut user_q: Query<&mut User>,
+    mut status: EventWriter<Status>,
+) {
+    let change = if keys.just_pressed(KeyCode::BracketRight) {
+        2.0
+    } else if keys.just_pressed(KeyCode::BracketLeft) {
+        -2.0
+    } else {
+        return;
+    };
+    let config = &mut user_q.single_mut().current_config;
+    config.eraser_radius = (config.eraser_radius + change).clamp(2.0, 200.0);
+    status.send(Status::info(format!(
+        "Eraser radius: {}",
+        config.eraser_radius
+    )));
+}
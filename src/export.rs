@@ -0,0 +1,638 @@
+use crate::chart::{Chart, ChartCell, ChartNumbering, ChartTable, LegendEntry};
+use crate::files::SaveFile;
+use crate::group::Groups;
+use crate::menu::{text_button, Sidebar};
+use crate::numbering::{Numbering, NumberingCorner};
+use crate::paint::cell_color;
+use crate::palette::Palette;
+use crate::player::User;
+use crate::selection::Selected;
+use crate::status::Status;
+use crate::symbols::palette_index;
+use crate::table::{CellQueries, TableHead};
+use crate::AppState;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+pub struct ExportPlugin;
+
+/// This plugin is responsible for exporting tables from a panel in the sidebar, as Markdown and
+/// HTML tables or as printable SVG, PNG and PDF charts. The selected tables are exported, or all
+/// tables if none are selected. Header rows become the headers of exported tables, charts show
+/// the symbols of painted cells with a legend of their palette colors. Numbered tables are
+/// exported with their row and column numbers. Grouped tables are exported together, unless the
+/// panel is switched to flatten groups
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeepGroups>().add_systems(
+            Update,
+            (build_export_panel, toggle_keep_groups, export_tables)
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExportFormat {
+    Markdown,
+    Html,
+    Svg,
+    Png,
+    Pdf,
+}
+impl ExportFormat {
+    /// Formats of the text of tables
+    const TABLES: [ExportFormat; 2] = [ExportFormat::Markdown, ExportFormat::Html];
+    /// Formats of charts
+    const CHARTS: [ExportFormat; 3] = [ExportFormat::Svg, ExportFormat::Png, ExportFormat::Pdf];
+
+    fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Svg => "SVG",
+            ExportFormat::Png => "PNG",
+            ExportFormat::Pdf => "PDF",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Png => "png",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+#[derive(Component)]
+struct ExportButton(ExportFormat);
+
+/// Whether the tables of a group are exported together, or each on its own as if they were not
+/// grouped
+#[derive(Resource, Debug)]
+struct KeepGroups(bool);
+impl Default for KeepGroups {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+impl KeepGroups {
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Groups: kept"
+        } else {
+            "Groups: flattened"
+        }
+    }
+}
+
+#[derive(Component)]
+struct KeepGroupsButton;
+
+/// The texts of the cells of a table by row, then by column
+#[derive(Debug)]
+struct ExportedTable {
+    header_rows: usize,
+    header_columns: usize,
+    rows: Vec<Vec<String>>,
+}
+impl ExportedTable {
+    fn read(
+        table_head: &TableHead,
+        numbering: Option<&Numbering>,
+        children: &Children,
+        cell_q: &CellQueries,
+    ) -> Self {
+        let mut rows = vec![
+            vec![String::new(); table_head.num_columns() as usize];
+            table_head.num_rows() as usize
+        ];
+        for (cell, contents) in cell_q.read(children) {
+            if let Some(text) = rows
+                .get_mut(cell.row as usize)
+                .and_then(|row| row.get_mut(cell.column as usize))
+            {
+                *text = contents.text;
+            }
+        }
+        let mut table = Self {
+            header_rows: table_head.header_rows as usize,
+            header_columns: table_head.header_columns as usize,
+            rows,
+        };
+        if let Some(numbering) = numbering {
+            let (rows, columns) = numbering.labeled_lines(table_head);
+            table.number(&rows, &columns, numbering.corner);
+        }
+        table
+    }
+
+    /// Adds a header column with the numbers of `rows` and a row with the numbers of `columns`,
+    /// on the sides of `corner`. Numbers on the top or left become headers
+    fn number(&mut self, rows: &[(u32, i32)], columns: &[(u32, i32)], corner: NumberingCorner) {
+        let width = self.rows.first().map_or(0, Vec::len);
+        let mut column_numbers = vec![String::new(); width];
+        for &(column, number) in columns {
+            if let Some(text) = column_numbers.get_mut(column as usize) {
+                *text = number.to_string();
+            }
+        }
+        let mut row_numbers = vec![String::new(); self.rows.len()];
+        for &(row, number) in rows {
+            if let Some(text) = row_numbers.get_mut(row as usize) {
+                *text = number.to_string();
+            }
+        }
+
+        for (row, number) in self.rows.iter_mut().zip(row_numbers) {
+            if corner.is_right() {
+                row.push(number);
+            } else {
+                row.insert(0, number);
+            }
+        }
+        if corner.is_right() {
+            column_numbers.push(String::new());
+        } else {
+            column_numbers.insert(0, String::new());
+            self.header_columns += 1;
+        }
+        if corner.is_bottom() {
+            self.rows.push(column_numbers);
+        } else {
+            self.rows.insert(0, column_numbers);
+            self.header_rows += 1;
+        }
+    }
+}
+
+/// A Markdown table. Markdown tables have exactly one header row, so the first header row is
+/// used, other header rows and the cells of header columns are bold. Tables without header rows
+/// get an empty one
+fn to_markdown(table: &ExportedTable) -> String {
+    let escape = |text: &str| text.replace('|', "\\|").replace('\n', "<br>");
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let columns = table.rows.first().map_or(0, Vec::len);
+
+    let mut rows = table.rows.iter().enumerate();
+    let header = match table.header_rows {
+        0 => vec![String::new(); columns],
+        _ => rows
+            .next()
+            .map(|(_, row)| row.iter().map(|text| escape(text)).collect())
+            .unwrap_or_default(),
+    };
+    let mut lines = vec![line(header), line(vec!["---".to_string(); columns])];
+    for (i, row) in rows {
+        let cells = row
+            .iter()
+            .enumerate()
+            .map(|(j, text)| {
+                let text = escape(text);
+                let bold = i < table.header_rows || j < table.header_columns;
+                if bold && !text.is_empty() {
+                    format!("**{text}**")
+                } else {
+                    text
+                }
+            })
+            .collect();
+        lines.push(line(cells));
+    }
+    lines.join("\n")
+}
+
+/// An HTML table, header rows go in its head and all header cells are `th` elements
+fn to_html(table: &ExportedTable) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\n', "<br>")
+    };
+    let row = |i: usize, row: &[String]| {
+        let cells: String = row
+            .iter()
+            .enumerate()
+            .map(|(j, text)| {
+                if i < table.header_rows {
+                    format!("<th scope=\"col\">{}</th>", escape(text))
+                } else if j < table.header_columns {
+                    format!("<th scope=\"row\">{}</th>", escape(text))
+                } else {
+                    format!("<td>{}</td>", escape(text))
+                }
+            })
+            .collect();
+        format!("    <tr>{cells}</tr>\n")
+    };
+
+    let (head, body) = table.rows.split_at(table.header_rows.min(table.rows.len()));
+    let mut html = String::from("<table>\n");
+    if !head.is_empty() {
+        html += "  <thead>\n";
+        html.extend(head.iter().enumerate().map(|(i, cells)| row(i, cells)));
+        html += "  </thead>\n";
+    }
+    html += "  <tbody>\n";
+    html.extend(
+        body.iter()
+            .enumerate()
+            .map(|(i, cells)| row(i + head.len(), cells)),
+    );
+    html += "  </tbody>\n</table>";
+    html
+}
+
+/// Adds the export panel to the sidebar whenever the sidebar is spawned
+fn build_export_panel(
+    mut cmd: Commands,
+    sidebar_q: Query<Entity, Added<Sidebar>>,
+    keep_groups: Res<KeepGroups>,
+) {
+    let Ok(sidebar) = sidebar_q.get_single() else {
+        return;
+    };
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        color: Color::WHITE,
+        ..default()
+    };
+    let panel = cmd
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(3.),
+                    padding: UiRect::all(Val::Px(5.)),
+                    ..default()
+                },
+                background_color: Color::linear_rgb(0.1, 0.1, 0.1).into(),
+                ..default()
+            },
+            Interaction::None,
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section("Export", text_style));
+            for formats in [&ExportFormat::TABLES[..], &ExportFormat::CHARTS[..]] {
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(5.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for format in formats {
+                            text_button(row, format.label(), ExportButton(*format));
+                        }
+                    });
+            }
+            text_button(panel, keep_groups.label(), KeepGroupsButton);
+        })
+        .id();
+    cmd.entity(sidebar).add_child(panel);
+}
+
+fn toggle_keep_groups(
+    button_q: Query<(&Interaction, &Children), (Changed<Interaction>, With<KeepGroupsButton>)>,
+    mut keep_groups: ResMut<KeepGroups>,
+    mut text_q: Query<&mut Text>,
+) {
+    for (interaction, children) in &button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        keep_groups.0 = !keep_groups.0;
+        for child in children {
+            if let Ok(mut text) = text_q.get_mut(*child) {
+                text.sections[0].value = keep_groups.label().to_string();
+            }
+        }
+    }
+}
+
+/// A table that is exported
+struct SourceTable<'a> {
+    table_head: &'a TableHead,
+    transform: &'a GlobalTransform,
+    children: &'a Children,
+    numbering: Option<&'a Numbering>,
+    /// The group the table is exported with, tables are not in groups when groups are flattened
+    group: Option<Entity>,
+}
+
+/// The tables that can be exported, with what is needed to draw them into charts
+#[derive(SystemParam)]
+struct ExportSources<'w, 's> {
+    table_head_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static TableHead,
+            &'static GlobalTransform,
+            &'static Children,
+            Option<&'static Numbering>,
+            Has<Selected>,
+        ),
+    >,
+    cell_q: CellQueries<'w, 's>,
+    materials: Res<'w, Assets<ColorMaterial>>,
+    palette: Res<'w, Palette>,
+    fonts: Res<'w, Assets<Font>>,
+    user_q: Query<'w, 's, &'static User>,
+    groups: Groups<'w, 's>,
+    keep_groups: Res<'w, KeepGroups>,
+}
+impl ExportSources<'_, '_> {
+    /// The selected tables, or all tables if none are selected, from top to bottom and left to
+    /// right. When groups are kept, the tables of a group follow the topmost one
+    fn tables(&self) -> Vec<SourceTable<'_>> {
+        let any_selected = self.table_head_q.iter().any(|(.., selected)| selected);
+        let mut tables: Vec<_> = self
+            .table_head_q
+            .iter()
+            .filter(|(.., selected)| *selected || !any_selected)
+            .map(
+                |(id, table_head, transform, children, numbering, _)| SourceTable {
+                    table_head,
+                    transform,
+                    children,
+                    numbering,
+                    group: self.groups.group_of(id).filter(|_| self.keep_groups.0),
+                },
+            )
+            .collect();
+        tables.sort_by(|a, b| {
+            let (a, b) = (a.transform.translation(), b.transform.translation());
+            b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x))
+        });
+        // The tables of a group move up to the first one, sorting is stable so they keep their order
+        let firsts: Vec<usize> = (0..tables.len())
+            .map(|i| {
+                let group = tables[i].group;
+                tables
+                    .iter()
+                    .position(|table| group.is_some() && table.group == group)
+                    .unwrap_or(i)
+            })
+            .collect();
+        let mut grouped: Vec<_> = firsts.into_iter().zip(tables).collect();
+        grouped.sort_by_key(|(first, _)| *first);
+        grouped.into_iter().map(|(_, table)| table).collect()
+    }
+
+    /// A chart of `tables` with the symbols of their painted cells, and a legend of the palette
+    /// colors they use
+    fn chart(&self, tables: &[SourceTable<'_>]) -> Chart {
+        let config = &self.user_q.single().current_config;
+        let mut counts = vec![0; self.palette.colors.len()];
+        let mut chart_tables = vec![];
+        let mut groups = vec![];
+        for table in tables {
+            let table_head = table.table_head;
+            let empty = ChartCell {
+                fill: Color::NONE,
+                text: String::new(),
+                symbol: None,
+            };
+            let mut cells = vec![
+                vec![empty; table_head.num_columns() as usize];
+                table_head.num_rows() as usize
+            ];
+            for (cell, contents) in self.cell_q.read(table.children) {
+                let used =
+                    palette_index(&contents.bg_color, config, &self.palette, &self.materials);
+                if let Some(i) = used {
+                    counts[i] += 1;
+                }
+                if let Some(chart_cell) = cells
+                    .get_mut(cell.row as usize)
+                    .and_then(|row| row.get_mut(cell.column as usize))
+                {
+                    *chart_cell = ChartCell {
+                        fill: cell_color(&contents.bg_color, &self.materials),
+                        text: contents.text,
+                        symbol: used.map(|i| self.palette.colors[i].symbol),
+                    };
+                }
+            }
+            let group = table.group.map(|group| {
+                groups.iter().position(|g| *g == group).unwrap_or_else(|| {
+                    groups.push(group);
+                    groups.len() - 1
+                })
+            });
+            chart_tables.push(ChartTable {
+                position: table.transform.translation().truncate(),
+                column_edges: table_head.column_edges(),
+                row_edges: table_head.row_edges(),
+                cells,
+                numbering: table.numbering.map(|numbering| {
+                    let (rows, columns) = numbering.labeled_lines(table_head);
+                    let lines = |lines: Vec<(u32, i32)>| {
+                        lines
+                            .into_iter()
+                            .map(|(line, number)| (line as usize, number))
+                            .collect()
+                    };
+                    ChartNumbering {
+                        rows: lines(rows),
+                        columns: lines(columns),
+                        right: numbering.corner.is_right(),
+                        bottom: numbering.corner.is_bottom(),
+                    }
+                }),
+                group,
+            });
+        }
+
+        let legend: Vec<LegendEntry> = self
+            .palette
+            .colors
+            .iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(palette_color, count)| LegendEntry {
+                symbol: palette_color.symbol,
+                color: palette_color.color,
+                name: palette_color.name.clone(),
+                count,
+            })
+            .collect();
+        Chart::new(&chart_tables, &legend, config.table_font_size)
+    }
+
+    fn export(&self, format: ExportFormat) -> Result<Vec<u8>, String> {
+        let tables = self.tables();
+        if tables.is_empty() {
+            return Err("there are no tables".to_string());
+        }
+        let text = |to_text: fn(&ExportedTable) -> String| {
+            let exported: Vec<_> = tables
+                .iter()
+                .map(|table| {
+                    let exported = ExportedTable::read(
+                        table.table_head,
+                        table.numbering,
+                        table.children,
+                        &self.cell_q,
+                    );
+                    (table.group, to_text(&exported))
+                })
+                .collect();
+            (join_tables(&exported, format) + "\n").into_bytes()
+        };
+        Ok(match format {
+            ExportFormat::Markdown => text(to_markdown),
+            ExportFormat::Html => text(to_html),
+            ExportFormat::Svg => self.chart(&tables).to_svg().into_bytes(),
+            ExportFormat::Pdf => self.chart(&tables).to_pdf(),
+            ExportFormat::Png => {
+                let font = self
+                    .fonts
+                    .get(&Handle::default())
+                    .ok_or("the font is not loaded")?;
+                self.chart(&tables).to_png(&font.font)?
+            }
+        })
+    }
+}
+
+/// Joins exported tables. The tables of a group are kept together, in HTML in a section and in
+/// Markdown between thematic breaks
+fn join_tables(tables: &[(Option<Entity>, String)], format: ExportFormat) -> String {
+    let mut joined = String::new();
+    let mut previous = None;
+    for (i, (group, table)) in tables.iter().enumerate() {
+        let next = tables.get(i + 1).and_then(|(group, _)| *group);
+        let starts_group = group.is_some() && *group != previous;
+        let ends_group = group.is_some() && *group != next;
+        if i > 0 {
+            let leaves_group = previous.is_some() && *group != previous;
+            joined += if format == ExportFormat::Markdown && (starts_group || leaves_group) {
+                "\n\n---\n\n"
+            } else {
+                "\n\n"
+            };
+        }
+        if format == ExportFormat::Html && starts_group {
+            joined += "<section class=\"group\">\n";
+        }
+        joined += table;
+        if format == ExportFormat::Html && ends_group {
+            joined += "\n</section>";
+        }
+        previous = *group;
+    }
+    joined
+}
+
+fn export_tables(
+    button_q: Query<(&Interaction, &ExportButton), Changed<Interaction>>,
+    sources: ExportSources,
+    mut save_events: EventWriter<SaveFile>,
+    mut status: EventWriter<Status>,
+) {
+    for (interaction, ExportButton(format)) in &button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match sources.export(*format) {
+            Ok(bytes) => {
+                save_events.send(SaveFile {
+                    name: "tables".to_string(),
+                    extension: format.extension(),
+                    bytes,
+                });
+            }
+            Err(e) => {
+                status.send(Status::warning(format!(
+                    "Could not export {}: {e}",
+                    format.label()
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(header_rows: usize, header_columns: usize, rows: &[&[&str]]) -> ExportedTable {
+        ExportedTable {
+            header_rows,
+            header_columns,
+            rows: rows
+                .iter()
+                .map(|row| row.iter().map(|text| text.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn markdown_header_row() {
+        let exported = table(1, 1, &[&["", "Count"], &["a|b", "1"], &["c", ""]]);
+        assert_eq!(
+            to_markdown(&exported),
+            "|  | Count |\n| --- | --- |\n| **a\\|b** | 1 |\n| **c** |  |"
+        );
+    }
+
+    #[test]
+    fn markdown_without_header_row() {
+        let exported = table(0, 0, &[&["x", "y"]]);
+        assert_eq!(to_markdown(&exported), "|  |  |\n| --- | --- |\n| x | y |");
+    }
+
+    #[test]
+    fn numbers_as_headers() {
+        let mut exported = table(0, 0, &[&["a", "b"], &["c", "d"]]);
+        exported.number(&[(0, 2), (1, 1)], &[(0, 1)], NumberingCorner::BottomLeft);
+        assert_eq!(
+            to_markdown(&exported),
+            "|  |  |  |\n| --- | --- | --- |\n| **2** | a | b |\n| **1** | c | d |\n|  | 1 |  |"
+        );
+    }
+
+    #[test]
+    fn groups_are_kept_together() {
+        let group = Some(Entity::from_raw(7));
+        let tables = [
+            (None, "a".to_string()),
+            (group, "b".to_string()),
+            (group, "c".to_string()),
+            (None, "d".to_string()),
+            (None, "e".to_string()),
+        ];
+        assert_eq!(
+            join_tables(&tables, ExportFormat::Markdown),
+            "a\n\n---\n\nb\n\nc\n\n---\n\nd\n\ne"
+        );
+        assert_eq!(
+            join_tables(&tables, ExportFormat::Html),
+            "a\n\n<section class=\"group\">\nb\n\nc\n</section>\n\nd\n\ne"
+        );
+    }
+
+    #[test]
+    fn html_header_cells() {
+        let exported = table(1, 1, &[&["", "<b>"], &["row", "1"]]);
+        assert_eq!(
+            to_html(&exported),
+            "<table>\n  <thead>\n    <tr><th scope=\"col\"></th><th scope=\"col\">&lt;b&gt;</th></tr>\n  \
+             </thead>\n  <tbody>\n    <tr><th scope=\"row\">row</th><td>1</td></tr>\n  </tbody>\n</table>"
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::sync::{Arc, Mutex};
+
+use crate::status::Status;
+use crate::{ctrl_just_pressed, typing, AppState};
+use bevy::prelude::*;
+
+pub struct FilesPlugin;
+
+/// This plugin is responsible for opening files from and saving files to the device.
+/// Desktop builds use the dialogs of the operating system, on the web files are picked with a
+/// file input and saved as downloads. `Ctrl+O` opens a file, the plugins that understand its
+/// kind react to [`FileOpened`]
+impl Plugin for FilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FileDialogs>()
+            .add_event::<FileOpened>()
+            .add_event::<SaveFile>()
+            .add_systems(
+                Update,
+                (
+                    open_file
+                        .run_if(ctrl_just_pressed(KeyCode::KeyO))
+                        .run_if(not(typing)),
+                    save_files.run_if(on_event::<SaveFile>()),
+                    receive_files,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// Kinds of files that can be opened
+const OPENED_EXTENSIONS: [&str; 8] = ["csv", "tsv", "gpl", "json", "pattern", "png", "jpg", "jpeg"];
+
+/// Used for saved files without a usable name
+const DEFAULT_FILE_NAME: &str = "pattern";
+
+/// A file picked by the user
+#[derive(Event, Clone, Debug)]
+pub struct FileOpened {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+impl FileOpened {
+    /// The lowercase extension of the file name
+    pub fn extension(&self) -> String {
+        self.name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+            .unwrap_or_default()
+    }
+
+    /// The file name without its extension
+    pub fn stem(&self) -> &str {
+        self.name
+            .rsplit_once('.')
+            .map_or(self.name.as_str(), |(stem, _)| stem)
+    }
+}
+
+/// Asks the user where to save `bytes`, `name` is suggested without its extension
+#[derive(Event, Clone, Debug)]
+pub struct SaveFile {
+    pub name: String,
+    pub extension: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+/// What the dialogs returned since the last frame. Dialogs finish asynchronously,
+/// so their results are collected here and sent as events by [`receive_files`]
+#[derive(Default)]
+struct Finished {
+    opened: Vec<FileOpened>,
+    statuses: Vec<Status>,
+}
+
+#[derive(Resource, Default)]
+struct FileDialogs(Arc<Mutex<Finished>>);
+
+/// A file name without characters that are not allowed in file names and with `extension`.
+/// Names that are left empty get a default name, so a file is never saved as only an extension
+fn file_name(name: &str, extension: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim().trim_matches('.');
+    let name = if name.is_empty() {
+        DEFAULT_FILE_NAME
+    } else {
+        name
+    };
+    let suffix = format!(".{extension}");
+    if name.to_lowercase().ends_with(&suffix) {
+        name.to_string()
+    } else {
+        name.to_string() + &suffix
+    }
+}
+
+fn open_file(dialogs: Res<FileDialogs>, mut status: EventWriter<Status>) {
+    if let Err(e) = pick_file(dialogs.0.clone()) {
+        status.send(Status::warning(format!("Could not open a file: {e}")));
+    }
+}
+
+fn save_files(
+    dialogs: Res<FileDialogs>,
+    mut save_events: EventReader<SaveFile>,
+    mut status: EventWriter<Status>,
+) {
+    for save in save_events.read() {
+        let name = file_name(&save.name, save.extension);
+        if let Err(e) = write_file(name.clone(), save, dialogs.0.clone()) {
+            status.send(Status::warning(format!("Could not save {name}: {e}")));
+        }
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn pick_file(finished: Arc<Mutex<Finished>>) -> Result<(), String> {
+    let picked = rfd::AsyncFileDialog::new()
+        .add_filter("Supported files", &OPENED_EXTENSIONS)
+        .pick_file();
+    bevy::tasks::IoTaskPool::get()
+        .spawn(async move {
+            if let Some(file) = picked.await {
+                let opened = FileOpened {
+                    name: file.file_name(),
+                    bytes: file.read().await,
+                };
+                finished.lock().unwrap().opened.push(opened);
+            }
+        })
+        .detach();
+    Ok(())
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn write_file(name: String, save: &SaveFile, finished: Arc<Mutex<Finished>>) -> Result<(), String> {
+    let bytes = save.bytes.clone();
+    let picked = rfd::AsyncFileDialog::new()
+        .set_file_name(&name)
+        .add_filter(save.extension, &[save.extension])
+        .save_file();
+    bevy::tasks::IoTaskPool::get()
+        .spawn(async move {
+            let Some(file) = picked.await else {
+                return;
+            };
+            let saved = match file.write(&bytes).await {
+                Ok(()) => Status::info(format!("Saved {}", file.file_name())),
+                Err(e) => Status::warning(format!("Could not save {name}: {e}")),
+            };
+            finished.lock().unwrap().statuses.push(saved);
+        })
+        .detach();
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn pick_file(finished: Arc<Mutex<Finished>>) -> Result<(), String> {
+    web::pick_file(&OPENED_EXTENSIONS, finished).map_err(|e| format!("{e:?}"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_file(name: String, save: &SaveFile, finished: Arc<Mutex<Finished>>) -> Result<(), String> {
+    web::download(&name, &save.bytes).map_err(|e| format!("{e:?}"))?;
+    let downloaded = Status::info(format!("Downloaded {name}"));
+    finished.lock().unwrap().statuses.push(downloaded);
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "linux",
+    target_arch = "wasm32"
+)))]
+fn pick_file(_finished: Arc<Mutex<Finished>>) -> Result<(), String> {
+    Err("files can not be opened on this platform".to_string())
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "linux",
+    target_arch = "wasm32"
+)))]
+fn write_file(
+    _name: String,
+    _save: &SaveFile,
+    _finished: Arc<Mutex<Finished>>,
+) -> Result<(), String> {
+    Err("files can not be saved on this platform".to_string())
+}
+
+fn receive_files(
+    dialogs: Res<FileDialogs>,
+    mut opened_events: EventWriter<FileOpened>,
+    mut status: EventWriter<Status>,
+) {
+    let Ok(mut finished) = dialogs.0.try_lock() else {
+        return;
+    };
+    opened_events.send_batch(finished.opened.drain(..));
+    status.send_batch(finished.statuses.drain(..));
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use std::sync::{Arc, Mutex};
+
+    use super::{FileOpened, Finished};
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen::{JsCast, JsValue};
+
+    fn document() -> Result<web_sys::Document, JsValue> {
+        web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or_else(|| JsValue::from_str("no document"))
+    }
+
+    /// Lets the user pick a file with a file input, its contents arrive in `finished`
+    pub fn pick_file(extensions: &[&str], finished: Arc<Mutex<Finished>>) -> Result<(), JsValue> {
+        let input: web_sys::HtmlInputElement =
+            document()?.create_element("input")?.unchecked_into();
+        input.set_type("file");
+        let accepted: Vec<String> = extensions.iter().map(|e| format!(".{e}")).collect();
+        input.set_accept(&accepted.join(","));
+
+        let picked = input.clone();
+        let on_change = Closure::once(move || {
+            let Some(file) = picked.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                let Ok(buffer) = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await
+                else {
+                    return;
+                };
+                let opened = FileOpened {
+                    name: file.name(),
+                    bytes: js_sys::Uint8Array::new(&buffer).to_vec(),
+                };
+                finished.lock().unwrap().opened.push(opened);
+            });
+        });
+        input.set_onchange(Some(on_change.as_ref().unchecked_ref()));
+        on_change.forget();
+        input.click();
+        Ok(())
+    }
+
+    /// Saves `bytes` as a download named `name`
+    pub fn download(name: &str, bytes: &[u8]) -> Result<(), JsValue> {
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+        let blob = web_sys::Blob::new_with_u8_array_sequence(&parts)?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+        let anchor: web_sys::HtmlAnchorElement = document()?.create_element("a")?.unchecked_into();
+        anchor.set_href(&url);
+        anchor.set_download(name);
+        anchor.click();
+        web_sys::Url::revoke_object_url(&url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_file_names() {
+        assert_eq!(file_name("My pattern", "csv"), "My pattern.csv");
+        assert_eq!(file_name("../a/b:c", "gpl"), "_a_b_c.gpl");
+        assert_eq!(file_name("colors.GPL", "gpl"), "colors.GPL");
+        assert_eq!(file_name("  ", "json"), "pattern.json");
+    }
+}
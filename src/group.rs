@@ -0,0 +1,186 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::selection::Selected;
+use crate::status::Status;
+use crate::table::TableHead;
+use crate::{ctrl_just_pressed, shift_pressed, typing, AppState};
+
+pub struct GroupPlugin;
+
+/// This plugin is responsible for groups of tables, which are selected, moved, copied and deleted
+/// together.
+/// `Ctrl+G` groups the selected tables and `Ctrl+Shift+G` ungroups them, double clicking a grouped
+/// table enters its group so that its tables can be selected on their own
+impl Plugin for GroupPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EnteredGroup>().add_systems(
+            Update,
+            (
+                (
+                    group_selected.run_if(not(shift_pressed)),
+                    ungroup_selected.run_if(shift_pressed),
+                )
+                    .run_if(ctrl_just_pressed(KeyCode::KeyG))
+                    .run_if(not(typing)),
+                cleanup_empty_groups,
+                group_outline,
+            )
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// Tag for the parent of grouped tables, positioned at the top left of the tables
+#[derive(Component)]
+pub struct Group;
+
+/// The group whose tables are selected on their own, entered by double clicking it
+#[derive(Resource, Default)]
+pub struct EnteredGroup(pub Option<Entity>);
+
+/// Puts the tables, given with their positions in the world, into a new group
+pub fn group_tables(cmd: &mut Commands, tables: &[(Entity, Vec3)]) -> Entity {
+    let origin = tables
+        .iter()
+        .map(|(_, translation)| translation.truncate())
+        .reduce(|a, b| Vec2::new(a.x.min(b.x), a.y.max(b.y)))
+        .unwrap_or_default()
+        .extend(0.0);
+    let group = cmd
+        .spawn((
+            Group,
+            SpatialBundle::from_transform(Transform::from_translation(origin)),
+        ))
+        .id();
+    for (id, translation) in tables {
+        cmd.entity(*id)
+            .insert(Transform::from_translation(*translation - origin));
+        cmd.entity(group).add_child(*id);
+    }
+    group
+}
+
+/// Queries to find the groups of tables
+#[derive(SystemParam)]
+pub struct Groups<'w, 's> {
+    parent_q: Query<'w, 's, &'static Parent>,
+    group_q: Query<'w, 's, &'static Children, With<Group>>,
+}
+impl Groups<'_, '_> {
+    /// The group `id` is in, if it is in one
+    pub fn group_of(&self, id: Entity) -> Option<Entity> {
+        self.parent_q
+            .get(id)
+            .ok()
+            .map(Parent::get)
+            .filter(|parent| self.group_q.contains(*parent))
+    }
+
+    /// The tables in `group`
+    pub fn tables(&self, group: Entity) -> Vec<Entity> {
+        self.group_q
+            .get(group)
+            .map(|children| children.to_vec())
+            .unwrap_or_default()
+    }
+}
+
+/// Groups the selected tables, tables that were in other groups are taken out of them
+fn group_selected(
+    mut cmd: Commands,
+    mut entered: ResMut<EnteredGroup>,
+    mut status: EventWriter<Status>,
+    selected_q: Query<(Entity, &GlobalTransform), (With<TableHead>, With<Selected>)>,
+) {
+    let tables: Vec<_> = selected_q
+        .iter()
+        .map(|(id, transform)| (id, transform.translation()))
+        .collect();
+    if tables.len() < 2 {
+        status.send(Status::warning("Select at least two tables to group them"));
+        return;
+    }
+    group_tables(&mut cmd, &tables);
+    entered.0 = None;
+}
+
+/// Dissolves the groups of the selected tables, their tables stay where they are
+fn ungroup_selected(
+    mut cmd: Commands,
+    mut entered: ResMut<EnteredGroup>,
+    selected_q: Query<&Parent, (With<TableHead>, With<Selected>)>,
+    group_q: Query<(&Transform, &Children), (With<Group>, Without<TableHead>)>,
+    mut table_q: Query<&mut Transform, With<TableHead>>,
+) {
+    let mut groups: Vec<Entity> = selected_q.iter().map(Parent::get).collect();
+    groups.sort();
+    groups.dedup();
+    for group in groups {
+        let Ok((group_transform, children)) = group_q.get(group) else {
+            continue;
+        };
+        for child in children {
+            if let Ok(mut transform) = table_q.get_mut(*child) {
+                transform.translation += group_transform.translation;
+            }
+            cmd.entity(*child).remove_parent();
+        }
+        cmd.entity(group).despawn();
+        if entered.0 == Some(group) {
+            entered.0 = None;
+        }
+    }
+}
+
+/// Groups whose tables were all deleted or taken out are removed
+fn cleanup_empty_groups(
+    mut cmd: Commands,
+    group_q: Query<(Entity, Option<&Children>), With<Group>>,
+) {
+    for (id, children) in &group_q {
+        if children.is_none_or(|children| children.is_empty()) {
+            cmd.entity(id).despawn();
+        }
+    }
+}
+
+/// Outlines the groups of the selected tables around all of their tables
+fn group_outline(
+    mut gizmos: Gizmos,
+    entered: Res<EnteredGroup>,
+    group_q: Query<(Entity, &Children), With<Group>>,
+    table_q: Query<(&TableHead, &GlobalTransform, Has<Selected>)>,
+) {
+    for (id, children) in &group_q {
+        let mut any_selected = false;
+        let mut top_left = Vec2::new(f32::INFINITY, f32::NEG_INFINITY);
+        let mut bottom_right = Vec2::new(f32::NEG_INFINITY, f32::INFINITY);
+        for (table_head, transform, selected) in table_q.iter_many(children) {
+            any_selected |= selected;
+            let corner = transform.translation().truncate();
+            let size = table_head.size();
+            top_left = Vec2::new(top_left.x.min(corner.x), top_left.y.max(corner.y));
+            bottom_right = Vec2::new(
+                bottom_right.x.max(corner.x + size.x),
+                bottom_right.y.min(corner.y - size.y),
+            );
+        }
+        if !any_selected && entered.0 != Some(id) {
+            continue;
+        }
+        let color = if entered.0 == Some(id) {
+            Color::srgb(0.9, 0.5, 0.3).with_alpha(0.5)
+        } else {
+            Color::srgb(0.9, 0.5, 0.3)
+        };
+        // Drawn a little outside of the tables, so it does not cover their own outlines
+        let margin = Vec2::splat(4.0);
+        gizmos.rect_2d(
+            (top_left + bottom_right) / 2.0,
+            0.0,
+            (bottom_right - top_left).abs() + margin * 2.0,
+            color,
+        );
+    }
+}
@@ -0,0 +1,497 @@
+use bevy::prelude::*;
+use bevy::window::WindowResized;
+
+use crate::actions::{maintain_actions, Actions};
+use crate::layers::Pickable;
+use crate::player::Tool;
+use crate::table::{make_table, table_at, TableHead};
+use crate::{using_tool, AppState, CanvasSet};
+
+pub struct GuidesPlugin;
+
+/// This plugin is responsible for the rulers along the top and left of the window and the guides
+/// dragged out of them.
+/// Tables snap to guides when they are made or moved, guides are moved with `Tool::Select` and
+/// removed by dropping them back onto a ruler
+impl Plugin for GuidesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Guides>()
+            .init_resource::<DraggedGuide>()
+            .add_systems(OnEnter(AppState::Running), spawn_rulers)
+            .add_systems(
+                Update,
+                (
+                    snap_actions
+                        .in_set(CanvasSet)
+                        .run_if(using_tool(Tool::Table))
+                        .after(maintain_actions)
+                        .before(make_table),
+                    pick_up_guide
+                        .in_set(CanvasSet)
+                        .run_if(using_tool(Tool::Select))
+                        .after(maintain_actions),
+                    (
+                        drag_guide_out_of_ruler,
+                        drag_guide,
+                        update_rulers,
+                        draw_guides,
+                    )
+                        .chain()
+                        .run_if(in_state(AppState::Running)),
+                ),
+            );
+    }
+}
+
+/// Thickness of the rulers in pixels, UI next to them is moved out of their way
+pub const RULER_SIZE: f32 = 20.0;
+/// How close in pixels something has to be to a guide to snap to it
+pub const SNAP_DISTANCE: f32 = 8.0;
+/// Ticks on the rulers are at least this many pixels apart
+const MIN_TICK_SPACING: f32 = 60.0;
+
+const GUIDE_COLOR: Color = Color::srgb(0.2, 0.8, 0.9);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuideAxis {
+    /// A vertical line at some x, dragged out of the left ruler
+    Vertical,
+    /// A horizontal line at some y, dragged out of the top ruler
+    Horizontal,
+}
+
+/// Positions of the guides on the canvas
+#[derive(Resource, Default, Debug)]
+pub struct Guides {
+    pub vertical: Vec<f32>,
+    pub horizontal: Vec<f32>,
+}
+impl Guides {
+    fn of(&mut self, axis: GuideAxis) -> &mut Vec<f32> {
+        match axis {
+            GuideAxis::Vertical => &mut self.vertical,
+            GuideAxis::Horizontal => &mut self.horizontal,
+        }
+    }
+
+    /// How far to move something with edges at `xs` and `ys` so that the edge closest to a guide
+    /// lies on it, as long as it is closer than `distance`
+    pub fn snap_offset(&self, xs: &[f32], ys: &[f32], distance: f32) -> Vec2 {
+        let closest = |guides: &[f32], edges: &[f32]| {
+            guides
+                .iter()
+                .flat_map(|guide| edges.iter().map(move |edge| guide - edge))
+                .filter(|offset| offset.abs() < distance)
+                .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+                .unwrap_or(0.0)
+        };
+        Vec2::new(closest(&self.vertical, xs), closest(&self.horizontal, ys))
+    }
+
+    /// `pos` moved onto the guides closer than `distance`
+    pub fn snap(&self, pos: Vec2, distance: f32) -> Vec2 {
+        pos + self.snap_offset(&[pos.x], &[pos.y], distance)
+    }
+}
+
+/// The guide that is being dragged, it is not part of [`Guides`] until it is dropped
+#[derive(Resource, Default, Debug)]
+struct DraggedGuide(Option<(GuideAxis, f32)>);
+
+/// The distance in world units that looks like `pixels` on screen
+pub fn world_distance(pixels: f32, projection: &OrthographicProjection) -> f32 {
+    pixels * projection.scale
+}
+
+#[derive(Component)]
+struct Ruler(GuideAxis);
+
+/// Tag for the tick lines of a ruler, they are moved along when the camera moves and hidden when
+/// fewer ticks are needed
+#[derive(Component)]
+struct RulerTick;
+
+/// Tag for the labels of the ticks of a ruler, they are reused like [`RulerTick`]s
+#[derive(Component)]
+struct RulerLabel;
+
+fn spawn_rulers(mut cmd: Commands) {
+    let background = Color::srgb(0.12, 0.12, 0.14);
+    for (axis, style) in [
+        (
+            GuideAxis::Horizontal,
+            Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(RULER_SIZE),
+                right: Val::Px(0.),
+                top: Val::Px(0.),
+                height: Val::Px(RULER_SIZE),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+        ),
+        (
+            GuideAxis::Vertical,
+            Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(RULER_SIZE),
+                bottom: Val::Px(0.),
+                left: Val::Px(0.),
+                width: Val::Px(RULER_SIZE),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+        ),
+    ] {
+        cmd.spawn((
+            NodeBundle {
+                style,
+                background_color: background.into(),
+                ..default()
+            },
+            Interaction::None,
+            Ruler(axis),
+        ));
+    }
+}
+
+/// The distance between ticks at `scale`, so they are at least [`MIN_TICK_SPACING`] pixels apart.
+/// Tick steps are 1, 2 or 5 times a power of ten
+fn tick_step(scale: f32) -> f32 {
+    let min_step = MIN_TICK_SPACING * scale;
+    let magnitude = 10f32.powf(min_step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= min_step)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// The world positions of the ticks of a ruler that shows `world_min` to `world_max`
+fn ticks(world_min: f32, world_max: f32, step: f32) -> impl Iterator<Item = f32> {
+    let first = (world_min / step).ceil() as i64;
+    let last = (world_max / step).floor() as i64;
+    (first..=last).map(move |i| i as f32 * step)
+}
+
+/// The label of a tick, with as many decimals as the step between ticks needs
+fn tick_label(world: f32, step: f32) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    // Avoids labeling the tick at zero as "-0"
+    let world = if world == 0.0 { 0.0 } else { world };
+    format!("{world:.decimals$}")
+}
+
+/// Moves the ticks of the rulers whenever the camera or window changes. Ticks are reused, new ones
+/// are only spawned when more are needed than ever before and unused ones are hidden
+fn update_rulers(
+    mut cmd: Commands,
+    camera_q: Query<
+        (&Camera, &GlobalTransform, &OrthographicProjection),
+        Or<(Changed<GlobalTransform>, Changed<OrthographicProjection>)>,
+    >,
+    mut resized: EventReader<WindowResized>,
+    all_cameras: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    ruler_q: Query<(Entity, &Ruler, Option<&Children>)>,
+    mut tick_q: Query<&mut Style, (With<RulerTick>, Without<RulerLabel>)>,
+    mut label_q: Query<(&mut Style, &mut Text), With<RulerLabel>>,
+) {
+    if camera_q.is_empty() && resized.read().count() == 0 {
+        return;
+    }
+    let Ok((camera, camera_transform, projection)) = all_cameras.get_single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    let Some(top_left) = camera.viewport_to_world_2d(camera_transform, Vec2::ZERO) else {
+        return;
+    };
+    let Some(bottom_right) = camera.viewport_to_world_2d(camera_transform, viewport) else {
+        return;
+    };
+    let text_style = TextStyle {
+        font_size: 11.0,
+        color: Color::srgb(0.7, 0.7, 0.7),
+        ..default()
+    };
+    let step = tick_step(projection.scale);
+
+    for (id, Ruler(axis), children) in &ruler_q {
+        let children = children.map(|children| &children[..]).unwrap_or_default();
+        let ticks_in_pool: Vec<Entity> = children
+            .iter()
+            .copied()
+            .filter(|child| tick_q.contains(*child))
+            .collect();
+        let labels_in_pool: Vec<Entity> = children
+            .iter()
+            .copied()
+            .filter(|child| label_q.contains(*child))
+            .collect();
+        let mut pooled_ticks = ticks_in_pool.into_iter();
+        let mut pooled_labels = labels_in_pool.into_iter();
+
+        let (world_min, world_max) = match axis {
+            GuideAxis::Horizontal => (top_left.x, bottom_right.x),
+            GuideAxis::Vertical => (bottom_right.y, top_left.y),
+        };
+        for world in ticks(world_min, world_max, step) {
+            let position = match axis {
+                GuideAxis::Horizontal => Vec2::new(world, top_left.y),
+                GuideAxis::Vertical => Vec2::new(top_left.x, world),
+            };
+            let Some(screen) = camera.world_to_viewport(camera_transform, position.extend(0.0))
+            else {
+                continue;
+            };
+            // Rulers start after the corner they leave free
+            let (line, label) = match axis {
+                GuideAxis::Horizontal => (
+                    Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(screen.x - RULER_SIZE),
+                        bottom: Val::Px(0.),
+                        width: Val::Px(1.),
+                        height: Val::Px(RULER_SIZE / 2.),
+                        ..default()
+                    },
+                    Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(screen.x - RULER_SIZE + 3.),
+                        top: Val::Px(0.),
+                        ..default()
+                    },
+                ),
+                GuideAxis::Vertical => (
+                    Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(screen.y - RULER_SIZE),
+                        right: Val::Px(0.),
+                        width: Val::Px(RULER_SIZE / 2.),
+                        height: Val::Px(1.),
+                        ..default()
+                    },
+                    Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(screen.y - RULER_SIZE + 2.),
+                        left: Val::Px(1.),
+                        ..default()
+                    },
+                ),
+            };
+            let value = tick_label(world, step);
+
+            match pooled_ticks
+                .next()
+                .and_then(|tick| tick_q.get_mut(tick).ok())
+            {
+                Some(mut style) => *style = line,
+                None => {
+                    let tick = cmd
+                        .spawn((
+                            NodeBundle {
+                                style: line,
+                                background_color: text_style.color.into(),
+                                ..default()
+                            },
+                            RulerTick,
+                        ))
+                        .id();
+                    cmd.entity(id).add_child(tick);
+                }
+            }
+            match pooled_labels
+                .next()
+                .and_then(|label| label_q.get_mut(label).ok())
+            {
+                Some((mut style, mut text)) => {
+                    *style = label;
+                    if text.sections[0].value != value {
+                        text.sections[0].value = value;
+                    }
+                }
+                None => {
+                    let label = cmd
+                        .spawn((
+                            TextBundle::from_section(value, text_style.clone()).with_style(label),
+                            RulerLabel,
+                        ))
+                        .id();
+                    cmd.entity(id).add_child(label);
+                }
+            }
+        }
+
+        for tick in pooled_ticks {
+            if let Ok(mut style) = tick_q.get_mut(tick) {
+                style.display = Display::None;
+            }
+        }
+        for label in pooled_labels {
+            if let Ok((mut style, _)) = label_q.get_mut(label) {
+                style.display = Display::None;
+            }
+        }
+    }
+}
+
+/// Pressing on a ruler starts dragging a new guide out of it
+fn drag_guide_out_of_ruler(
+    ruler_q: Query<(&Interaction, &Ruler), Changed<Interaction>>,
+    mut dragged: ResMut<DraggedGuide>,
+) {
+    for (interaction, Ruler(axis)) in &ruler_q {
+        if *interaction == Interaction::Pressed {
+            dragged.0 = Some((*axis, 0.0));
+        }
+    }
+}
+
+/// Left clicking close to a guide, but not on a table, picks it up again
+fn pick_up_guide(
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    actions: Res<Actions>,
+    projection_q: Query<&OrthographicProjection>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform), Pickable>,
+    mut guides: ResMut<Guides>,
+    mut dragged: ResMut<DraggedGuide>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Left)
+        || table_at(&table_head_q, actions.from).is_some()
+    {
+        return;
+    }
+    let distance = world_distance(SNAP_DISTANCE, projection_q.single());
+    let pos = actions.from;
+    for (axis, at) in [(GuideAxis::Vertical, pos.x), (GuideAxis::Horizontal, pos.y)] {
+        let guides = guides.of(axis);
+        if let Some(i) = guides
+            .iter()
+            .position(|guide| (guide - at).abs() < distance)
+        {
+            dragged.0 = Some((axis, guides.remove(i)));
+            return;
+        }
+    }
+}
+
+/// The dragged guide follows the cursor and is dropped when the mouse is released,
+/// dropping it onto a ruler removes it
+fn drag_guide(
+    mouse_q: crate::MousePosQueries,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    mut guides: ResMut<Guides>,
+    mut dragged: ResMut<DraggedGuide>,
+) {
+    let Some((axis, at)) = &mut dragged.0 else {
+        return;
+    };
+    let pos = mouse_q.mouse_pos();
+    if !pos.is_nan() {
+        *at = match axis {
+            GuideAxis::Vertical => pos.x,
+            GuideAxis::Horizontal => pos.y,
+        }
+        .round();
+    }
+    if mouse_buttons.pressed(MouseButton::Left) {
+        return;
+    }
+
+    let on_ruler = windows
+        .single()
+        .cursor_position()
+        .is_none_or(|cursor| cursor.min_element() < RULER_SIZE);
+    if !on_ruler {
+        guides.of(*axis).push(*at);
+    }
+    dragged.0 = None;
+}
+
+fn draw_guides(
+    mut gizmos: Gizmos,
+    guides: Res<Guides>,
+    dragged: Res<DraggedGuide>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    let (Some(top_left), Some(bottom_right)) = (
+        camera.viewport_to_world_2d(camera_transform, Vec2::ZERO),
+        camera.viewport_to_world_2d(camera_transform, viewport),
+    ) else {
+        return;
+    };
+
+    let mut line = |axis: GuideAxis, at: f32, color: Color| match axis {
+        GuideAxis::Vertical => gizmos.line_2d(
+            Vec2::new(at, top_left.y),
+            Vec2::new(at, bottom_right.y),
+            color,
+        ),
+        GuideAxis::Horizontal => gizmos.line_2d(
+            Vec2::new(top_left.x, at),
+            Vec2::new(bottom_right.x, at),
+            color,
+        ),
+    };
+    for x in &guides.vertical {
+        line(GuideAxis::Vertical, *x, GUIDE_COLOR);
+    }
+    for y in &guides.horizontal {
+        line(GuideAxis::Horizontal, *y, GUIDE_COLOR);
+    }
+    if let Some((axis, at)) = dragged.0 {
+        line(axis, at, GUIDE_COLOR.with_alpha(0.6));
+    }
+}
+
+/// Tables are made from and to the guides close to the cursor
+fn snap_actions(
+    mut actions: ResMut<Actions>,
+    guides: Res<Guides>,
+    projection_q: Query<&OrthographicProjection>,
+) {
+    let distance = world_distance(SNAP_DISTANCE, projection_q.single());
+    let from = guides.snap(actions.from, distance);
+    let to = guides.snap(actions.to, distance);
+    if actions.from != from || actions.to != to {
+        actions.from = from;
+        actions.to = to;
+    }
+}
+
+/// How far to move tables whose outlines are `bounds`, as `(top left, bottom right)`, so they
+/// snap to the guides
+pub fn snap_bounds(
+    guides: &Guides,
+    bounds: (Vec2, Vec2),
+    projection: &OrthographicProjection,
+) -> Vec2 {
+    let (top_left, bottom_right) = bounds;
+    guides.snap_offset(
+        &[top_left.x, bottom_right.x],
+        &[top_left.y, bottom_right.y],
+        world_distance(SNAP_DISTANCE, projection),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_have_the_precision_of_the_step() {
+        assert_eq!(tick_label(300.0, 100.0), "300");
+        assert_eq!(tick_label(0.6000001, 0.2), "0.6");
+        assert_eq!(tick_label(-0.05, 0.05), "-0.05");
+        assert_eq!(tick_label(-0.0, 1.0), "0");
+    }
+}
@@ -0,0 +1,580 @@
+use std::path::Path;
+
+use crate::files::FileOpened;
+use crate::menu::text_button;
+use crate::paint::PaintMaterials;
+use crate::placed_image::{spawn_placed_image, texture_of};
+use crate::player::User;
+use crate::selection::{Selected, Selection};
+use crate::status::Status;
+use crate::table::{spawn_table, CellContents, TableHead};
+use crate::AppState;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use image::imageops::FilterType;
+
+pub struct ImageImportPlugin;
+
+/// This plugin is responsible for turning pictures into patterns.
+/// Dropping an image file onto the window, or opening one with `Ctrl+O`, opens a dialog previewing the image downsampled to a
+/// grid of cells and quantized to a few colors, which can then be imported as a table.
+/// The image can also be placed on the canvas as it is, see `PlacedImagePlugin`
+impl Plugin for ImageImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                open_import_dialog
+                    .run_if(on_event::<FileDragAndDrop>().or_else(on_event::<FileOpened>())),
+                update_import_preview.run_if(resource_changed::<ImportPreview>),
+                import_dialog_buttons.run_if(resource_exists::<ImportPreview>),
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// How the colors of an image are reduced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quantizer {
+    MedianCut,
+    KMeans,
+}
+
+/// The image being imported, while this exists the import dialog is open
+#[derive(Resource)]
+struct ImportPreview {
+    /// Name of the image file
+    name: String,
+    /// Contents of the image file
+    bytes: Vec<u8>,
+    source: image::RgbaImage,
+    /// Number of columns and rows of the imported table
+    size: UVec2,
+    /// Number of colors the image is quantized to
+    colors: u32,
+    quantizer: Quantizer,
+    preview: Handle<Image>,
+    /// Quantized color of every cell, by row then column, transparent cells stay empty
+    cells: Vec<Option<Color>>,
+}
+
+#[derive(Component)]
+struct ImportDialog;
+
+#[derive(Component)]
+struct ImportPreviewImage;
+
+#[derive(Component, Clone, Copy)]
+enum ImportLabel {
+    Columns,
+    Rows,
+    Colors,
+    Quantizer,
+}
+
+#[derive(Component, Clone, Copy)]
+enum ImportButton {
+    Columns(i32),
+    Rows(i32),
+    Colors(i32),
+    Quantizer,
+    Import,
+    Place,
+    Cancel,
+}
+
+const PREVIEW_WIDTH: f32 = 256.0;
+
+/// Extensions of the image formats that can be imported
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// Downsampled pixels that are less opaque than this become empty cells
+const MIN_ALPHA: u8 = 128;
+
+/// Opens the import dialog for the last image file that was dropped onto the window or opened
+fn open_import_dialog(
+    mut cmd: Commands,
+    mut drop_events: EventReader<FileDragAndDrop>,
+    mut opened_events: EventReader<FileOpened>,
+    mut images: ResMut<Assets<Image>>,
+    mut status: EventWriter<Status>,
+    dialog_q: Query<Entity, With<ImportDialog>>,
+) {
+    let dropped = drop_events
+        .read()
+        .filter_map(|event| match event {
+            FileDragAndDrop::DroppedFile { path_buf, .. } if is_image_file(path_buf) => {
+                Some(path_buf)
+            }
+            _ => None,
+        })
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            (name.into_owned(), std::fs::read(path))
+        });
+    let opened = opened_events
+        .read()
+        .filter(|file| is_image_extension(&file.extension()))
+        .map(|file| (file.name.clone(), Ok(file.bytes.clone())));
+    let Some((name, bytes)) = dropped.chain(opened).last() else {
+        return;
+    };
+    let decoded = bytes.map_err(|e| e.to_string()).and_then(|bytes| {
+        let source = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+        Ok((bytes, source.to_rgba8()))
+    });
+    let (bytes, source) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            status.send(Status::warning(format!(
+                "Could not open {name} as an image: {e}"
+            )));
+            return;
+        }
+    };
+
+    let columns = source.width().min(32);
+    let rows = ((columns * source.height()) as f32 / source.width() as f32).round() as u32;
+    cmd.insert_resource(ImportPreview {
+        name,
+        bytes,
+        source,
+        size: UVec2::new(columns, rows.max(1)),
+        colors: 8,
+        quantizer: Quantizer::MedianCut,
+        preview: images.add(Image::default()),
+        cells: vec![],
+    });
+
+    for dialog in &dialog_q {
+        cmd.entity(dialog).despawn_recursive();
+    }
+    spawn_import_dialog(&mut cmd);
+}
+
+/// Whether `path` has the extension of an image format that can be imported
+fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(is_image_extension)
+}
+
+fn is_image_extension(extension: &str) -> bool {
+    IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+}
+
+fn spawn_import_dialog(cmd: &mut Commands) {
+    let text_style = TextStyle {
+        font_size: 16.0,
+        color: Color::WHITE,
+        ..default()
+    };
+    let row_style = Style {
+        flex_direction: FlexDirection::Row,
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::SpaceBetween,
+        column_gap: Val::Px(5.),
+        ..default()
+    };
+
+    cmd.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(50.),
+                right: Val::Px(50.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(5.),
+                padding: UiRect::all(Val::Px(10.)),
+                ..default()
+            },
+            background_color: Color::linear_rgb(0.1, 0.1, 0.1).into(),
+            ..default()
+        },
+        // Keeps clicks on the dialog away from the canvas
+        Interaction::None,
+        ImportDialog,
+    ))
+    .with_children(|dialog| {
+        dialog.spawn(TextBundle::from_section("Import image", text_style.clone()));
+        dialog.spawn((
+            ImageBundle {
+                style: Style {
+                    width: Val::Px(PREVIEW_WIDTH),
+                    ..default()
+                },
+                ..default()
+            },
+            ImportPreviewImage,
+        ));
+        for (label, less, more) in [
+            (
+                ImportLabel::Columns,
+                ImportButton::Columns(-1),
+                ImportButton::Columns(1),
+            ),
+            (
+                ImportLabel::Rows,
+                ImportButton::Rows(-1),
+                ImportButton::Rows(1),
+            ),
+            (
+                ImportLabel::Colors,
+                ImportButton::Colors(-1),
+                ImportButton::Colors(1),
+            ),
+        ] {
+            dialog
+                .spawn(NodeBundle {
+                    style: row_style.clone(),
+                    ..default()
+                })
+                .with_children(|row| {
+                    row.spawn((TextBundle::from_section("", text_style.clone()), label));
+                    text_button(row, "-", less);
+                    text_button(row, "+", more);
+                });
+        }
+        dialog
+            .spawn(NodeBundle {
+                style: row_style.clone(),
+                ..default()
+            })
+            .with_children(|row| {
+                row.spawn((
+                    TextBundle::from_section("", text_style.clone()),
+                    ImportLabel::Quantizer,
+                ));
+                text_button(row, "Change", ImportButton::Quantizer);
+            });
+        dialog
+            .spawn(NodeBundle {
+                style: row_style,
+                ..default()
+            })
+            .with_children(|row| {
+                text_button(row, "Import", ImportButton::Import);
+                text_button(row, "Place image", ImportButton::Place);
+                text_button(row, "Cancel", ImportButton::Cancel);
+            });
+    });
+}
+
+fn update_import_preview(
+    mut preview: ResMut<ImportPreview>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_q: Query<(&mut UiImage, &mut Style), With<ImportPreviewImage>>,
+    mut label_q: Query<(&mut Text, &ImportLabel)>,
+) {
+    let downsampled = image::imageops::resize(
+        &preview.source,
+        preview.size.x,
+        preview.size.y,
+        FilterType::Triangle,
+    );
+    let pixels: Vec<Option<Vec3>> = downsampled
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            (a >= MIN_ALPHA).then(|| Vec3::new(r as f32, g as f32, b as f32) / 255.0)
+        })
+        .collect();
+    // Transparent pixels do not take part in choosing the colors
+    let opaque: Vec<Vec3> = pixels.iter().flatten().copied().collect();
+    let palette = match preview.quantizer {
+        Quantizer::MedianCut => median_cut(&opaque, preview.colors as usize),
+        Quantizer::KMeans => k_means(&opaque, preview.colors as usize),
+    };
+    let cells: Vec<Option<Color>> = pixels
+        .iter()
+        .map(|pixel| {
+            pixel.map(|pixel| {
+                let nearest = nearest_color(&palette, pixel);
+                Color::srgb(nearest.x, nearest.y, nearest.z)
+            })
+        })
+        .collect();
+
+    let mut image = Image::new(
+        Extent3d {
+            width: preview.size.x,
+            height: preview.size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        cells
+            .iter()
+            .flat_map(|color| color.unwrap_or(Color::NONE).to_srgba().to_u8_array())
+            .collect(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::nearest();
+    images.insert(&preview.preview, image);
+
+    for (mut ui_image, mut style) in &mut image_q {
+        ui_image.texture = preview.preview.clone();
+        style.height = Val::Px(PREVIEW_WIDTH * preview.size.y as f32 / preview.size.x as f32);
+    }
+    for (mut text, label) in &mut label_q {
+        text.sections[0].value = match label {
+            ImportLabel::Columns => format!("Columns: {}", preview.size.x),
+            ImportLabel::Rows => format!("Rows: {}", preview.size.y),
+            ImportLabel::Colors => format!("Colors: {}", preview.colors),
+            ImportLabel::Quantizer => format!("{:?}", preview.quantizer),
+        };
+    }
+    preview.bypass_change_detection().cells = cells;
+}
+
+/// The open import dialog with the preview it shows and its buttons
+#[derive(SystemParam)]
+struct ImportDialogQueries<'w, 's> {
+    preview: ResMut<'w, ImportPreview>,
+    button_q: Query<'w, 's, (&'static Interaction, &'static ImportButton), Changed<Interaction>>,
+    dialog_q: Query<'w, 's, Entity, With<ImportDialog>>,
+}
+
+fn import_dialog_buttons(
+    mut cmd: Commands,
+    dialog: ImportDialogQueries,
+    selection: Selection,
+    camera_q: Query<&GlobalTransform, With<Camera>>,
+    user_q: Query<&User>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: PaintMaterials,
+) {
+    let ImportDialogQueries {
+        mut preview,
+        button_q,
+        dialog_q,
+    } = dialog;
+    let step = |value: u32, by: i32, max: u32| value.saturating_add_signed(by).clamp(1, max);
+
+    for (interaction, button) in &button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            ImportButton::Columns(by) => {
+                preview.size.x = step(preview.size.x, by, preview.source.width());
+            }
+            ImportButton::Rows(by) => {
+                preview.size.y = step(preview.size.y, by, preview.source.height());
+            }
+            ImportButton::Colors(by) => preview.colors = step(preview.colors, by, 64),
+            ImportButton::Quantizer => {
+                preview.quantizer = match preview.quantizer {
+                    Quantizer::MedianCut => Quantizer::KMeans,
+                    Quantizer::KMeans => Quantizer::MedianCut,
+                }
+            }
+            ImportButton::Import => {
+                let config = &user_q.single().current_config;
+                let size = preview.size;
+                let table_size = size.as_vec2() * config.cell_dimensions;
+                let center = camera_q
+                    .get_single()
+                    .map(|transform| transform.translation().truncate())
+                    .unwrap_or_default();
+                let top_left = center + table_size * Vec2::new(-0.5, 0.5);
+
+                selection.clear(&mut cmd);
+                let table_head = TableHead::new(0, 0);
+                let id = spawn_table(
+                    &mut cmd,
+                    top_left.extend(0.0),
+                    &table_head,
+                    size,
+                    config,
+                    |table_head, cell| {
+                        let mut contents = CellContents::empty(table_head, &cell, config);
+                        let index = (cell.row * size.x + cell.column) as usize;
+                        if let Some(Some(color)) = preview.cells.get(index) {
+                            contents.bg_color = materials.get_or_add(*color);
+                        }
+                        contents
+                    },
+                );
+                cmd.entity(id).insert(Selected);
+                close_import_dialog(&mut cmd, &dialog_q);
+                return;
+            }
+            ImportButton::Place => {
+                let (width, height) = preview.source.dimensions();
+                let texture = images.add(texture_of(&preview.source));
+                let center = camera_q
+                    .get_single()
+                    .map(|transform| transform.translation().truncate())
+                    .unwrap_or_default();
+                spawn_placed_image(
+                    &mut cmd,
+                    preview.name.clone(),
+                    preview.bytes.clone(),
+                    texture,
+                    UVec2::new(width, height),
+                    center,
+                );
+                close_import_dialog(&mut cmd, &dialog_q);
+                return;
+            }
+            ImportButton::Cancel => {
+                close_import_dialog(&mut cmd, &dialog_q);
+                return;
+            }
+        }
+    }
+}
+
+fn close_import_dialog(cmd: &mut Commands, dialog_q: &Query<Entity, With<ImportDialog>>) {
+    cmd.remove_resource::<ImportPreview>();
+    for dialog in dialog_q {
+        cmd.entity(dialog).despawn_recursive();
+    }
+}
+
+fn nearest_color(palette: &[Vec3], color: Vec3) -> Vec3 {
+    palette
+        .iter()
+        .copied()
+        .min_by(|a, b| {
+            a.distance_squared(color)
+                .total_cmp(&b.distance_squared(color))
+        })
+        .unwrap_or(color)
+}
+
+fn mean(colors: &[Vec3]) -> Vec3 {
+    colors.iter().sum::<Vec3>() / colors.len().max(1) as f32
+}
+
+/// Splits the colors at the median of their widest channel until there are `count` groups,
+/// returning the mean color of each group
+pub fn median_cut(colors: &[Vec3], count: usize) -> Vec<Vec3> {
+    let mut groups = vec![colors.to_vec()];
+
+    while groups.len() < count {
+        // The group with the widest range in any channel is split next
+        let widest = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(i, group)| {
+                let min = group.iter().copied().fold(Vec3::INFINITY, Vec3::min);
+                let max = group.iter().copied().fold(Vec3::NEG_INFINITY, Vec3::max);
+                let range = max - min;
+                let channel = (0..3)
+                    .max_by(|a, b| range[*a].total_cmp(&range[*b]))
+                    .unwrap_or(0);
+                (i, channel, range[channel])
+            })
+            .max_by(|(.., a), (.., b)| a.total_cmp(b));
+        let Some((i, channel, range)) = widest else {
+            break;
+        };
+        if range <= 0.0 {
+            break;
+        }
+
+        let mut group = groups.swap_remove(i);
+        group.sort_by(|a, b| a[channel].total_cmp(&b[channel]));
+        let upper = group.split_off(group.len() / 2);
+        groups.push(group);
+        groups.push(upper);
+    }
+
+    groups.iter().map(|group| mean(group)).collect()
+}
+
+/// Clusters the colors into `count` groups around their means, starting from [`median_cut`]
+pub fn k_means(colors: &[Vec3], count: usize) -> Vec<Vec3> {
+    let mut means = median_cut(colors, count);
+
+    for _ in 0..16 {
+        let mut clusters = vec![vec![]; means.len()];
+        for color in colors {
+            let nearest = means
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    a.distance_squared(*color)
+                        .total_cmp(&b.distance_squared(*color))
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            clusters[nearest].push(*color);
+        }
+
+        let mut moved = false;
+        for (current, cluster) in means.iter_mut().zip(&clusters) {
+            // Empty clusters keep their mean
+            if cluster.is_empty() {
+                continue;
+            }
+            let new = mean(cluster);
+            moved |= new.distance_squared(*current) > 1e-8;
+            *current = new;
+        }
+        if !moved {
+            break;
+        }
+    }
+    means
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Vec3 = Vec3::new(0.9, 0.1, 0.1);
+    const BLUE: Vec3 = Vec3::new(0.1, 0.2, 0.8);
+
+    /// A two color image of 40 pixels, `red` of them red, with slightly varying shades of each
+    /// color
+    fn two_colors(red: usize) -> Vec<Vec3> {
+        (0..40)
+            .map(|i| {
+                let shade = Vec3::splat((i % 5) as f32 * 0.01);
+                if i < red {
+                    RED + shade
+                } else {
+                    BLUE - shade
+                }
+            })
+            .collect()
+    }
+
+    fn assert_finds_both(palette: &[Vec3]) {
+        assert_eq!(palette.len(), 2);
+        for color in [RED, BLUE] {
+            assert!(
+                palette.iter().any(|found| found.distance(color) < 0.05),
+                "{color} not in {palette:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn median_cut_two_colors() {
+        assert_finds_both(&median_cut(&two_colors(20), 2));
+    }
+
+    #[test]
+    fn k_means_two_colors() {
+        assert_finds_both(&k_means(&two_colors(20), 2));
+        // Median cut splits at the median, so it mixes unevenly used colors, which k-means fixes
+        assert_finds_both(&k_means(&two_colors(15), 2));
+    }
+
+    #[test]
+    fn no_more_colors_than_the_image_has() {
+        let colors = [RED, RED, BLUE];
+        assert_eq!(median_cut(&colors, 8).len(), 2);
+        assert_eq!(k_means(&colors, 8).len(), 2);
+    }
+}
@@ -0,0 +1,479 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::guides::RULER_SIZE;
+use crate::line::Polyline;
+use crate::menu::text_button;
+use crate::pen::Stroke;
+use crate::placed_image::PlacedImage;
+use crate::selection::Selected;
+use crate::shape::Shape;
+use crate::status::Status;
+use crate::sticky_note::StickyNote;
+use crate::table::TableHead;
+use crate::text_box::TextBox;
+use crate::{ctrl_just_pressed, shift_pressed, typing, AppState};
+
+pub struct LayersPlugin;
+
+/// This plugin is responsible for the layers tables are on and the panel listing them.
+/// Every table belongs to a layer, tables on hidden or locked layers can not be picked on the canvas.
+/// Layers are stacked in the order of the panel, dragging a layer onto another moves it there.
+/// Within a layer, `Ctrl+]` and `Ctrl+[` bring the selected tables forward and backward,
+/// with `Shift` to the front and back
+impl Plugin for LayersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Layers>()
+            .init_resource::<DraggedLayer>()
+            .add_systems(
+                Update,
+                (
+                    assign_layer,
+                    build_layers_panel,
+                    (
+                        layer_buttons,
+                        drop_layer,
+                        reorder_tables
+                            .run_if(
+                                ctrl_just_pressed(KeyCode::BracketLeft)
+                                    .or_else(ctrl_just_pressed(KeyCode::BracketRight)),
+                            )
+                            .run_if(not(typing)),
+                    ),
+                    (apply_layers, stack_tables),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub id: u32,
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+}
+
+/// The layers, from the bottom to the top
+#[derive(Resource, Debug)]
+pub struct Layers {
+    pub layers: Vec<Layer>,
+    /// New tables are put onto this layer
+    pub active: u32,
+    next_id: u32,
+}
+impl Default for Layers {
+    fn default() -> Self {
+        let mut layers = Self {
+            layers: vec![],
+            active: 0,
+            next_id: 0,
+        };
+        layers.add();
+        layers
+    }
+}
+impl Layers {
+    /// Adds a layer on top and makes it the active one
+    pub fn add(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.layers.push(Layer {
+            id,
+            name: format!("Layer {}", id + 1),
+            visible: true,
+            locked: false,
+        });
+        self.active = id;
+        id
+    }
+
+    /// Position of the layer `id` from the bottom
+    pub fn index(&self, id: u32) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.id == id)
+    }
+
+    /// Replaces the layers with saved ones, keeping `active` active if it is one of them.
+    /// Without any saved layers a new one is added
+    pub fn replace(&mut self, layers: Vec<Layer>, active: u32) {
+        self.next_id = layers.iter().map(|layer| layer.id + 1).max().unwrap_or(0);
+        self.layers = layers;
+        match self.layers.first() {
+            None => {
+                self.add();
+            }
+            Some(bottom) if self.get(active).is_none() => self.active = bottom.id,
+            Some(_) => self.active = active,
+        }
+    }
+}
+
+/// Everything on the canvas that is put onto a layer and stacked
+pub type Stacked = Or<(
+    With<TableHead>,
+    With<Shape>,
+    With<Stroke>,
+    With<TextBox>,
+    With<StickyNote>,
+    With<PlacedImage>,
+    With<Polyline>,
+)>;
+
+/// The layer a table belongs to
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InLayer(pub u32);
+
+/// Tag for tables on locked layers, they are shown but can not be picked or changed on the canvas
+#[derive(Component)]
+pub struct Locked;
+
+/// Tag for tables on hidden layers, they are neither shown nor picked on the canvas
+#[derive(Component)]
+pub struct Hidden;
+
+/// Filter for tables that can be picked on the canvas, because their layer is shown and unlocked
+pub type Pickable = (Without<Locked>, Without<Hidden>);
+
+#[derive(Component)]
+struct LayersPanel;
+
+/// A row of the panel, showing the layer with this id
+#[derive(Component)]
+struct LayerRow(u32);
+
+#[derive(Component, Clone, Copy)]
+enum LayerButton {
+    /// Makes the layer active, or starts dragging it
+    Activate(u32),
+    ToggleVisible(u32),
+    ToggleLocked(u32),
+    Add,
+    Delete,
+    /// Moves the selected tables onto the active layer
+    MoveSelected,
+}
+
+/// The layer that is being dragged to another place in the panel
+#[derive(Resource, Default)]
+struct DraggedLayer(Option<u32>);
+
+/// Puts tables that do not have a layer yet onto the active layer
+fn assign_layer(
+    mut cmd: Commands,
+    layers: Res<Layers>,
+    table_q: Query<Entity, (Stacked, Without<InLayer>)>,
+) {
+    for id in &table_q {
+        cmd.entity(id).insert(InLayer(layers.active));
+    }
+}
+
+fn build_layers_panel(
+    mut cmd: Commands,
+    layers: Res<Layers>,
+    panel_q: Query<Entity, With<LayersPanel>>,
+) {
+    if !layers.is_changed() {
+        return;
+    }
+    for panel in &panel_q {
+        cmd.entity(panel).despawn_recursive();
+    }
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        color: Color::WHITE,
+        ..default()
+    };
+    let row_style = Style {
+        flex_direction: FlexDirection::Row,
+        align_items: AlignItems::Center,
+        column_gap: Val::Px(5.),
+        ..default()
+    };
+    cmd.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(RULER_SIZE + 5.),
+                right: Val::Px(5.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(3.),
+                padding: UiRect::all(Val::Px(5.)),
+                ..default()
+            },
+            background_color: Color::linear_rgb(0.1, 0.1, 0.1).into(),
+            ..default()
+        },
+        // Keeps clicks on the panel away from the canvas
+        Interaction::None,
+        LayersPanel,
+    ))
+    .with_children(|panel| {
+        panel.spawn(TextBundle::from_section("Layers", text_style.clone()));
+        // The top layer is listed first
+        for layer in layers.layers.iter().rev() {
+            let background = if layer.id == layers.active {
+                Color::linear_rgb(0.2, 0.2, 0.3)
+            } else {
+                Color::NONE
+            };
+            panel
+                .spawn((
+                    NodeBundle {
+                        style: row_style.clone(),
+                        background_color: background.into(),
+                        ..default()
+                    },
+                    LayerRow(layer.id),
+                ))
+                .with_children(|row| {
+                    text_button(row, &layer.name, LayerButton::Activate(layer.id));
+                    let visible = if layer.visible { "Hide" } else { "Show" };
+                    text_button(row, visible, LayerButton::ToggleVisible(layer.id));
+                    let locked = if layer.locked { "Unlock" } else { "Lock" };
+                    text_button(row, locked, LayerButton::ToggleLocked(layer.id));
+                });
+        }
+        panel
+            .spawn(NodeBundle {
+                style: row_style,
+                ..default()
+            })
+            .with_children(|row| {
+                text_button(row, "Add", LayerButton::Add);
+                text_button(row, "Delete", LayerButton::Delete);
+                text_button(row, "Move selected here", LayerButton::MoveSelected);
+            });
+    });
+}
+
+fn layer_buttons(
+    mut cmd: Commands,
+    mut layers: ResMut<Layers>,
+    mut dragged: ResMut<DraggedLayer>,
+    mut status: EventWriter<Status>,
+    button_q: Query<(&Interaction, &LayerButton), Changed<Interaction>>,
+    mut table_q: Query<(Entity, &mut InLayer, Has<Selected>)>,
+) {
+    for (interaction, button) in &button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            LayerButton::Activate(id) => {
+                layers.active = id;
+                dragged.0 = Some(id);
+            }
+            LayerButton::ToggleVisible(id) => {
+                if let Some(layer) = layers.get_mut(id) {
+                    layer.visible = !layer.visible;
+                }
+            }
+            LayerButton::ToggleLocked(id) => {
+                if let Some(layer) = layers.get_mut(id) {
+                    layer.locked = !layer.locked;
+                }
+            }
+            LayerButton::Add => {
+                layers.add();
+            }
+            LayerButton::Delete => {
+                if layers.layers.len() < 2 {
+                    status.send(Status::warning("The last layer can not be deleted"));
+                    continue;
+                }
+                // Tables of the deleted layer end up on the layer below it, or above the bottom one
+                let Some(i) = layers.index(layers.active) else {
+                    continue;
+                };
+                let deleted = layers.layers.remove(i);
+                let remaining = layers.layers[i.saturating_sub(1)].id;
+                for (_, mut in_layer, _) in &mut table_q {
+                    if in_layer.0 == deleted.id {
+                        in_layer.0 = remaining;
+                    }
+                }
+                layers.active = remaining;
+            }
+            LayerButton::MoveSelected => {
+                for (id, mut in_layer, selected) in &mut table_q {
+                    if selected && in_layer.0 != layers.active {
+                        in_layer.0 = layers.active;
+                        // The tables would disappear from the selection anyways if the layer is locked
+                        cmd.entity(id).remove::<Selected>();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Dropping a dragged layer onto another row of the panel moves it to that place
+fn drop_layer(
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    row_q: Query<(&LayerRow, &Node, &GlobalTransform)>,
+    mut layers: ResMut<Layers>,
+    mut dragged: ResMut<DraggedLayer>,
+) {
+    if !mouse_buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(id) = dragged.0.take() else {
+        return;
+    };
+    let Some(cursor) = windows.single().cursor_position() else {
+        return;
+    };
+    let Some(LayerRow(target)) = row_q
+        .iter()
+        .find(|(_, node, transform)| node.logical_rect(transform).contains(cursor))
+        .map(|(row, ..)| row)
+    else {
+        return;
+    };
+    if *target == id {
+        return;
+    }
+    let (Some(from), Some(to)) = (layers.index(id), layers.index(*target)) else {
+        return;
+    };
+    let layer = layers.layers.remove(from);
+    layers.layers.insert(to, layer);
+}
+
+/// Hides the tables of hidden layers and marks the tables of hidden and locked layers, which keeps
+/// them from being picked
+fn apply_layers(
+    mut cmd: Commands,
+    layers: Res<Layers>,
+    mut table_q: Query<(
+        Entity,
+        Ref<InLayer>,
+        &mut Visibility,
+        Has<Locked>,
+        Has<Hidden>,
+    )>,
+) {
+    for (id, in_layer, mut visibility, locked, hidden) in &mut table_q {
+        if !layers.is_changed() && !in_layer.is_changed() {
+            continue;
+        }
+        let Some(layer) = layers.get(in_layer.0) else {
+            continue;
+        };
+        let shown = if layer.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != shown {
+            *visibility = shown;
+        }
+        if layer.locked != locked {
+            if layer.locked {
+                cmd.entity(id).insert(Locked);
+            } else {
+                cmd.entity(id).remove::<Locked>();
+            }
+        }
+        if layer.visible == hidden {
+            if layer.visible {
+                cmd.entity(id).remove::<Hidden>();
+            } else {
+                cmd.entity(id).insert(Hidden);
+            }
+        }
+        if !layer.visible || layer.locked {
+            cmd.entity(id).remove::<Selected>();
+        }
+    }
+}
+
+/// Gives every table its own depth, tables on higher layers are above those on lower ones.
+/// Within a layer tables keep their order, tables that are new to it go on top
+fn stack_tables(layers: Res<Layers>, mut table_q: Query<(Ref<InLayer>, &mut Transform), Stacked>) {
+    if !layers.is_changed() && !table_q.iter().any(|(in_layer, _)| in_layer.is_changed()) {
+        return;
+    }
+    let mut stack: Vec<_> = table_q
+        .iter_mut()
+        .map(|(in_layer, transform)| {
+            let layer = layers.index(in_layer.0).unwrap_or_default();
+            (
+                (layer, in_layer.is_changed(), transform.translation.z),
+                transform,
+            )
+        })
+        .collect();
+    stack.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.total_cmp(&b.2)));
+    for (depth, (_, mut transform)) in stack.into_iter().enumerate() {
+        let z = depth as f32 + 1.0;
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+/// Moves the selected tables up or down among the tables of their layer, the tables of a layer
+/// swap their depths so that the layers stay apart
+fn reorder_tables(
+    keys: Res<ButtonInput<KeyCode>>,
+    layers: Res<Layers>,
+    mut table_q: Query<(Entity, &InLayer, &mut Transform, Has<Selected>), Stacked>,
+) {
+    let up = keys.just_pressed(KeyCode::BracketRight);
+    let all_the_way = shift_pressed(keys);
+
+    for layer in &layers.layers {
+        let mut stack: Vec<_> = table_q
+            .iter()
+            .filter(|(_, in_layer, ..)| in_layer.0 == layer.id)
+            .map(|(id, _, transform, selected)| (id, transform.translation.z, selected))
+            .collect();
+        if !stack.iter().any(|(.., selected)| *selected) {
+            continue;
+        }
+        stack.sort_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+        let depths: Vec<f32> = stack.iter().map(|(_, z, _)| *z).collect();
+
+        // Sorting is stable, so the selected and other tables keep their order among themselves
+        match (up, all_the_way) {
+            (true, true) => stack.sort_by_key(|(.., selected)| *selected),
+            (false, true) => stack.sort_by_key(|(.., selected)| !selected),
+            (true, false) => {
+                for i in (0..stack.len() - 1).rev() {
+                    if stack[i].2 && !stack[i + 1].2 {
+                        stack.swap(i, i + 1);
+                    }
+                }
+            }
+            (false, false) => {
+                for i in 1..stack.len() {
+                    if stack[i].2 && !stack[i - 1].2 {
+                        stack.swap(i, i - 1);
+                    }
+                }
+            }
+        }
+
+        for ((id, ..), z) in stack.into_iter().zip(depths) {
+            if let Ok((.., mut transform, _)) = table_q.get_mut(id) {
+                if transform.translation.z != z {
+                    transform.translation.z = z;
+                }
+            }
+        }
+    }
+}
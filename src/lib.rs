@@ -1,18 +1,77 @@
 #![allow(clippy::type_complexity)]
 
 mod actions;
+mod align;
 mod audio;
+mod camera;
+mod chart;
+mod clipboard;
+mod connector;
+mod document;
+mod duplicate;
+mod editing;
+mod eraser;
+mod export;
+mod files;
+mod group;
+mod guides;
+mod image_import;
+mod layers;
+mod line;
 mod loading;
+mod measure;
 mod menu;
+mod numbering;
+mod paint;
+mod palette;
+mod pen;
+mod placed_image;
 mod player;
+mod selection;
+mod shape;
+mod sort;
+mod status;
+mod sticky_note;
+mod symbols;
 mod table;
+mod text_box;
+mod tile;
 
 use actions::{Actions, ActionsPlugin};
+use align::AlignPlugin;
 use audio::InternalAudioPlugin;
+use camera::CameraPlugin;
+use clipboard::ClipboardPlugin;
+use connector::ConnectorPlugin;
+use document::DocumentPlugin;
+use duplicate::DuplicatePlugin;
+use editing::{EditingPlugin, TextEditing};
+use eraser::EraserPlugin;
+use export::ExportPlugin;
+use files::FilesPlugin;
+use group::GroupPlugin;
+use guides::GuidesPlugin;
+use image_import::ImageImportPlugin;
+use layers::LayersPlugin;
+use line::LinePlugin;
 use loading::LoadingPlugin;
+use measure::MeasurePlugin;
 use menu::MenuPlugin;
+use numbering::NumberingPlugin;
+use paint::PaintPlugin;
+use palette::PalettePlugin;
+use pen::PenPlugin;
+use placed_image::PlacedImagePlugin;
 use player::{Tool, User, UserPlugin};
+use selection::SelectionPlugin;
+use shape::ShapePlugin;
+use sort::SortPlugin;
+use status::StatusPlugin;
+use sticky_note::StickyNotePlugin;
+use symbols::SymbolPlugin;
 use table::TablePlugin;
+use text_box::TextBoxPlugin;
+use tile::TilePlugin;
 
 use bevy::app::App;
 #[cfg(debug_assertions)]
@@ -66,6 +125,39 @@ impl Plugin for GamePlugin {
                 InternalAudioPlugin,
                 UserPlugin,
                 TablePlugin,
+                SelectionPlugin,
+                ClipboardPlugin,
+                DuplicatePlugin,
+                CameraPlugin,
+                GuidesPlugin,
+            ))
+            .add_plugins((
+                PaintPlugin,
+                TilePlugin,
+                ImageImportPlugin,
+                PalettePlugin,
+                SymbolPlugin,
+                NumberingPlugin,
+                LayersPlugin,
+                GroupPlugin,
+                AlignPlugin,
+                ShapePlugin,
+                ConnectorPlugin,
+                PenPlugin,
+                EraserPlugin,
+                EditingPlugin,
+                TextBoxPlugin,
+            ))
+            .add_plugins((
+                StickyNotePlugin,
+                PlacedImagePlugin,
+                LinePlugin,
+                MeasurePlugin,
+                StatusPlugin,
+                SortPlugin,
+                FilesPlugin,
+                ExportPlugin,
+                DocumentPlugin,
             ))
             .add_systems(OnEnter(AppState::Running), canvas_start)
             .configure_sets(
@@ -110,6 +202,41 @@ impl MousePosQueries<'_, '_> {
             .map(|ray| ray.origin.truncate())
             .unwrap_or(Vec2::NAN)
     }
+
+    /// The mouse position, or the center of the view while the mouse is outside of the window
+    pub fn mouse_or_view_center(&self) -> Vec2 {
+        let mouse = self.mouse_pos();
+        if mouse.is_nan() {
+            self.camera_q.single().1.translation().truncate()
+        } else {
+            mouse
+        }
+    }
+}
+
+/// Clicks that follow each other within this many seconds make a double click
+const DOUBLE_CLICK_TIME: f64 = 0.4;
+
+/// Keeps track of left clicks to tell double clicks apart from single clicks
+#[derive(SystemParam)]
+pub struct DoubleClicks<'w, 's> {
+    mouse_buttons: Res<'w, ButtonInput<MouseButton>>,
+    time: Res<'w, Time>,
+    last_click: Local<'s, (Option<Entity>, f64)>,
+}
+impl DoubleClicks<'_, '_> {
+    pub fn just_clicked(&self) -> bool {
+        self.mouse_buttons.just_pressed(MouseButton::Left)
+    }
+
+    /// Records a click on `target`, it is a double click if the last click was on the same target
+    pub fn double_click(&mut self, target: Option<Entity>) -> bool {
+        let now = self.time.elapsed_seconds_f64();
+        let double_click =
+            self.last_click.0 == target && now - self.last_click.1 < DOUBLE_CLICK_TIME;
+        *self.last_click = (target, now);
+        double_click
+    }
 }
 
 pub fn canvas_start(mut next_user_state: ResMut<NextState<UserState>>) {
@@ -128,6 +255,38 @@ pub fn using_tool(tool: Tool) -> impl FnMut(Query<&User>, Res<Actions>) -> bool
     move |user, actions| performing_actions(actions) && user.single().current_tool == tool
 }
 
+pub fn tool_selected(tool: Tool) -> impl FnMut(Query<&User>) -> bool {
+    move |user| user.single().current_tool == tool
+}
+
 pub fn run_state_transitions(world: &mut World) {
     let _ = world.try_run_schedule(StateTransition);
 }
+
+/// Run condition for keyboard shortcuts that are pressed together with Ctrl, or Cmd on macOS
+pub fn ctrl_just_pressed(key: KeyCode) -> impl FnMut(Res<ButtonInput<KeyCode>>) -> bool {
+    move |keys| keys.just_pressed(key) && ctrl_pressed(keys)
+}
+
+/// Run condition for Ctrl being held, or Cmd on macOS
+pub fn ctrl_pressed(keys: Res<ButtonInput<KeyCode>>) -> bool {
+    keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ])
+}
+
+pub fn shift_pressed(keys: Res<ButtonInput<KeyCode>>) -> bool {
+    keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+}
+
+/// Run condition for keyboard shortcuts, which are ignored while text is being edited
+pub fn typing(editing: Res<TextEditing>) -> bool {
+    editing.target.is_some()
+}
+
+pub fn alt_pressed(keys: Res<ButtonInput<KeyCode>>) -> bool {
+    keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight])
+}
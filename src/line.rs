@@ -0,0 +1,350 @@
+use bevy::ecs::system::SystemParam;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+use crate::actions::{finish_actions, maintain_actions, Actions};
+use crate::guides::{world_distance, SNAP_DISTANCE};
+use crate::paint::PaintMaterials;
+use crate::pen::stroke_mesh;
+use crate::player::{DashStyle, Tool, User};
+use crate::shape::change_stroke;
+use crate::status::Status;
+use crate::table::TableHead;
+use crate::{
+    ctrl_pressed, tool_selected, typing, using_tool, AppState, CanvasSet, MousePosQueries,
+    WhenActionDoneSet,
+};
+
+pub struct LinePlugin;
+
+/// This plugin is responsible for straight lines drawn with `Tool::Line`.
+/// Dragging draws a single segment, clicking starts a polyline which gets a corner on every click
+/// until the last corner is clicked again or `Enter` or `Escape` is pressed.
+/// Corners snap to the edges of the cells of tables, with `Shift` the segments snap to angles of
+/// 15° instead. `D` cycles the dash style and `[` and `]` change the stroke width
+impl Plugin for LinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                draw_line
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Line))
+                    .after(maintain_actions),
+                follow_cursor
+                    .in_set(CanvasSet)
+                    .run_if(tool_selected(Tool::Line)),
+                finish_segment
+                    .in_set(WhenActionDoneSet)
+                    .before(finish_actions),
+                (
+                    (
+                        finish_polyline.run_if(
+                            input_just_pressed(KeyCode::Enter)
+                                .or_else(input_just_pressed(KeyCode::Escape)),
+                        ),
+                        cycle_dash_style.run_if(input_just_pressed(KeyCode::KeyD)),
+                        change_stroke.run_if(not(ctrl_pressed)),
+                    )
+                        .run_if(tool_selected(Tool::Line))
+                        .run_if(not(typing)),
+                    finish_polyline.run_if(not(tool_selected(Tool::Line))),
+                    update_line_meshes,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// Straight segments through `points`, which are positions in the world
+#[derive(Component, Clone, Debug)]
+pub struct Polyline {
+    pub points: Vec<Vec2>,
+    pub width: f32,
+    pub color: Color,
+    pub dash: DashStyle,
+}
+
+/// Tag for the polyline being drawn, its last point follows the cursor
+#[derive(Component)]
+struct DrawingLine;
+
+/// Segments snap to multiples of this angle while `Shift` is held
+const SNAP_ANGLE: f32 = 15.0;
+
+/// Where a corner at `pos` ends up. With `shift` the segment from `previous` keeps its length
+/// but snaps to the closest multiple of `SNAP_ANGLE`, otherwise `pos` snaps to the closest edges
+/// between the cells of the topmost table it is on
+fn snap_point<'a>(
+    pos: Vec2,
+    previous: Option<Vec2>,
+    shift: bool,
+    tables: impl IntoIterator<Item = (&'a TableHead, &'a GlobalTransform)>,
+    distance: f32,
+) -> Vec2 {
+    if let (true, Some(previous)) = (shift, previous) {
+        let offset = pos - previous;
+        let step = SNAP_ANGLE.to_radians();
+        let angle = (offset.y.atan2(offset.x) / step).round() * step;
+        return previous + Vec2::from_angle(angle) * offset.length();
+    }
+
+    let nearest = |edges: Vec<f32>, at: f32| {
+        edges
+            .into_iter()
+            .min_by(|a, b| (a - at).abs().total_cmp(&(b - at).abs()))
+            .filter(|edge| (edge - at).abs() < distance)
+            .unwrap_or(at)
+    };
+    tables
+        .into_iter()
+        .filter(|(table_head, transform)| {
+            let top_left = transform.translation().truncate();
+            Rect::from_corners(
+                top_left,
+                top_left + table_head.size() * Vec2::new(1.0, -1.0),
+            )
+            .inflate(distance)
+            .contains(pos)
+        })
+        .max_by(|(_, a), (_, b)| a.translation().z.total_cmp(&b.translation().z))
+        .map_or(pos, |(table_head, transform)| {
+            let top_left = transform.translation().truncate();
+            let local = pos - top_left;
+            top_left
+                + Vec2::new(
+                    nearest(table_head.column_edges(), local.x),
+                    -nearest(table_head.row_edges(), -local.y),
+                )
+        })
+}
+
+/// The input that places corners, and what they snap to
+#[derive(SystemParam)]
+struct CornerInput<'w, 's> {
+    mouse_buttons: Res<'w, ButtonInput<MouseButton>>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    projection_q: Query<'w, 's, &'static OrthographicProjection>,
+    table_head_q: Query<'w, 's, (&'static TableHead, &'static GlobalTransform)>,
+}
+impl CornerInput<'_, '_> {
+    fn just_clicked(&self) -> bool {
+        self.mouse_buttons.just_pressed(MouseButton::Left)
+    }
+
+    /// How close, in world units, points have to be to snap
+    fn distance(&self) -> f32 {
+        world_distance(SNAP_DISTANCE, self.projection_q.single())
+    }
+
+    /// Where a corner at `pos` ends up, see [`snap_point`]
+    fn snap(&self, pos: Vec2, previous: Option<Vec2>) -> Vec2 {
+        let shift = self
+            .keys
+            .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        snap_point(pos, previous, shift, &self.table_head_q, self.distance())
+    }
+}
+
+/// Starts a line, or adds a corner to the polyline being drawn, and moves its end while dragging
+fn draw_line(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    user_q: Query<&User>,
+    input: CornerInput,
+    mut drawing_q: Query<(Entity, &mut Polyline), With<DrawingLine>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: PaintMaterials,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    let distance = input.distance();
+
+    if let Ok((id, mut line)) = drawing_q.get_single_mut() {
+        let n = line.points.len();
+        let previous = n.checked_sub(2).map(|i| line.points[i]);
+        if !input.just_clicked() {
+            let pos = input.snap(actions.to, previous);
+            if line.points[n - 1] != pos {
+                line.points[n - 1] = pos;
+            }
+            return;
+        }
+        let pos = input.snap(actions.from, previous);
+        // Clicking the last corner again finishes the polyline
+        if previous.is_some_and(|previous| previous.distance(pos) < distance) {
+            finish(&mut cmd, id, &mut line);
+            return;
+        }
+        line.points[n - 1] = pos;
+        line.points.push(pos);
+        return;
+    }
+
+    if !input.just_clicked() {
+        return;
+    }
+    let config = &user_q.single().current_config;
+    let pos = input.snap(actions.from, None);
+    let line = Polyline {
+        points: vec![pos, pos],
+        width: config.stroke_width,
+        color: config.paint_color,
+        dash: config.dash_style,
+    };
+    let id = spawn_polyline(&mut cmd, line, 0.0, &mut meshes, &mut materials);
+    cmd.entity(id).insert(DrawingLine);
+}
+
+pub fn spawn_polyline(
+    cmd: &mut Commands,
+    line: Polyline,
+    z: f32,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut PaintMaterials,
+) -> Entity {
+    let material = materials.get_or_add(line.color);
+    cmd.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(stroke_mesh(&[], 0.0)).into(),
+            material,
+            transform: Transform::from_xyz(0.0, 0.0, z),
+            ..default()
+        },
+        line,
+    ))
+    .id()
+}
+
+/// Moves the end of the polyline being drawn to the cursor between clicks
+fn follow_cursor(
+    actions: Res<Actions>,
+    mouse_q: MousePosQueries,
+    input: CornerInput,
+    mut drawing_q: Query<&mut Polyline, With<DrawingLine>>,
+) {
+    if actions.button_push.is_some() {
+        return;
+    }
+    let Ok(mut line) = drawing_q.get_single_mut() else {
+        return;
+    };
+    let pos = mouse_q.mouse_pos();
+    if pos.is_nan() {
+        return;
+    }
+    let n = line.points.len();
+    let previous = n.checked_sub(2).map(|i| line.points[i]);
+    let pos = input.snap(pos, previous);
+    if line.points[n - 1] != pos {
+        line.points[n - 1] = pos;
+    }
+}
+
+/// A line that was dragged is done when the mouse is released, a click starts a polyline instead
+fn finish_segment(
+    mut cmd: Commands,
+    projection_q: Query<&OrthographicProjection>,
+    drawing_q: Query<(Entity, &Polyline), With<DrawingLine>>,
+) {
+    let distance = world_distance(SNAP_DISTANCE, projection_q.single());
+    for (id, line) in &drawing_q {
+        if let [from, to] = line.points[..] {
+            if from.distance(to) >= distance {
+                cmd.entity(id).remove::<DrawingLine>();
+            }
+        }
+    }
+}
+
+/// Drops the end that follows the cursor, polylines without any segment left are removed
+fn finish(cmd: &mut Commands, id: Entity, line: &mut Polyline) {
+    line.points.pop();
+    if line.points.len() < 2 {
+        cmd.entity(id).despawn();
+    } else {
+        cmd.entity(id).remove::<DrawingLine>();
+    }
+}
+
+fn finish_polyline(
+    mut cmd: Commands,
+    mut drawing_q: Query<(Entity, &mut Polyline), With<DrawingLine>>,
+) {
+    for (id, mut line) in &mut drawing_q {
+        finish(&mut cmd, id, &mut line);
+    }
+}
+
+/// The dashes of `style` along `points`, the pattern continues around corners
+fn dashes(points: &[Vec2], style: DashStyle, width: f32) -> Vec<Vec<Vec2>> {
+    let width = width.max(1.0);
+    let (dash, gap) = match style {
+        DashStyle::Solid => return vec![points.to_vec()],
+        DashStyle::Dashed => (4.0 * width, 3.0 * width),
+        DashStyle::Dotted => (width, 2.0 * width),
+    };
+    let Some(first) = points.first() else {
+        return vec![];
+    };
+    let mut runs = vec![];
+    let mut run = vec![*first];
+    let mut drawing = true;
+    let mut left = dash;
+    for pair in points.windows(2) {
+        let (mut from, to) = (pair[0], pair[1]);
+        loop {
+            let length = from.distance(to);
+            if length < left {
+                left -= length;
+                if drawing {
+                    run.push(to);
+                }
+                break;
+            }
+            from += (to - from).normalize() * left;
+            run.push(from);
+            if drawing {
+                runs.push(std::mem::take(&mut run));
+            }
+            drawing = !drawing;
+            left = if drawing { dash } else { gap };
+        }
+    }
+    runs.push(run);
+    runs.retain(|run| run.len() > 1);
+    runs
+}
+
+fn update_line_meshes(
+    line_q: Query<(&Polyline, &Mesh2dHandle), Changed<Polyline>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (line, Mesh2dHandle(handle)) in &line_q {
+        if let Some(mesh) = meshes.get_mut(handle) {
+            *mesh = stroke_mesh(&dashes(&line.points, line.dash, line.width), line.width);
+        }
+    }
+}
+
+/// Cycles the dash style of the polyline being drawn and of lines drawn from now on
+fn cycle_dash_style(
+    mut user_q: Query<&mut User>,
+    mut drawing_q: Query<&mut Polyline, With<DrawingLine>>,
+    mut status: EventWriter<Status>,
+) {
+    let config = &mut user_q.single_mut().current_config;
+    config.dash_style = match config.dash_style {
+        DashStyle::Solid => DashStyle::Dashed,
+        DashStyle::Dashed => DashStyle::Dotted,
+        DashStyle::Dotted => DashStyle::Solid,
+    };
+    for mut line in &mut drawing_q {
+        line.dash = config.dash_style;
+    }
+    status.send(Status::info(format!("Dash style: {:?}", config.dash_style)));
+}
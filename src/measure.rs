@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+
+use crate::actions::{maintain_actions, Actions};
+use crate::player::{Tool, User};
+use crate::table::{table_at, TableHead};
+use crate::{using_tool, AppState, CanvasSet};
+
+pub struct MeasurePlugin;
+
+/// This plugin is responsible for `Tool::Measure`, which measures what it is dragged across.
+/// The distance and angle of the drag are shown next to the cursor, together with the rows and
+/// columns it spans when it starts or ends on a table. Nothing is left on the canvas
+impl Plugin for MeasurePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Running), spawn_measure_label)
+            .add_systems(
+                Update,
+                (
+                    draw_measurement
+                        .in_set(CanvasSet)
+                        .run_if(using_tool(Tool::Measure))
+                        .after(maintain_actions),
+                    update_measure_label.run_if(in_state(AppState::Running)),
+                ),
+            );
+    }
+}
+
+/// The text next to the cursor showing the measurement, hidden unless measuring
+#[derive(Component)]
+struct MeasureLabel;
+
+const MEASURE_COLOR: Color = Color::srgb(1.0, 0.6, 0.2);
+/// Radius in pixels of the arc showing the angle
+const ARC_RADIUS: f32 = 30.0;
+
+fn spawn_measure_label(mut cmd: Commands) {
+    cmd.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 14.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            padding: UiRect::all(Val::Px(4.)),
+            ..default()
+        })
+        .with_background_color(Color::srgba(0.1, 0.1, 0.1, 0.8)),
+        Visibility::Hidden,
+        MeasureLabel,
+    ));
+}
+
+/// Angle of the line from `from` to `to` in degrees, counterclockwise from the x axis
+fn angle(from: Vec2, to: Vec2) -> f32 {
+    let offset = to - from;
+    offset.y.atan2(offset.x).to_degrees()
+}
+
+/// The cells from the one under `from` to the one under `to`, on the table under either of them.
+/// Returns their outline and how many columns and rows they span
+fn spanned_cells<'a>(
+    from: Vec2,
+    to: Vec2,
+    tables: impl IntoIterator<Item = (Entity, &'a TableHead, &'a GlobalTransform)> + Clone,
+) -> Option<(Rect, UVec2)> {
+    let (id, _) = table_at(tables.clone(), from).or_else(|| table_at(tables.clone(), to))?;
+    let (_, table_head, transform) = tables.into_iter().find(|(table, ..)| *table == id)?;
+    let translation = transform.translation().truncate();
+    let [a, b] = [from, to].map(|pos| table_head.clamped_cell_at(pos - translation));
+    let outline = [a, b]
+        .map(|cell| {
+            Rect::from_center_size(
+                translation + table_head.cell_center(cell),
+                table_head.cell_size(cell),
+            )
+        })
+        .into_iter()
+        .reduce(|a, b| a.union(b))?;
+    let span = UVec2::new(a.column.abs_diff(b.column) + 1, a.row.abs_diff(b.row) + 1);
+    Some((outline, span))
+}
+
+fn draw_measurement(
+    mut gizmos: Gizmos,
+    actions: Res<Actions>,
+    projection_q: Query<&OrthographicProjection>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform)>,
+) {
+    let (from, to) = (actions.from, actions.to);
+    if let Some((outline, _)) = spanned_cells(from, to, &table_head_q) {
+        gizmos.rect_2d(
+            outline.center(),
+            0.0,
+            outline.size(),
+            MEASURE_COLOR.with_alpha(0.5),
+        );
+    }
+    gizmos.line_2d(from, to, MEASURE_COLOR);
+    if from == to {
+        return;
+    }
+
+    // The angle is shown as an arc from the x axis
+    let radius = (ARC_RADIUS * projection_q.single().scale).min(from.distance(to));
+    gizmos.line_2d(from, from + Vec2::X * radius, MEASURE_COLOR.with_alpha(0.5));
+    let angle = angle(from, to).to_radians();
+    let steps = (angle.abs() / 5f32.to_radians()).ceil().max(1.0) as u32;
+    gizmos.linestrip_2d(
+        (0..=steps).map(|i| from + Vec2::from_angle(angle * i as f32 / steps as f32) * radius),
+        MEASURE_COLOR,
+    );
+}
+
+fn update_measure_label(
+    actions: Res<Actions>,
+    user_q: Query<&User>,
+    window_q: Query<&Window>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform)>,
+    mut label_q: Query<(&mut Text, &mut Style, &mut Visibility), With<MeasureLabel>>,
+) {
+    let Ok((mut text, mut style, mut visibility)) = label_q.get_single_mut() else {
+        return;
+    };
+    let cursor = window_q.get_single().ok().and_then(|w| w.cursor_position());
+    let measuring = actions.button_push == Some(MouseButton::Left)
+        && user_q.single().current_tool == Tool::Measure;
+    let Some(cursor) = cursor.filter(|_| measuring) else {
+        if *visibility != Visibility::Hidden {
+            *visibility = Visibility::Hidden;
+        }
+        return;
+    };
+
+    let (from, to) = (actions.from, actions.to);
+    let mut value = format!("{:.1}  {:.1}°", from.distance(to), angle(from, to));
+    if let Some((_, span)) = spanned_cells(from, to, &table_head_q) {
+        value += &format!("\n{} columns, {} rows", span.x, span.y);
+    }
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+    style.left = Val::Px(cursor.x + 16.);
+    style.top = Val::Px(cursor.y + 16.);
+    *visibility = Visibility::Visible;
+}
@@ -1,4 +1,4 @@
-use crate::loading::TextureAssets;
+use crate::guides::RULER_SIZE;
 use crate::player::{Tool, User};
 use crate::{AppState, UserState};
 use bevy::input::common_conditions::input_just_pressed;
@@ -14,6 +14,7 @@ impl Plugin for MenuPlugin {
             .add_systems(
                 Update,
                 (
+                    button_colors,
                     sidebar_buttons,
                     check_if_in_ui.run_if(
                         input_just_pressed(MouseButton::Left)
@@ -30,7 +31,7 @@ impl Plugin for MenuPlugin {
 }
 
 #[derive(Component)]
-struct ButtonColors {
+pub struct ButtonColors {
     normal: Color,
     hovered: Color,
 }
@@ -45,20 +46,21 @@ impl Default for ButtonColors {
 }
 
 #[derive(Component)]
-struct Sidebar;
+pub struct Sidebar;
 
-fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
+fn setup_menu(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
     commands
         .spawn((
             NodeBundle {
                 style: Style {
-                    align_items: AlignItems::Center,
-                    justify_content: JustifyContent::SpaceAround,
-                    top: Val::Px(5.),
-                    width: Val::Percent(100.),
+                    align_items: AlignItems::Stretch,
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(5.),
+                    top: Val::Px(RULER_SIZE + 5.),
+                    left: Val::Px(RULER_SIZE + 5.),
                     position_type: PositionType::Absolute,
-                    display: Display::Grid,
+                    display: Display::Flex,
                     ..default()
                 },
                 ..default()
@@ -66,60 +68,55 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
             Sidebar,
         ))
         .with_children(|children| {
-            for _ in 0..3 {
-                children
-                    .spawn((
-                        ButtonBundle {
-                            style: Style {
-                                width: Val::Px(30.0),
-                                height: Val::Px(30.0),
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                padding: UiRect::all(Val::Px(5.)),
-                                ..Default::default()
-                            },
-                            background_color: Color::NONE.into(),
-                            ..Default::default()
-                        },
-                        ButtonColors::default(),
-                        ChangeTool(Tool::Table),
-                    ))
-                    .with_children(|parent| {
-                        parent.spawn(ImageBundle {
-                            image: textures.bevy.clone().into(),
-                            style: Style {
-                                width: Val::Px(30.),
-                                ..default()
-                            },
-                            ..default()
-                        });
-                    });
+            for tool in Tool::ALL {
+                text_button(children, tool.name(), ChangeTool(tool));
             }
         });
 }
 
+/// Spawns a button showing `label`, `marker` tells the button apart from others
+pub fn text_button(parent: &mut ChildBuilder, label: &str, marker: impl Bundle) -> Entity {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    height: Val::Px(30.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(5.)),
+                    ..Default::default()
+                },
+                background_color: Color::NONE.into(),
+                ..Default::default()
+            },
+            ButtonColors::default(),
+            marker,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ));
+        })
+        .id()
+}
+
 #[derive(Component)]
 struct ChangeTool(Tool);
 
-fn sidebar_buttons(
-    mut user: Query<&mut User>,
+fn button_colors(
     mut interaction_query: Query<
-        (
-            &Interaction,
-            &mut BackgroundColor,
-            &ButtonColors,
-            &ChangeTool,
-        ),
-        (Changed<Interaction>, With<Button>),
+        (&Interaction, &mut BackgroundColor, &ButtonColors),
+        Changed<Interaction>,
     >,
 ) {
-    for (interaction, mut color, button_colors, change_tool) in &mut interaction_query {
+    for (interaction, mut color, button_colors) in &mut interaction_query {
         match *interaction {
-            Interaction::Pressed => {
-                let ChangeTool(tool) = change_tool;
-                let mut user = user.single_mut();
-                user.current_tool = *tool;
-            }
+            Interaction::Pressed => (),
             Interaction::Hovered => {
                 *color = button_colors.hovered.into();
             }
@@ -130,19 +127,26 @@ fn sidebar_buttons(
     }
 }
 
+fn sidebar_buttons(
+    mut user: Query<&mut User>,
+    interaction_query: Query<(&Interaction, &ChangeTool), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, ChangeTool(tool)) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            let mut user = user.single_mut();
+            user.current_tool = *tool;
+        }
+    }
+}
+
+/// Any UI element that can be interacted with, like buttons and panels, keeps clicks away from
+/// the canvas
 fn check_if_in_ui(
-    interaction_query: Query<Entity, With<Sidebar>>,
-    children: Query<&Children>,
-    child_interact: Query<&Interaction>,
+    interaction_query: Query<&Interaction>,
     mut next_state: ResMut<NextState<UserState>>,
 ) {
-    let sidebar = interaction_query.single();
-
-    for child in children.iter_descendants(sidebar) {
-        let Ok(c_interact) = child_interact.get(child) else {
-            continue;
-        };
-        match c_interact {
+    for interaction in &interaction_query {
+        match interaction {
             Interaction::Pressed | Interaction::Hovered => {
                 return next_state.set(UserState::Sidebar)
             }
@@ -0,0 +1,312 @@
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+
+use crate::player::User;
+use crate::selection::Selected;
+use crate::status::Status;
+use crate::table::{Cell, TableHead};
+use crate::{alt_pressed, typing, AppState};
+
+pub struct NumberingPlugin;
+
+/// This plugin is responsible for numbering the rows and columns along the edges of tables, so
+/// patterns can be followed by coordinates.
+/// `Alt+N` toggles numbering on the selected tables, `Alt+C` cycles the corner counting starts
+/// from, `Alt+E` cycles how often lines are labeled and `Alt+Up`/`Alt+Down` change the first number
+impl Plugin for NumberingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Numbering>().add_systems(
+            Update,
+            (
+                (toggle_numbering, cycle_numbering_settings)
+                    .run_if(alt_pressed)
+                    .run_if(not(typing)),
+                (label_numbers, remove_number_labels, tenth_lines),
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// The corner of the table where counting starts, rows are numbered on its side and columns on
+/// its edge
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumberingCorner {
+    #[default]
+    TopLeft,
+    /// Like knitting charts, which are read from the bottom up
+    BottomLeft,
+    BottomRight,
+    TopRight,
+}
+impl NumberingCorner {
+    pub fn is_bottom(self) -> bool {
+        matches!(self, Self::BottomLeft | Self::BottomRight)
+    }
+
+    pub fn is_right(self) -> bool {
+        matches!(self, Self::BottomRight | Self::TopRight)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::TopLeft => "top left",
+            Self::BottomLeft => "bottom left",
+            Self::BottomRight => "bottom right",
+            Self::TopRight => "top right",
+        }
+    }
+}
+
+/// Numbering of the rows and columns of a table, header cells are not counted.
+/// As a resource it holds the settings that numbering is turned on with
+#[derive(Resource, Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Numbering {
+    /// Number of the first row and column
+    pub start: i32,
+    pub corner: NumberingCorner,
+    /// Only every `every`th number is labeled, the first one always is
+    pub every: u32,
+}
+impl Default for Numbering {
+    fn default() -> Self {
+        Self {
+            start: 1,
+            corner: NumberingCorner::default(),
+            every: 1,
+        }
+    }
+}
+impl Numbering {
+    /// The numbers of `count` lines in order from the top or left, `reverse` counts from the other end
+    fn numbers(&self, count: u32, reverse: bool) -> impl Iterator<Item = i32> + '_ {
+        (0..count as i32).map(move |i| {
+            let offset = if reverse { count as i32 - 1 - i } else { i };
+            self.start + offset
+        })
+    }
+
+    fn is_labeled(&self, number: i32) -> bool {
+        number == self.start || number.rem_euclid(self.every.max(1) as i32) == 0
+    }
+
+    /// The labeled rows and the labeled columns of a table, with their numbers
+    pub fn labeled_lines(&self, table_head: &TableHead) -> (Vec<(u32, i32)>, Vec<(u32, i32)>) {
+        let rows = table_head.num_rows().saturating_sub(table_head.header_rows);
+        let columns = table_head
+            .num_columns()
+            .saturating_sub(table_head.header_columns);
+        let rows = (table_head.header_rows..)
+            .zip(self.numbers(rows, self.corner.is_bottom()))
+            .filter(|(_, number)| self.is_labeled(*number))
+            .collect();
+        let columns = (table_head.header_columns..)
+            .zip(self.numbers(columns, self.corner.is_right()))
+            .filter(|(_, number)| self.is_labeled(*number))
+            .collect();
+        (rows, columns)
+    }
+}
+
+/// Emphasized like a bold line on paper
+fn is_tenth(number: i32) -> bool {
+    number != 0 && number % 10 == 0
+}
+
+/// Tag for the text entities numbering a table, they are children of the table
+#[derive(Component)]
+pub struct NumberLabel;
+
+fn toggle_numbering(
+    mut cmd: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Numbering>,
+    selected_q: Query<(Entity, Has<Numbering>), (With<TableHead>, With<Selected>)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyN) {
+        return;
+    }
+    for (id, numbered) in &selected_q {
+        if numbered {
+            cmd.entity(id).remove::<Numbering>();
+        } else {
+            cmd.entity(id).insert(*settings);
+        }
+    }
+}
+
+/// Changes the settings, the selected tables that are numbered take them over
+fn cycle_numbering_settings(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Numbering>,
+    mut status: EventWriter<Status>,
+    mut selected_q: Query<&mut Numbering, (With<TableHead>, With<Selected>)>,
+) {
+    if keys.just_pressed(KeyCode::KeyC) {
+        settings.corner = match settings.corner {
+            NumberingCorner::TopLeft => NumberingCorner::BottomLeft,
+            NumberingCorner::BottomLeft => NumberingCorner::BottomRight,
+            NumberingCorner::BottomRight => NumberingCorner::TopRight,
+            NumberingCorner::TopRight => NumberingCorner::TopLeft,
+        };
+    } else if keys.just_pressed(KeyCode::KeyE) {
+        settings.every = match settings.every {
+            1 => 2,
+            2 => 5,
+            5 => 10,
+            _ => 1,
+        };
+    } else if keys.just_pressed(KeyCode::ArrowUp) {
+        settings.start += 1;
+    } else if keys.just_pressed(KeyCode::ArrowDown) {
+        settings.start -= 1;
+    } else {
+        return;
+    }
+    status.send(Status::info(format!(
+        "Numbering from {} in the {} corner, every {} labeled",
+        settings.start,
+        settings.corner.name(),
+        settings.every
+    )));
+    for mut numbering in &mut selected_q {
+        *numbering = *settings;
+    }
+}
+
+/// Respawns the labels of a table whenever its size or numbering changes
+fn label_numbers(
+    mut cmd: Commands,
+    user: Query<&User>,
+    table_q: Query<
+        (Entity, &TableHead, &Numbering, Option<&Children>),
+        Or<(Changed<TableHead>, Changed<Numbering>)>,
+    >,
+    label_q: Query<Entity, With<NumberLabel>>,
+) {
+    let config = &user.single().current_config;
+    for (id, table_head, numbering, children) in &table_q {
+        if let Some(children) = children {
+            for label in label_q.iter_many(children) {
+                cmd.entity(label).despawn_recursive();
+            }
+        }
+        let size = table_head.size();
+        let gap = 4.0;
+        let label = |value: i32, position: Vec2, anchor: Anchor| {
+            let (font_size, color) = if is_tenth(value) {
+                (config.table_font_size * 1.2, Color::srgb(0.95, 0.85, 0.4))
+            } else {
+                (config.table_font_size, config.table_text_color)
+            };
+            (
+                NumberLabel,
+                Text2dBundle {
+                    text: Text::from_section(
+                        value.to_string(),
+                        TextStyle {
+                            font_size,
+                            color,
+                            ..default()
+                        },
+                    ),
+                    text_anchor: anchor,
+                    transform: Transform::from_translation(position.extend(0.1)),
+                    ..default()
+                },
+            )
+        };
+
+        let corner = numbering.corner;
+        let (rows, columns) = numbering.labeled_lines(table_head);
+
+        cmd.entity(id).with_children(|l_cmd| {
+            for (row, number) in rows {
+                let y = table_head.cell_center(Cell { row, column: 0 }).y;
+                l_cmd.spawn(if corner.is_right() {
+                    label(number, Vec2::new(size.x + gap, y), Anchor::CenterLeft)
+                } else {
+                    label(number, Vec2::new(-gap, y), Anchor::CenterRight)
+                });
+            }
+            for (column, number) in columns {
+                let x = table_head.cell_center(Cell { row: 0, column }).x;
+                l_cmd.spawn(if corner.is_bottom() {
+                    label(number, Vec2::new(x, -size.y - gap), Anchor::TopCenter)
+                } else {
+                    label(number, Vec2::new(x, gap), Anchor::BottomCenter)
+                });
+            }
+        });
+    }
+}
+
+fn remove_number_labels(
+    mut cmd: Commands,
+    mut removed: RemovedComponents<Numbering>,
+    table_q: Query<&Children, With<TableHead>>,
+    label_q: Query<Entity, With<NumberLabel>>,
+) {
+    for table in removed.read() {
+        let Ok(children) = table_q.get(table) else {
+            continue;
+        };
+        for label in label_q.iter_many(children) {
+            cmd.entity(label).despawn_recursive();
+        }
+    }
+}
+
+/// Draws a line after every tenth row and column, on the side facing away from the first one
+fn tenth_lines(mut gizmos: Gizmos, table_q: Query<(&TableHead, &Numbering, &GlobalTransform)>) {
+    let color = Color::srgb(0.95, 0.85, 0.4);
+    for (table_head, numbering, transform) in &table_q {
+        let size = table_head.size();
+        let line = |from: Vec2, to: Vec2| {
+            (
+                transform.transform_point(from.extend(0.0)).truncate(),
+                transform.transform_point(to.extend(0.0)).truncate(),
+            )
+        };
+        let corner = numbering.corner;
+
+        let rows = table_head.num_rows().saturating_sub(table_head.header_rows);
+        let row_numbers = numbering.numbers(rows, corner.is_bottom());
+        for (row, number) in (table_head.header_rows..).zip(row_numbers) {
+            if !is_tenth(number) {
+                continue;
+            }
+            let cell = Cell { row, column: 0 };
+            let half_height = table_head.cell_size(cell).y / 2.0;
+            let y = table_head.cell_center(cell).y
+                + if corner.is_bottom() {
+                    half_height
+                } else {
+                    -half_height
+                };
+            let (from, to) = line(Vec2::new(0.0, y), Vec2::new(size.x, y));
+            gizmos.line_2d(from, to, color);
+        }
+
+        let columns = table_head
+            .num_columns()
+            .saturating_sub(table_head.header_columns);
+        let column_numbers = numbering.numbers(columns, corner.is_right());
+        for (column, number) in (table_head.header_columns..).zip(column_numbers) {
+            if !is_tenth(number) {
+                continue;
+            }
+            let cell = Cell { row: 0, column };
+            let half_width = table_head.cell_size(cell).x / 2.0;
+            let x = table_head.cell_center(cell).x
+                + if corner.is_right() {
+                    -half_width
+                } else {
+                    half_width
+                };
+            let (from, to) = line(Vec2::new(x, 0.0), Vec2::new(x, -size.y));
+            gizmos.line_2d(from, to, color);
+        }
+    }
+}
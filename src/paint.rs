@@ -0,0 +1,473 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::ops::Deref;
+
+use crate::actions::{maintain_actions, Actions};
+use crate::layers::Pickable;
+use crate::palette::Palette;
+use crate::player::{PaintMode, Tool, User};
+use crate::status::Status;
+use crate::table::{table_at, Cell, TableHead};
+use crate::{
+    alt_pressed, ctrl_pressed, shift_pressed, tool_selected, typing, using_tool, AppState,
+    CanvasSet, MousePosQueries,
+};
+use bevy::ecs::system::SystemParam;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+
+pub struct PaintPlugin;
+
+/// This plugin is responsible for painting cells with `Tool::Paint`, so tables can be used as
+/// grids for pixel, cross-stitch or knitting patterns. While it is selected, `B` switches the paint
+/// mode and `1` to `9` choose one of the first colors of the palette
+impl Plugin for PaintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ColorMaterials>()
+            .init_resource::<PaintSymmetry>()
+            .add_systems(
+                Update,
+                (
+                    (paint_cells, pick_paint_color, place_symmetry_center)
+                        .in_set(CanvasSet)
+                        .run_if(using_tool(Tool::Paint))
+                        .after(maintain_actions),
+                    symmetry_guide
+                        .in_set(CanvasSet)
+                        .run_if(tool_selected(Tool::Paint)),
+                    (
+                        (
+                            toggle_paint_mode.run_if(input_just_pressed(KeyCode::KeyB)),
+                            choose_paint_color,
+                        )
+                            .run_if(tool_selected(Tool::Paint))
+                            .run_if(not(ctrl_pressed)),
+                        cycle_symmetry
+                            .run_if(input_just_pressed(KeyCode::KeyS))
+                            .run_if(alt_pressed)
+                            .run_if(not(shift_pressed)),
+                        cycle_rotations
+                            .run_if(input_just_pressed(KeyCode::KeyS))
+                            .run_if(alt_pressed)
+                            .run_if(shift_pressed),
+                    )
+                        .run_if(not(typing))
+                        .run_if(in_state(AppState::Running)),
+                ),
+            );
+    }
+}
+
+/// Which copies of every painted cell are painted as well
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symmetry {
+    #[default]
+    None,
+    /// Left and right mirror each other across a vertical axis
+    Horizontal,
+    /// Top and bottom mirror each other across a horizontal axis
+    Vertical,
+    /// Mirrored across both axes
+    Both,
+    /// Repeated this many times around the center
+    Rotational(u32),
+}
+impl Symmetry {
+    fn name(self) -> String {
+        match self {
+            Symmetry::None => "no".to_owned(),
+            Symmetry::Horizontal => "horizontal".to_owned(),
+            Symmetry::Vertical => "vertical".to_owned(),
+            Symmetry::Both => "horizontal and vertical".to_owned(),
+            Symmetry::Rotational(n) => format!("{n}-fold rotational"),
+        }
+    }
+}
+
+/// Symmetry of painting, cycled with `Alt+S`. `Shift+Alt+S` cycles how many times rotational
+/// symmetry repeats cells.
+/// Middle click places the center of symmetry, `Shift` + middle click resets it
+#[derive(Resource, Debug, Default)]
+pub struct PaintSymmetry {
+    pub symmetry: Symmetry,
+    /// Position of the center of symmetry, the center of the painted table if `None`
+    pub center: Option<Vec2>,
+}
+impl PaintSymmetry {
+    /// Center of symmetry relative to the position of a table
+    fn local_center(&self, table_head: &TableHead, translation: Vec2) -> Vec2 {
+        self.center
+            .map(|center| center - translation)
+            .unwrap_or(table_head.size() * Vec2::new(0.5, -0.5))
+    }
+
+    /// `cell` and all cells mirroring it
+    pub fn images(&self, table_head: &TableHead, translation: Vec2, cell: Cell) -> Vec<Cell> {
+        let center = self.local_center(table_head, translation);
+        let pos = table_head.cell_center(cell);
+        let mirror_x = Vec2::new(2.0 * center.x - pos.x, pos.y);
+        let mirror_y = Vec2::new(pos.x, 2.0 * center.y - pos.y);
+
+        let images = match self.symmetry {
+            Symmetry::None => vec![pos],
+            Symmetry::Horizontal => vec![pos, mirror_x],
+            Symmetry::Vertical => vec![pos, mirror_y],
+            Symmetry::Both => vec![pos, mirror_x, mirror_y, 2.0 * center - pos],
+            Symmetry::Rotational(n) => (0..n.max(1))
+                .map(|i| {
+                    let rotation = Vec2::from_angle(TAU * i as f32 / n.max(1) as f32);
+                    center + rotation.rotate(pos - center)
+                })
+                .collect(),
+        };
+        images
+            .into_iter()
+            .filter_map(|image| table_head.cell_at(image))
+            .collect()
+    }
+}
+
+/// One material per color, so painted cells of the same color share their material
+#[derive(Resource, Default)]
+struct ColorMaterials(HashMap<[u8; 4], Handle<ColorMaterial>>);
+
+/// The color materials, with one shared material per color
+#[derive(SystemParam)]
+pub struct PaintMaterials<'w> {
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    color_materials: ResMut<'w, ColorMaterials>,
+}
+impl PaintMaterials<'_> {
+    pub fn get_or_add(&mut self, color: Color) -> Handle<ColorMaterial> {
+        let materials = &mut self.materials;
+        self.color_materials
+            .0
+            .entry(color_key(color))
+            .or_insert_with(|| materials.add(color))
+            .clone()
+    }
+}
+impl Deref for PaintMaterials<'_> {
+    type Target = Assets<ColorMaterial>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.materials
+    }
+}
+
+/// Colors that look the same have the same key
+pub fn color_key(color: Color) -> [u8; 4] {
+    color.to_srgba().to_u8_array()
+}
+
+/// The color a cell is painted with
+pub fn cell_color(bg_color: &Handle<ColorMaterial>, materials: &Assets<ColorMaterial>) -> Color {
+    materials
+        .get(bg_color)
+        .map(|material| material.color)
+        .unwrap_or(Color::NONE)
+}
+
+/// The cells of `table_head` that are contiguous with `start` and have the same color,
+/// `colors` holds the [`color_key`] of every cell
+pub fn flood_fill_cells(
+    table_head: &TableHead,
+    colors: &HashMap<Cell, [u8; 4]>,
+    start: Cell,
+) -> HashSet<Cell> {
+    let Some(&key) = colors.get(&start) else {
+        return HashSet::new();
+    };
+    let mut filled = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(Cell { row, column }) = queue.pop_front() {
+        let neighbors = [
+            row.checked_sub(1).map(|row| Cell { row, column }),
+            column.checked_sub(1).map(|column| Cell { row, column }),
+            (row + 1 < table_head.num_rows()).then_some(Cell {
+                row: row + 1,
+                column,
+            }),
+            (column + 1 < table_head.num_columns()).then_some(Cell {
+                row,
+                column: column + 1,
+            }),
+        ];
+        for neighbor in neighbors.into_iter().flatten() {
+            if colors.get(&neighbor) == Some(&key) && filled.insert(neighbor) {
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    filled
+}
+
+/// The tables that are not locked, with the colors of their cells
+#[derive(SystemParam)]
+struct PaintableCells<'w, 's> {
+    table_head_q: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static TableHead,
+            &'static GlobalTransform,
+            &'static Children,
+        ),
+        Pickable,
+    >,
+    cell_q: Query<'w, 's, (&'static Cell, &'static mut Handle<ColorMaterial>)>,
+}
+
+/// Paints the cells the cursor drags across, or flood fills from the clicked cell
+fn paint_cells(
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    user_q: Query<&User>,
+    cells: PaintableCells,
+    mut materials: PaintMaterials,
+    symmetry: Res<PaintSymmetry>,
+    mut last_pos: Local<Option<Vec2>>,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        *last_pos = None;
+        return;
+    }
+    let PaintableCells {
+        table_head_q,
+        mut cell_q,
+    } = cells;
+    let config = &user_q.single().current_config;
+    let paint = materials.get_or_add(config.paint_color);
+    let tables = || {
+        table_head_q
+            .iter()
+            .map(|(id, table_head, transform, _)| (id, table_head, transform))
+    };
+
+    let mut painted: HashMap<Entity, HashSet<Cell>> = HashMap::new();
+    match config.paint_mode {
+        PaintMode::Brush => {
+            // Sample the path since the last frame, so fast strokes do not skip cells
+            let from = last_pos.unwrap_or(actions.to);
+            let step = config.cell_dimensions.min_element() / 2.0;
+            let samples = (from.distance(actions.to) / step).ceil().max(1.0) as u32;
+            for i in 0..=samples {
+                let pos = from.lerp(actions.to, i as f32 / samples as f32);
+                if let Some((id, cell)) = table_at(tables(), pos) {
+                    painted.entry(id).or_default().insert(cell);
+                }
+            }
+            *last_pos = Some(actions.to);
+        }
+        PaintMode::Bucket => {
+            if !mouse_buttons.just_pressed(MouseButton::Left) {
+                return;
+            }
+            let Some((id, start)) = table_at(tables(), actions.from) else {
+                return;
+            };
+            let Ok((_, table_head, _, children)) = table_head_q.get(id) else {
+                return;
+            };
+            let colors = cell_q
+                .iter_many(children)
+                .map(|(cell, bg_color)| (*cell, color_key(cell_color(bg_color, &materials))))
+                .collect();
+            painted.insert(id, flood_fill_cells(table_head, &colors, start));
+        }
+    }
+
+    for (id, cells) in painted {
+        let Ok((_, table_head, transform, children)) = table_head_q.get(id) else {
+            continue;
+        };
+        let translation = transform.translation().truncate();
+        let cells: HashSet<Cell> = cells
+            .into_iter()
+            .flat_map(|cell| symmetry.images(table_head, translation, cell))
+            .collect();
+        let mut cell_iter = cell_q.iter_many_mut(children);
+        while let Some((cell, mut bg_color)) = cell_iter.fetch_next() {
+            if cells.contains(cell) && *bg_color != paint {
+                *bg_color = paint.clone();
+            }
+        }
+    }
+}
+
+/// Right click picks the color of the cell under the cursor
+fn pick_paint_color(
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mut user_q: Query<&mut User>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform, &Children), Pickable>,
+    cell_q: Query<(&Cell, &Handle<ColorMaterial>)>,
+    materials: Res<Assets<ColorMaterial>>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Right) {
+        return;
+    }
+    let tables = table_head_q
+        .iter()
+        .map(|(id, table_head, transform, _)| (id, table_head, transform));
+    let Some((id, picked)) = table_at(tables, actions.from) else {
+        return;
+    };
+    let Ok((.., children)) = table_head_q.get(id) else {
+        return;
+    };
+    if let Some((_, bg_color)) = cell_q
+        .iter_many(children)
+        .find(|(cell, _)| **cell == picked)
+    {
+        user_q.single_mut().current_config.paint_color = cell_color(bg_color, &materials);
+    }
+}
+
+/// Middle click places the center of symmetry, snapped to the cell corners and centers of the
+/// table under the cursor
+fn place_symmetry_center(
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform), Pickable>,
+    mut symmetry: ResMut<PaintSymmetry>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Middle) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        symmetry.center = None;
+        return;
+    }
+    let Some((id, cell)) = table_at(&table_head_q, actions.from) else {
+        symmetry.center = Some(actions.from);
+        return;
+    };
+    let Ok((_, table_head, transform)) = table_head_q.get(id) else {
+        return;
+    };
+    let translation = transform.translation().truncate();
+    let half_cell = table_head.cell_size(cell) / 2.0;
+    let local = actions.from - translation;
+    symmetry.center = Some(translation + (local / half_cell).round() * half_cell);
+}
+
+/// Shows the axes of symmetry on the table under the cursor
+fn symmetry_guide(
+    mut gizmos: Gizmos,
+    symmetry: Res<PaintSymmetry>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform), Pickable>,
+    mouse_q: MousePosQueries,
+) {
+    if symmetry.symmetry == Symmetry::None {
+        return;
+    }
+    let Some((id, _)) = table_at(&table_head_q, mouse_q.mouse_pos()) else {
+        return;
+    };
+    let Ok((_, table_head, transform)) = table_head_q.get(id) else {
+        return;
+    };
+    let translation = transform.translation().truncate();
+    let size = table_head.size();
+    let center = translation + symmetry.local_center(table_head, translation);
+    let color = Color::srgb(0.3, 0.8, 0.8);
+
+    let vertical_axis = || {
+        (
+            Vec2::new(center.x, translation.y),
+            Vec2::new(center.x, translation.y - size.y),
+        )
+    };
+    let horizontal_axis = || {
+        (
+            Vec2::new(translation.x, center.y),
+            Vec2::new(translation.x + size.x, center.y),
+        )
+    };
+    let axes = match symmetry.symmetry {
+        Symmetry::None => vec![],
+        Symmetry::Horizontal => vec![vertical_axis()],
+        Symmetry::Vertical => vec![horizontal_axis()],
+        Symmetry::Both => vec![vertical_axis(), horizontal_axis()],
+        Symmetry::Rotational(n) => {
+            let radius = size.length();
+            (0..n.max(1))
+                .map(|i| {
+                    let direction = Vec2::from_angle(TAU * i as f32 / n.max(1) as f32 + FRAC_PI_2);
+                    (center, center + direction * radius)
+                })
+                .collect()
+        }
+    };
+    for (start, end) in axes {
+        gizmos.line_2d(start, end, color);
+    }
+    gizmos.circle_2d(center, 3.0, color);
+}
+
+fn cycle_symmetry(mut symmetry: ResMut<PaintSymmetry>, mut status: EventWriter<Status>) {
+    symmetry.symmetry = match symmetry.symmetry {
+        Symmetry::None => Symmetry::Horizontal,
+        Symmetry::Horizontal => Symmetry::Vertical,
+        Symmetry::Vertical => Symmetry::Both,
+        Symmetry::Both => Symmetry::Rotational(4),
+        Symmetry::Rotational(_) => Symmetry::None,
+    };
+    status.send(Status::info(format!(
+        "Painting with {} symmetry",
+        symmetry.symmetry.name()
+    )));
+}
+
+fn cycle_rotations(mut symmetry: ResMut<PaintSymmetry>, mut status: EventWriter<Status>) {
+    symmetry.symmetry = Symmetry::Rotational(match symmetry.symmetry {
+        Symmetry::Rotational(2) => 3,
+        Symmetry::Rotational(3) => 4,
+        Symmetry::Rotational(4) => 6,
+        Symmetry::Rotational(6) => 8,
+        _ => 2,
+    });
+    status.send(Status::info(format!(
+        "Painting with {} symmetry",
+        symmetry.symmetry.name()
+    )));
+}
+
+fn toggle_paint_mode(mut user_q: Query<&mut User>, mut status: EventWriter<Status>) {
+    let config = &mut user_q.single_mut().current_config;
+    config.paint_mode = match config.paint_mode {
+        PaintMode::Brush => PaintMode::Bucket,
+        PaintMode::Bucket => PaintMode::Brush,
+    };
+    status.send(Status::info(format!("Paint mode: {:?}", config.paint_mode)));
+}
+
+/// The number keys choose one of the first colors of the palette
+fn choose_paint_color(
+    keys: Res<ButtonInput<KeyCode>>,
+    palette: Res<Palette>,
+    mut user_q: Query<&mut User>,
+) {
+    let digits = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    let chosen = digits
+        .iter()
+        .position(|digit| keys.just_pressed(*digit))
+        .and_then(|i| palette.colors.get(i));
+    if let Some(palette_color) = chosen {
+        user_q.single_mut().current_config.paint_color = palette_color.color;
+    }
+}
@@ -0,0 +1,648 @@
+use std::path::Path;
+
+use crate::files::{FileOpened, SaveFile};
+use crate::menu::{text_button, Sidebar};
+use crate::paint::{color_key, PaintMaterials};
+use crate::player::User;
+use crate::selection::Selected;
+use crate::status::Status;
+use crate::table::{Cell, TableHead};
+use crate::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct PalettePlugin;
+
+/// This plugin is responsible for the named palette of colors used for patterns.
+/// The palette is shown in the sidebar with how often each color is used in the selected tables.
+/// Palettes are loaded by opening or dropping a GIMP palette (`.gpl`) or a JSON file onto the
+/// window, and saved in either format from the panel
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Palette>().add_systems(
+            Update,
+            (
+                load_dropped_palette.run_if(on_event::<FileDragAndDrop>()),
+                load_opened_palette.run_if(on_event::<FileOpened>()),
+                build_palette_panel,
+                (
+                    pick_swatch,
+                    palette_buttons,
+                    highlight_paint_color,
+                    count_palette_usage.run_if(palette_usage_changed),
+                ),
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PaletteColor {
+    pub name: String,
+    pub color: Color,
+    /// Drawn inside cells of this color, so printed charts do not rely on color alone
+    pub symbol: char,
+}
+
+/// Symbols given to palette colors that do not have one, in order
+const DEFAULT_SYMBOLS: &str = "X/O+*#=%@&~^\\$?<>";
+
+/// The symbol given to the `i`th palette color if it does not have one
+pub fn default_symbol(i: usize) -> char {
+    DEFAULT_SYMBOLS.chars().cycle().nth(i).unwrap_or('?')
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<PaletteColor>,
+}
+impl Default for Palette {
+    fn default() -> Self {
+        let colors = [
+            ("Black", Color::BLACK),
+            ("White", Color::WHITE),
+            ("Red", Color::srgb(0.8, 0.2, 0.2)),
+            ("Orange", Color::srgb(0.9, 0.6, 0.2)),
+            ("Yellow", Color::srgb(0.9, 0.85, 0.3)),
+            ("Green", Color::srgb(0.3, 0.7, 0.3)),
+            ("Blue", Color::srgb(0.3, 0.5, 0.9)),
+            ("Purple", Color::srgb(0.5, 0.3, 0.7)),
+            ("Gray", Color::srgb(0.5, 0.5, 0.5)),
+        ];
+        Self {
+            name: "Default".to_owned(),
+            colors: colors
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, color))| PaletteColor {
+                    name: name.to_owned(),
+                    color,
+                    symbol: default_symbol(i),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Palette as stored in JSON, colors are hex codes
+#[derive(Serialize, Deserialize)]
+struct PaletteFile {
+    name: String,
+    colors: Vec<PaletteFileColor>,
+}
+#[derive(Serialize, Deserialize)]
+struct PaletteFileColor {
+    name: String,
+    color: String,
+    #[serde(default)]
+    symbol: Option<char>,
+}
+
+impl Palette {
+    /// Index of the palette color that looks like `color`
+    pub fn position(&self, color: Color) -> Option<usize> {
+        let key = color_key(color);
+        self.colors
+            .iter()
+            .position(|palette_color| color_key(palette_color.color) == key)
+    }
+
+    /// Parses a palette in the format of GIMP
+    pub fn from_gpl(gpl: &str) -> Result<Self, String> {
+        let mut lines = gpl.lines();
+        if lines.next().map(str::trim) != Some("GIMP Palette") {
+            return Err("missing the `GIMP Palette` header".to_owned());
+        }
+        let mut palette = Self {
+            name: String::new(),
+            colors: vec![],
+        };
+        for line in lines.map(str::trim) {
+            if let Some(name) = line.strip_prefix("Name:") {
+                palette.name = name.trim().to_owned();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let mut channel = || -> Result<u8, String> {
+                let part = parts.next().ok_or(format!("incomplete color `{line}`"))?;
+                part.parse()
+                    .map_err(|_| format!("invalid channel `{part}` in `{line}`"))
+            };
+            let [r, g, b] = [channel()?, channel()?, channel()?];
+            let name = parts.collect::<Vec<_>>().join(" ");
+            palette.colors.push(PaletteColor {
+                name,
+                color: Color::srgb_u8(r, g, b),
+                symbol: default_symbol(palette.colors.len()),
+            });
+        }
+        Ok(palette)
+    }
+
+    pub fn to_gpl(&self) -> String {
+        let mut gpl = format!("GIMP Palette\nName: {}\nColumns: 0\n#\n", self.name);
+        for PaletteColor { name, color, .. } in &self.colors {
+            let [r, g, b, _] = color_key(*color);
+            gpl += &format!("{r:3} {g:3} {b:3}\t{name}\n");
+        }
+        gpl
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let file: PaletteFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let colors = file
+            .colors
+            .into_iter()
+            .enumerate()
+            .map(
+                |(
+                    i,
+                    PaletteFileColor {
+                        name,
+                        color,
+                        symbol,
+                    },
+                )| {
+                    let srgba = Srgba::hex(&color).map_err(|e| format!("`{color}`: {e}"))?;
+                    Ok(PaletteColor {
+                        name,
+                        color: srgba.into(),
+                        symbol: symbol.unwrap_or_else(|| default_symbol(i)),
+                    })
+                },
+            )
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            name: file.name,
+            colors,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = PaletteFile {
+            name: self.name.clone(),
+            colors: self
+                .colors
+                .iter()
+                .map(
+                    |PaletteColor {
+                         name,
+                         color,
+                         symbol,
+                     }| PaletteFileColor {
+                        name: name.clone(),
+                        color: color.to_srgba().to_hex(),
+                        symbol: Some(*symbol),
+                    },
+                )
+                .collect(),
+        };
+        serde_json::to_string_pretty(&file).unwrap_or_default()
+    }
+
+    /// Parses a palette in `format`, palettes without a name are named `file_stem`
+    pub fn parse(contents: &str, format: PaletteFormat, file_stem: &str) -> Result<Self, String> {
+        let mut palette = match format {
+            PaletteFormat::Gpl => Self::from_gpl(contents)?,
+            PaletteFormat::Json => Self::from_json(contents)?,
+        };
+        if palette.name.is_empty() {
+            file_stem.clone_into(&mut palette.name);
+        }
+        Ok(palette)
+    }
+
+    /// Loads a `.gpl` or `.json` palette
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let format = PaletteFormat::of(path).ok_or("not a palette file")?;
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let file_stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        Self::parse(&contents, format, &file_stem)
+    }
+
+    /// The palette as a file in `format` to be saved where the user chooses
+    pub fn save(&self, format: PaletteFormat) -> SaveFile {
+        let contents = match format {
+            PaletteFormat::Gpl => self.to_gpl(),
+            PaletteFormat::Json => self.to_json(),
+        };
+        SaveFile {
+            name: self.name.clone(),
+            extension: format.extension(),
+            bytes: contents.into_bytes(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteFormat {
+    Gpl,
+    Json,
+}
+impl PaletteFormat {
+    pub fn of(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "gpl" => Some(Self::Gpl),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gpl => "gpl",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Component)]
+struct PalettePanel;
+
+#[derive(Component)]
+struct PaletteSwatch(usize);
+
+#[derive(Component)]
+struct PaletteUsageLabel(usize);
+
+#[derive(Component, Clone, Copy)]
+enum PaletteButton {
+    /// Replaces the paint color with this palette color in the selected tables
+    Replace(usize),
+    Save(PaletteFormat),
+}
+
+fn load_dropped_palette(
+    mut drop_events: EventReader<FileDragAndDrop>,
+    mut palette: ResMut<Palette>,
+    mut status: EventWriter<Status>,
+) {
+    for event in drop_events.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = event else {
+            continue;
+        };
+        if PaletteFormat::of(path_buf).is_none() {
+            continue;
+        }
+        match Palette::load(path_buf) {
+            Ok(loaded) => {
+                status.send(loaded_status(&loaded));
+                *palette = loaded;
+            }
+            Err(e) => {
+                status.send(Status::warning(format!(
+                    "Could not load palette {}: {e}",
+                    path_buf.display()
+                )));
+            }
+        }
+    }
+}
+
+fn load_opened_palette(
+    mut opened_events: EventReader<FileOpened>,
+    mut palette: ResMut<Palette>,
+    mut status: EventWriter<Status>,
+) {
+    for file in opened_events.read() {
+        let Some(format) = PaletteFormat::from_extension(&file.extension()) else {
+            continue;
+        };
+        let loaded = std::str::from_utf8(&file.bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| Palette::parse(contents, format, file.stem()));
+        match loaded {
+            Ok(loaded) => {
+                status.send(loaded_status(&loaded));
+                *palette = loaded;
+            }
+            Err(e) => {
+                status.send(Status::warning(format!(
+                    "Could not load palette {}: {e}",
+                    file.name
+                )));
+            }
+        }
+    }
+}
+
+fn loaded_status(palette: &Palette) -> Status {
+    Status::info(format!(
+        "Loaded palette {} with {} colors",
+        palette.name,
+        palette.colors.len()
+    ))
+}
+
+/// Rebuilds the palette panel in the sidebar whenever the palette changes
+fn build_palette_panel(
+    mut cmd: Commands,
+    palette: Res<Palette>,
+    sidebar_q: Query<Entity, With<Sidebar>>,
+    added_sidebar_q: Query<(), Added<Sidebar>>,
+    panel_q: Query<Entity, With<PalettePanel>>,
+) {
+    if !palette.is_changed() && added_sidebar_q.is_empty() {
+        return;
+    }
+    let Ok(sidebar) = sidebar_q.get_single() else {
+        return;
+    };
+    for panel in &panel_q {
+        cmd.entity(panel).despawn_recursive();
+    }
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        color: Color::WHITE,
+        ..default()
+    };
+    let row_style = Style {
+        flex_direction: FlexDirection::Row,
+        align_items: AlignItems::Center,
+        column_gap: Val::Px(5.),
+        ..default()
+    };
+    let panel = cmd
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(3.),
+                    padding: UiRect::all(Val::Px(5.)),
+                    ..default()
+                },
+                background_color: Color::linear_rgb(0.1, 0.1, 0.1).into(),
+                ..default()
+            },
+            Interaction::None,
+            PalettePanel,
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                palette.name.clone(),
+                text_style.clone(),
+            ));
+            for (i, palette_color) in palette.colors.iter().enumerate() {
+                panel
+                    .spawn(NodeBundle {
+                        style: row_style.clone(),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(20.),
+                                    height: Val::Px(20.),
+                                    border: UiRect::all(Val::Px(2.)),
+                                    ..default()
+                                },
+                                background_color: palette_color.color.into(),
+                                ..default()
+                            },
+                            PaletteSwatch(i),
+                        ));
+                        row.spawn(TextBundle::from_section(
+                            palette_color.symbol.to_string(),
+                            text_style.clone(),
+                        ));
+                        row.spawn((
+                            TextBundle::from_section(
+                                palette_color.name.clone(),
+                                text_style.clone(),
+                            ),
+                            PaletteUsageLabel(i),
+                        ));
+                        text_button(row, "Replace", PaletteButton::Replace(i));
+                    });
+            }
+            panel
+                .spawn(NodeBundle {
+                    style: row_style,
+                    ..default()
+                })
+                .with_children(|row| {
+                    text_button(row, "Save GPL", PaletteButton::Save(PaletteFormat::Gpl));
+                    text_button(row, "Save JSON", PaletteButton::Save(PaletteFormat::Json));
+                });
+        })
+        .id();
+    cmd.entity(sidebar).add_child(panel);
+}
+
+/// Clicking a swatch makes its color the paint color
+fn pick_swatch(
+    palette: Res<Palette>,
+    mut user_q: Query<&mut User>,
+    swatch_q: Query<(&Interaction, &PaletteSwatch), Changed<Interaction>>,
+) {
+    for (interaction, PaletteSwatch(i)) in &swatch_q {
+        if *interaction == Interaction::Pressed {
+            user_q.single_mut().current_config.paint_color = palette.colors[*i].color;
+        }
+    }
+}
+
+fn palette_buttons(
+    palette: Res<Palette>,
+    user_q: Query<&User>,
+    button_q: Query<(&Interaction, &PaletteButton), Changed<Interaction>>,
+    selected_q: Query<&Children, (With<TableHead>, With<Selected>)>,
+    mut cell_q: Query<&mut Handle<ColorMaterial>>,
+    mut materials: PaintMaterials,
+    mut save_events: EventWriter<SaveFile>,
+) {
+    for (interaction, button) in &button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            PaletteButton::Replace(i) => {
+                let replaced = color_key(user_q.single().current_config.paint_color);
+                let replacement = materials.get_or_add(palette.colors[i].color);
+                for children in &selected_q {
+                    let mut cells = cell_q.iter_many_mut(children);
+                    while let Some(mut bg_color) = cells.fetch_next() {
+                        let is_replaced = materials
+                            .get(&*bg_color)
+                            .is_some_and(|material| color_key(material.color) == replaced);
+                        if is_replaced {
+                            *bg_color = replacement.clone();
+                        }
+                    }
+                }
+            }
+            PaletteButton::Save(format) => {
+                save_events.send(palette.save(format));
+            }
+        }
+    }
+}
+
+/// Outlines the swatch of the paint color
+fn highlight_paint_color(
+    palette: Res<Palette>,
+    user_q: Query<&User>,
+    mut swatch_q: Query<(&mut BorderColor, &PaletteSwatch)>,
+) {
+    let current = palette.position(user_q.single().current_config.paint_color);
+    for (mut border_color, PaletteSwatch(i)) in &mut swatch_q {
+        let color = if current == Some(*i) {
+            Color::WHITE
+        } else {
+            Color::NONE
+        };
+        if border_color.0 != color {
+            border_color.0 = color;
+        }
+    }
+}
+
+/// Whether the counts of [`count_palette_usage`] may be out of date, because the palette, the
+/// colors of cells or the selected tables changed
+fn palette_usage_changed(
+    palette: Res<Palette>,
+    painted_q: Query<(), (With<Cell>, Changed<Handle<ColorMaterial>>)>,
+    selected_q: Query<(), (With<TableHead>, Added<Selected>)>,
+    mut deselected: RemovedComponents<Selected>,
+    label_q: Query<(), Added<PaletteUsageLabel>>,
+) -> bool {
+    // Reading all removals keeps them from piling up for the next frames
+    let any_deselected = deselected.read().count() > 0;
+    palette.is_changed()
+        || any_deselected
+        || !painted_q.is_empty()
+        || !selected_q.is_empty()
+        || !label_q.is_empty()
+}
+
+/// Shows how many cells of the selected tables have each palette color
+fn count_palette_usage(
+    palette: Res<Palette>,
+    selected_q: Query<&Children, (With<TableHead>, With<Selected>)>,
+    cell_q: Query<&Handle<ColorMaterial>>,
+    materials: Res<Assets<ColorMaterial>>,
+    mut label_q: Query<(&mut Text, &PaletteUsageLabel)>,
+) {
+    let mut counts = vec![0; palette.colors.len()];
+    for children in &selected_q {
+        for bg_color in cell_q.iter_many(children) {
+            let used = materials
+                .get(bg_color)
+                .and_then(|material| palette.position(material.color));
+            if let Some(i) = used {
+                counts[i] += 1;
+            }
+        }
+    }
+
+    let any_selected = !selected_q.is_empty();
+    for (mut text, PaletteUsageLabel(i)) in &mut label_q {
+        let Some(palette_color) = palette.colors.get(*i) else {
+            continue;
+        };
+        let label = if any_selected {
+            format!("{} ({})", palette_color.name, counts[*i])
+        } else {
+            palette_color.name.clone()
+        };
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        Palette {
+            name: "Test colors".to_owned(),
+            colors: vec![
+                PaletteColor {
+                    name: "Dark red".to_owned(),
+                    color: Color::srgb_u8(128, 0, 16),
+                    symbol: 'R',
+                },
+                PaletteColor {
+                    name: "Sky".to_owned(),
+                    color: Color::srgb_u8(135, 206, 235),
+                    symbol: '~',
+                },
+            ],
+        }
+    }
+
+    fn colors(palette: &Palette) -> Vec<(String, [u8; 4], char)> {
+        palette
+            .colors
+            .iter()
+            .map(|c| (c.name.clone(), color_key(c.color), c.symbol))
+            .collect()
+    }
+
+    #[test]
+    fn gpl_round_trip() {
+        let saved = palette();
+        let loaded = Palette::from_gpl(&saved.to_gpl()).unwrap();
+        assert_eq!(loaded.name, saved.name);
+        // GIMP palettes have no symbols, colors get the default ones
+        let expected: Vec<_> = colors(&saved)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, color, _))| (name, color, default_symbol(i)))
+            .collect();
+        assert_eq!(colors(&loaded), expected);
+        assert_eq!(loaded.to_gpl(), saved.to_gpl());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let saved = palette();
+        let loaded = Palette::from_json(&saved.to_json()).unwrap();
+        assert_eq!(loaded.name, saved.name);
+        assert_eq!(colors(&loaded), colors(&saved));
+    }
+
+    #[test]
+    fn gpl_from_gimp() {
+        let gpl =
+            "GIMP Palette\nName: Mixed\nColumns: 4\n# comment\n  0   0   0\tBlack\n255 255 255\n";
+        let loaded = Palette::from_gpl(gpl).unwrap();
+        assert_eq!(loaded.name, "Mixed");
+        assert_eq!(
+            colors(&loaded),
+            [
+                ("Black".to_owned(), [0, 0, 0, 255], default_symbol(0)),
+                (String::new(), [255, 255, 255, 255], default_symbol(1)),
+            ]
+        );
+        assert!(Palette::from_gpl("not a palette").is_err());
+    }
+
+    #[test]
+    fn json_without_symbols() {
+        let json = r##"{"name": "", "colors": [{"name": "Red", "color": "#ff0000"}]}"##;
+        let loaded = Palette::parse(json, PaletteFormat::Json, "reds").unwrap();
+        assert_eq!(loaded.name, "reds");
+        assert_eq!(
+            colors(&loaded),
+            [("Red".to_owned(), [255, 0, 0, 255], default_symbol(0))]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+use crate::actions::{finish_actions, maintain_actions, Actions, Preview};
+use crate::paint::PaintMaterials;
+use crate::player::{Tool, User};
+use crate::shape::change_stroke;
+use crate::status::Status;
+use crate::{ctrl_pressed, tool_selected, using_tool, AppState, CanvasSet, WhenActionDoneSet};
+
+pub struct PenPlugin;
+
+/// This plugin is responsible for freehand strokes drawn with `Tool::Pen`, to annotate tables.
+/// Strokes have the paint color and the stroke width, `[` and `]` change the width.
+/// `M` switches between the pen and a highlighter, which draws wide translucent strokes
+impl Plugin for PenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                record_stroke
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Pen))
+                    .after(maintain_actions),
+                simplify_stroke
+                    .in_set(WhenActionDoneSet)
+                    .before(finish_actions),
+                (
+                    toggle_highlighter.run_if(input_just_pressed(KeyCode::KeyM)),
+                    change_stroke.run_if(not(ctrl_pressed)),
+                )
+                    .run_if(tool_selected(Tool::Pen))
+                    .run_if(in_state(AppState::Running)),
+                update_stroke_meshes.run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// A freehand line through `points`, which are positions in the world
+#[derive(Component, Clone, Debug)]
+pub struct Stroke {
+    pub points: Vec<Vec2>,
+    pub width: f32,
+    pub color: Color,
+}
+
+/// Highlighter strokes are this much wider than pen strokes
+const HIGHLIGHTER_WIDTH: f32 = 4.0;
+const HIGHLIGHTER_ALPHA: f32 = 0.35;
+/// How far, in pixels, a recorded point may be off the simplified stroke
+const SIMPLIFY_TOLERANCE: f32 = 0.75;
+
+/// Spawns a stroke with its mesh, the mesh is built once its points are known
+pub fn spawn_stroke(
+    cmd: &mut Commands,
+    stroke: Stroke,
+    z: f32,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut PaintMaterials,
+) -> Entity {
+    let material = materials.get_or_add(stroke.color);
+    cmd.spawn((
+        stroke,
+        MaterialMesh2dBundle {
+            mesh: meshes.add(stroke_mesh(&[], 0.0)).into(),
+            material,
+            transform: Transform::from_xyz(0.0, 0.0, z),
+            ..default()
+        },
+    ))
+    .id()
+}
+
+/// Adds the cursor position to the stroke being drawn whenever it moved far enough
+fn record_stroke(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    user_q: Query<&User>,
+    projection_q: Query<&OrthographicProjection>,
+    mut stroke_q: Query<&mut Stroke, With<Preview>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: PaintMaterials,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    if let Ok(mut stroke) = stroke_q.get_single_mut() {
+        let min_step = 2.0 * projection_q.single().scale;
+        if stroke
+            .points
+            .last()
+            .is_none_or(|last| last.distance(actions.to) >= min_step)
+        {
+            stroke.points.push(actions.to);
+        }
+        return;
+    }
+
+    let config = &user_q.single().current_config;
+    let stroke = if config.highlighter {
+        Stroke {
+            points: vec![actions.from],
+            width: config.stroke_width * HIGHLIGHTER_WIDTH,
+            color: config.paint_color.with_alpha(HIGHLIGHTER_ALPHA),
+        }
+    } else {
+        Stroke {
+            points: vec![actions.from],
+            width: config.stroke_width,
+            color: config.paint_color,
+        }
+    };
+    let id = spawn_stroke(&mut cmd, stroke, 0.0, &mut meshes, &mut materials);
+    cmd.entity(id).insert(Preview);
+}
+
+/// Drops the points of the finished stroke that barely change its shape
+fn simplify_stroke(
+    projection_q: Query<&OrthographicProjection>,
+    mut stroke_q: Query<&mut Stroke, With<Preview>>,
+) {
+    let tolerance = SIMPLIFY_TOLERANCE * projection_q.single().scale;
+    for mut stroke in &mut stroke_q {
+        let simplified = simplify(&stroke.points, tolerance);
+        stroke.points = simplified;
+    }
+}
+
+/// Ramer-Douglas-Peucker simplification, keeps the points that are further than `tolerance` away
+/// from the line through the points kept around them
+pub fn simplify(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let distance = |p: Vec2| {
+        let line = last - first;
+        if line == Vec2::ZERO {
+            p.distance(first)
+        } else {
+            line.perp_dot(p - first).abs() / line.length()
+        }
+    };
+    let (furthest, max_distance) = points[1..points.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i + 1, distance(*p)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap_or((0, 0.0));
+    if max_distance <= tolerance {
+        return vec![first, last];
+    }
+    let mut simplified = simplify(&points[..=furthest], tolerance);
+    simplified.pop();
+    simplified.extend(simplify(&points[furthest..], tolerance));
+    simplified
+}
+
+/// A Catmull-Rom spline through `points`, so simplified strokes stay round
+fn smooth(points: &[Vec2]) -> Vec<Vec2> {
+    const STEPS: usize = 4;
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let at = |i: isize| points[i.clamp(0, points.len() as isize - 1) as usize];
+    let mut smoothed = vec![];
+    for i in 0..points.len() as isize - 1 {
+        let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+        for step in 0..STEPS {
+            let t = step as f32 / STEPS as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            smoothed.push(
+                0.5 * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                    + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3),
+            );
+        }
+    }
+    smoothed.push(points[points.len() - 1]);
+    smoothed
+}
+
+/// Bands of `width` along each of the open lines in `runs`
+pub fn stroke_mesh(runs: &[Vec<Vec2>], width: f32) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    for points in runs {
+        // A single point is drawn as a short dash
+        let points = match points.as_slice() {
+            [point] => vec![
+                *point - Vec2::X * width / 4.0,
+                *point + Vec2::X * width / 4.0,
+            ],
+            _ => points.to_vec(),
+        };
+        let n = points.len();
+        for i in 0..n {
+            let before = points[i.saturating_sub(1)];
+            let after = points[(i + 1).min(n - 1)];
+            let normal = (after - before).perp().normalize_or_zero();
+            if i + 1 < n {
+                let i = positions.len() as u32;
+                indices.extend([i, i + 1, i + 2, i + 2, i + 1, i + 3]);
+            }
+            for side in [1.0, -1.0] {
+                let p = points[i] + normal * side * width / 2.0;
+                positions.push([p.x, p.y, 0.0]);
+            }
+        }
+    }
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn update_stroke_meshes(
+    stroke_q: Query<(&Stroke, &Mesh2dHandle), Changed<Stroke>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (stroke, Mesh2dHandle(handle)) in &stroke_q {
+        if let Some(mesh) = meshes.get_mut(handle) {
+            *mesh = stroke_mesh(&[smooth(&stroke.points)], stroke.width);
+        }
+    }
+}
+
+fn toggle_highlighter(mut user_q: Query<&mut User>, mut status: EventWriter<Status>) {
+    let config = &mut user_q.single_mut().current_config;
+    config.highlighter = !config.highlighter;
+    status.send(Status::info(if config.highlighter {
+        "Drawing with the highlighter"
+    } else {
+        "Drawing with the pen"
+    }));
+}
@@ -0,0 +1,341 @@
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::sprite::Anchor;
+
+use crate::actions::{maintain_actions, Actions};
+use crate::layers::Pickable;
+use crate::player::Tool;
+use crate::status::Status;
+use crate::{ctrl_pressed, tool_selected, typing, using_tool, AppState, CanvasSet};
+
+pub struct PlacedImagePlugin;
+
+/// This plugin is responsible for images placed on the canvas from the import dialog.
+/// With `Tool::Select` images are moved by dragging them and resized by dragging their corners,
+/// keeping their aspect ratio unless it is unlocked with `K` or `Shift` is held.
+/// `Alt` dragging a corner crops the image instead, `[` and `]` change the opacity of the image
+/// that was clicked last
+impl Plugin for PlacedImagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveImage>().add_systems(
+            Update,
+            (
+                drag_images
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Select))
+                    .after(maintain_actions),
+                image_handles
+                    .in_set(CanvasSet)
+                    .run_if(tool_selected(Tool::Select)),
+                (
+                    (
+                        toggle_aspect_lock.run_if(input_just_pressed(KeyCode::KeyK)),
+                        change_opacity.run_if(not(ctrl_pressed)),
+                    )
+                        .run_if(tool_selected(Tool::Select))
+                        .run_if(not(typing)),
+                    update_sprites,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// An image on the canvas, positioned at its top left like tables
+#[derive(Component)]
+pub struct PlacedImage {
+    /// Name of the file the image was loaded from
+    pub name: String,
+    /// Contents of the file, which saved documents embed
+    pub bytes: Vec<u8>,
+    /// Size of the whole image in pixels
+    pub pixels: Vec2,
+    /// Part of the image that is shown, in pixels from its top left
+    pub crop: Rect,
+    /// Size the shown part is stretched to on the canvas
+    pub size: Vec2,
+    pub opacity: f32,
+    /// Whether resizing keeps the aspect ratio
+    pub keep_aspect: bool,
+}
+impl PlacedImage {
+    pub fn bounds(&self, transform: &GlobalTransform) -> Rect {
+        let top_left = transform.translation().truncate();
+        Rect::from_corners(top_left, top_left + self.size * Vec2::new(1.0, -1.0))
+    }
+}
+
+/// The image that was clicked last, which the keyboard shortcuts apply to
+#[derive(Resource, Default)]
+struct ActiveImage(Option<Entity>);
+
+/// Images are placed no larger than this, so huge photos do not cover the whole canvas
+const MAX_SIZE: f32 = 512.0;
+/// Size of the corners that resize or crop an image when they are dragged
+const HANDLE_SIZE: f32 = 10.0;
+
+/// A texture of `source`
+pub fn texture_of(source: &image::RgbaImage) -> Image {
+    let (width, height) = source.dimensions();
+    Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        source.as_raw().clone(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// Spawns the image decoded from the file `bytes` as `texture` centered on `center`,
+/// it is shown at its size in pixels unless that is too large
+pub fn spawn_placed_image(
+    cmd: &mut Commands,
+    name: String,
+    bytes: Vec<u8>,
+    texture: Handle<Image>,
+    pixels: UVec2,
+    center: Vec2,
+) -> Entity {
+    let pixels = pixels.as_vec2();
+    let size = pixels * (MAX_SIZE / pixels.max_element()).min(1.0);
+    let top_left = center + size * Vec2::new(-0.5, 0.5);
+    let image = PlacedImage {
+        name,
+        bytes,
+        pixels,
+        crop: Rect::from_corners(Vec2::ZERO, pixels),
+        size,
+        opacity: 1.0,
+        keep_aspect: true,
+    };
+    spawn_image(cmd, image, texture, top_left.extend(0.0))
+}
+
+/// Spawns `image` showing `texture` with its top left at `translation`
+pub fn spawn_image(
+    cmd: &mut Commands,
+    image: PlacedImage,
+    texture: Handle<Image>,
+    translation: Vec3,
+) -> Entity {
+    cmd.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(image.size),
+                rect: Some(image.crop),
+                color: Color::WHITE.with_alpha(image.opacity),
+                anchor: Anchor::TopLeft,
+                ..default()
+            },
+            texture,
+            transform: Transform::from_translation(translation),
+            ..default()
+        },
+        image,
+    ))
+    .id()
+}
+
+enum ImageDrag {
+    Move(Entity, Vec3),
+    /// Dragging `corner`, from `(0, 0)` at the top left to `(1, 1)` at the bottom right,
+    /// resizes or crops the image while the opposite corner stays in place
+    Corner {
+        id: Entity,
+        corner: Vec2,
+        crop: bool,
+        /// Position of the opposite corner
+        anchor: Vec2,
+        start: Rect,
+        size: Vec2,
+    },
+}
+
+/// Position of `corner` of an image with `size` whose top left is at `top_left`
+fn corner_pos(top_left: Vec2, size: Vec2, corner: Vec2) -> Vec2 {
+    top_left + corner * size * Vec2::new(1.0, -1.0)
+}
+
+fn drag_images(
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut active: ResMut<ActiveImage>,
+    mut dragged: Local<Option<ImageDrag>>,
+    mut image_q: Query<(Entity, &mut PlacedImage, &mut Transform, &GlobalTransform), Pickable>,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        let hit = image_q
+            .iter()
+            .filter(|(_, image, _, transform)| {
+                image
+                    .bounds(transform)
+                    .inflate(HANDLE_SIZE / 2.0)
+                    .contains(actions.from)
+            })
+            .max_by(|(.., a), (.., b)| a.translation().z.total_cmp(&b.translation().z));
+        *dragged = hit.map(|(id, image, transform, _)| {
+            let top_left = transform.translation.truncate();
+            let corner = [
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(0.0, 1.0),
+                Vec2::new(1.0, 1.0),
+            ]
+            .into_iter()
+            .find(|corner| {
+                let pos = corner_pos(top_left, image.size, *corner);
+                (actions.from - pos).abs().max_element() < HANDLE_SIZE / 2.0
+            });
+            match corner {
+                Some(corner) => ImageDrag::Corner {
+                    id,
+                    corner,
+                    crop: keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+                    anchor: corner_pos(top_left, image.size, Vec2::ONE - corner),
+                    start: image.crop,
+                    size: image.size,
+                },
+                None => ImageDrag::Move(id, transform.translation),
+            }
+        });
+        let id = hit.map(|(id, ..)| id);
+        if active.0 != id {
+            active.0 = id;
+        }
+        return;
+    }
+
+    let delta = actions.to - actions.from;
+    match *dragged {
+        Some(ImageDrag::Move(id, origin)) => {
+            if let Ok((_, _, mut transform, _)) = image_q.get_mut(id) {
+                let z = transform.translation.z;
+                transform.translation = (origin.truncate() + delta).extend(z);
+            }
+        }
+        Some(ImageDrag::Corner {
+            id,
+            corner,
+            crop,
+            anchor,
+            start,
+            size,
+        }) => {
+            let Ok((_, mut image, mut transform, _)) = image_q.get_mut(id) else {
+                return;
+            };
+            let fixed = Vec2::ONE - corner;
+            // Dragging away from the opposite corner grows the image
+            let grow = delta * Vec2::new(1.0, -1.0) * (corner * 2.0 - Vec2::ONE);
+
+            if crop {
+                // The image keeps its scale, so what stays visible does not move
+                let scale = size / start.size();
+                let moved = grow / scale;
+                let mut rect = start;
+                if corner.x == 0.0 {
+                    rect.min.x = (rect.min.x - moved.x).clamp(0.0, rect.max.x - 1.0);
+                } else {
+                    rect.max.x = (rect.max.x + moved.x).clamp(rect.min.x + 1.0, image.pixels.x);
+                }
+                if corner.y == 0.0 {
+                    rect.min.y = (rect.min.y - moved.y).clamp(0.0, rect.max.y - 1.0);
+                } else {
+                    rect.max.y = (rect.max.y + moved.y).clamp(rect.min.y + 1.0, image.pixels.y);
+                }
+                image.crop = rect;
+                image.size = rect.size() * scale;
+            } else {
+                let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+                let mut new_size = (size + grow).max(Vec2::splat(HANDLE_SIZE));
+                if image.keep_aspect && !shift {
+                    new_size = size * (new_size / size).max_element();
+                }
+                image.size = new_size;
+            }
+            let z = transform.translation.z;
+            transform.translation = (anchor - corner_pos(Vec2::ZERO, image.size, fixed)).extend(z);
+        }
+        None => {}
+    }
+}
+
+/// Outlines the clicked image and the corners that resize it
+fn image_handles(
+    mut gizmos: Gizmos,
+    active: Res<ActiveImage>,
+    image_q: Query<(&PlacedImage, &GlobalTransform), Pickable>,
+) {
+    let Some((image, transform)) = active.0.and_then(|id| image_q.get(id).ok()) else {
+        return;
+    };
+    let color = Color::srgb(0.3, 0.3, 0.3);
+    let bounds = image.bounds(transform);
+    gizmos.rect_2d(bounds.center(), 0.0, bounds.size(), color);
+    for corner in [
+        bounds.min,
+        bounds.max,
+        Vec2::new(bounds.min.x, bounds.max.y),
+        Vec2::new(bounds.max.x, bounds.min.y),
+    ] {
+        gizmos.rect_2d(corner, 0.0, Vec2::splat(HANDLE_SIZE), color);
+    }
+}
+
+fn toggle_aspect_lock(
+    active: Res<ActiveImage>,
+    mut image_q: Query<&mut PlacedImage>,
+    mut status: EventWriter<Status>,
+) {
+    if let Some(mut image) = active.0.and_then(|id| image_q.get_mut(id).ok()) {
+        image.keep_aspect = !image.keep_aspect;
+        status.send(Status::info(format!(
+            "Aspect ratio of {}: {}",
+            image.name,
+            if image.keep_aspect { "locked" } else { "free" }
+        )));
+    }
+}
+
+fn change_opacity(
+    keys: Res<ButtonInput<KeyCode>>,
+    active: Res<ActiveImage>,
+    mut image_q: Query<&mut PlacedImage>,
+    mut status: EventWriter<Status>,
+) {
+    let change = if keys.just_pressed(KeyCode::BracketRight) {
+        0.1
+    } else if keys.just_pressed(KeyCode::BracketLeft) {
+        -0.1
+    } else {
+        return;
+    };
+    if let Some(mut image) = active.0.and_then(|id| image_q.get_mut(id).ok()) {
+        image.opacity = (image.opacity + change).clamp(0.1, 1.0);
+        status.send(Status::info(format!(
+            "Opacity of {}: {:.0}%",
+            image.name,
+            image.opacity * 100.0
+        )));
+    }
+}
+
+fn update_sprites(mut image_q: Query<(&PlacedImage, &mut Sprite), Changed<PlacedImage>>) {
+    for (image, mut sprite) in &mut image_q {
+        sprite.custom_size = Some(image.size);
+        sprite.rect = Some(image.crop);
+        sprite.color = Color::WHITE.with_alpha(image.opacity);
+    }
+}
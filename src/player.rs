@@ -8,12 +8,117 @@ pub struct UserPlugin;
 pub struct UserConfig {
     pub cell_dimensions: Vec2,
     pub table_text_color: Color,
+    pub table_font_size: f32,
     pub table_bg_color: Handle<ColorMaterial>,
+    pub table_header_bg_color: Handle<ColorMaterial>,
+    /// Number of header rows given to new tables
+    pub header_rows: u32,
+    /// Number of header columns given to new tables
+    pub header_columns: u32,
     pub cell_mesh: Handle<Mesh>,
+    /// Color cells are painted with by `Tool::Paint`
+    pub paint_color: Color,
+    pub paint_mode: PaintMode,
+    /// Whether shapes are filled with the paint color
+    pub shape_filled: bool,
+    /// Color of the outline of shapes
+    pub stroke_color: Color,
+    pub stroke_width: f32,
+    /// Radius of the corners of rectangles
+    pub corner_radius: f32,
+    /// Routing of new connectors
+    pub routing: Routing,
+    /// Arrowheads at the start and end of new connectors
+    pub arrow_heads: [ArrowHead; 2],
+    /// Whether `Tool::Pen` draws wide translucent strokes
+    pub highlighter: bool,
+    /// Radius of `Tool::Eraser`
+    pub eraser_radius: f32,
+    /// Font size of new text boxes
+    pub text_font_size: f32,
+    /// Alignment of the lines of new text boxes
+    pub text_justify: JustifyText,
+    /// Color of new sticky notes
+    pub note_color: Color,
+    /// Dashes of new lines
+    pub dash_style: DashStyle,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaintMode {
+    /// Paints every cell dragged across
+    Brush,
+    /// Fills all contiguous cells of the same color
+    Bucket,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Routing {
+    Straight,
+    /// Horizontal and vertical lines with elbows in between
+    Orthogonal,
+    Curved,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DashStyle {
+    Solid,
+    Dashed,
+    Dotted,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrowHead {
+    None,
+    /// Two lines, open at the back
+    Arrow,
+    Triangle,
+    Circle,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Tool {
     Table,
+    Select,
+    Paint,
+    Rectangle,
+    Ellipse,
+    Connector,
+    Pen,
+    Eraser,
+    Text,
+    Note,
+    Line,
+    Measure,
+}
+impl Tool {
+    /// All tools, in the order they are shown in the sidebar
+    pub const ALL: [Tool; 12] = [
+        Tool::Table,
+        Tool::Select,
+        Tool::Paint,
+        Tool::Rectangle,
+        Tool::Ellipse,
+        Tool::Connector,
+        Tool::Pen,
+        Tool::Eraser,
+        Tool::Text,
+        Tool::Note,
+        Tool::Line,
+        Tool::Measure,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Table => "Table",
+            Tool::Select => "Select",
+            Tool::Paint => "Paint",
+            Tool::Rectangle => "Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::Connector => "Connector",
+            Tool::Pen => "Pen",
+            Tool::Eraser => "Eraser",
+            Tool::Text => "Text",
+            Tool::Note => "Note",
+            Tool::Line => "Line",
+            Tool::Measure => "Measure",
+        }
+    }
 }
 #[derive(Component, Debug)]
 pub struct User {
@@ -39,6 +144,10 @@ fn spawn_user(
         ..Default::default()
     };
     let bg_handle = color_assets.add(bg_material);
+    let header_bg_handle = color_assets.add(ColorMaterial {
+        color: Color::srgb(0.2, 0.2, 0.3),
+        ..Default::default()
+    });
 
     let cell_mesh = mesh_assets.add(Rectangle::new(1.0, 1.0));
 
@@ -47,8 +156,26 @@ fn spawn_user(
         current_config: UserConfig {
             cell_dimensions: Vec2::splat(20.0),
             table_text_color: Color::WHITE,
+            table_font_size: 14.0,
             table_bg_color: bg_handle,
+            table_header_bg_color: header_bg_handle,
+            header_rows: 1,
+            header_columns: 0,
             cell_mesh,
+            paint_color: Color::WHITE,
+            paint_mode: PaintMode::Brush,
+            shape_filled: false,
+            stroke_color: Color::WHITE,
+            stroke_width: 2.0,
+            corner_radius: 0.0,
+            routing: Routing::Straight,
+            arrow_heads: [ArrowHead::None, ArrowHead::Arrow],
+            highlighter: false,
+            eraser_radius: 10.0,
+            text_font_size: 16.0,
+            text_justify: JustifyText::Left,
+            note_color: Color::srgb(1.0, 0.92, 0.45),
+            dash_style: DashStyle::Solid,
         },
     });
 }
@@ -0,0 +1,547 @@
+use crate::actions::{maintain_actions, Actions};
+use crate::align::{alignment_lines, smart_snap, table_bounds, SmartGuides};
+use crate::connector::Connector;
+use crate::group::{EnteredGroup, Groups};
+use crate::guides::{snap_bounds, world_distance, Guides, SNAP_DISTANCE};
+use crate::layers::{Locked, Pickable};
+use crate::player::{Tool, User};
+use crate::table::{resize_table, table_at, Cell, CellContents, TableHead};
+use crate::{using_tool, AppState, CanvasSet, DoubleClicks, WhenActionDoneSet};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+pub struct SelectionPlugin;
+
+/// This plugin is responsible for selecting tables and ranges of cells with `Tool::Select`.
+/// Dragging a selected table with the left mouse button moves all selected tables, dragging the
+/// handles on the edges and corners of a selected table adds or removes columns and rows there
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ResizedTable>().add_systems(
+            Update,
+            (
+                (
+                    grab_resize_handle,
+                    select_tables.run_if(not_resizing),
+                    select_cells,
+                    move_tables,
+                    resize_tables,
+                )
+                    .chain()
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Select))
+                    .after(maintain_actions),
+                drop_tables.in_set(WhenActionDoneSet),
+                selection_outline.run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// Tag for tables that are currently selected
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Selected;
+
+/// The selected tables and range of cells
+#[derive(SystemParam)]
+pub struct Selection<'w, 's> {
+    pub cells: Option<Res<'w, SelectedCells>>,
+    selected_q: Query<'w, 's, Entity, With<Selected>>,
+}
+impl Selection<'_, '_> {
+    /// Deselects all tables and cells
+    pub fn clear(&self, cmd: &mut Commands) {
+        cmd.remove_resource::<SelectedCells>();
+        for id in &self.selected_q {
+            cmd.entity(id).remove::<Selected>();
+        }
+    }
+}
+
+/// Tables that are being dragged, with the position they were at when the drag started
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct DragOrigin(Vec3);
+
+/// A handle on the outline of a selected table, which resizes it when dragged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ResizeHandle {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+impl ResizeHandle {
+    const ALL: [ResizeHandle; 8] = [
+        ResizeHandle::TopLeft,
+        ResizeHandle::Top,
+        ResizeHandle::TopRight,
+        ResizeHandle::Left,
+        ResizeHandle::Right,
+        ResizeHandle::BottomLeft,
+        ResizeHandle::Bottom,
+        ResizeHandle::BottomRight,
+    ];
+
+    /// The edges the handle moves, -1 for the left or top edge, 1 for the right or bottom edge
+    /// and 0 where it moves neither
+    fn sides(self) -> IVec2 {
+        match self {
+            ResizeHandle::TopLeft => IVec2::new(-1, -1),
+            ResizeHandle::Top => IVec2::new(0, -1),
+            ResizeHandle::TopRight => IVec2::new(1, -1),
+            ResizeHandle::Left => IVec2::new(-1, 0),
+            ResizeHandle::Right => IVec2::new(1, 0),
+            ResizeHandle::BottomLeft => IVec2::new(-1, 1),
+            ResizeHandle::Bottom => IVec2::new(0, 1),
+            ResizeHandle::BottomRight => IVec2::new(1, 1),
+        }
+    }
+
+    /// Position of the handle relative to the top left of a table of `size`
+    fn offset(self, size: Vec2) -> Vec2 {
+        (self.sides().as_vec2() + 1.0) / 2.0 * size * Vec2::new(1.0, -1.0)
+    }
+}
+
+/// The table whose handle is being dragged, with the handle and the size of the table when the
+/// drag started
+#[derive(Resource, Default)]
+struct ResizedTable(Option<(Entity, ResizeHandle, Vec2)>);
+
+/// Size of the resize handles in pixels
+const HANDLE_SIZE: f32 = 8.0;
+
+/// A rectangular range of cells in a table, inclusive of both corners
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SelectedCells {
+    pub table: Entity,
+    pub from: Cell,
+    pub to: Cell,
+}
+impl SelectedCells {
+    /// Top left cell of the range
+    pub fn min(&self) -> Cell {
+        Cell {
+            row: self.from.row.min(self.to.row),
+            column: self.from.column.min(self.to.column),
+        }
+    }
+
+    /// Bottom right cell of the range
+    pub fn max(&self) -> Cell {
+        Cell {
+            row: self.from.row.max(self.to.row),
+            column: self.from.column.max(self.to.column),
+        }
+    }
+
+    /// Number of columns and rows in the range
+    pub fn size(&self) -> UVec2 {
+        let (min, max) = (self.min(), self.max());
+        UVec2::new(max.column - min.column + 1, max.row - min.row + 1)
+    }
+
+    pub fn contains(&self, cell: Cell) -> bool {
+        let (min, max) = (self.min(), self.max());
+        (min.row..=max.row).contains(&cell.row) && (min.column..=max.column).contains(&cell.column)
+    }
+
+    /// The range after the cells of its table were renumbered by `shift` columns and rows and the
+    /// table was resized to `size` columns and rows, none when none of its cells are left
+    pub fn shifted(self, shift: IVec2, size: UVec2) -> Option<Self> {
+        let index = |cell: Cell| IVec2::new(cell.column as i32, cell.row as i32) + shift;
+        let last = size.as_ivec2() - 1;
+        if index(self.max()).cmplt(IVec2::ZERO).any() || index(self.min()).cmpgt(last).any() {
+            return None;
+        }
+        let clamped = |cell: Cell| {
+            let index = index(cell).clamp(IVec2::ZERO, last).as_uvec2();
+            Cell {
+                row: index.y,
+                column: index.x,
+            }
+        };
+        Some(Self {
+            from: clamped(self.from),
+            to: clamped(self.to),
+            ..self
+        })
+    }
+}
+
+/// The tables that can be clicked and the ones that are selected
+#[derive(SystemParam)]
+struct SelectableTables<'w, 's> {
+    table_head_q: Query<'w, 's, (Entity, &'static TableHead, &'static GlobalTransform), Pickable>,
+    selected_q: Query<'w, 's, Entity, With<Selected>>,
+    transform_q: Query<'w, 's, &'static Transform, With<TableHead>>,
+}
+
+/// Left click selects the table under the cursor, holding shift adds to or removes from the selection.
+/// Tables of a group are selected together, unless the group was entered by double clicking it
+fn select_tables(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut clicks: DoubleClicks,
+    mut entered: ResMut<EnteredGroup>,
+    tables: SelectableTables,
+    groups: Groups,
+) {
+    if !clicks.just_clicked() {
+        return;
+    }
+    let SelectableTables {
+        table_head_q,
+        selected_q,
+        transform_q,
+    } = tables;
+    cmd.remove_resource::<SelectedCells>();
+
+    let hit = table_at(&table_head_q, actions.from).map(|(id, _)| id);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let drag = |cmd: &mut Commands, id: Entity| {
+        if let Ok(transform) = transform_q.get(id) {
+            cmd.entity(id).insert(DragOrigin(transform.translation));
+        }
+    };
+
+    let group = hit.and_then(|id| groups.group_of(id));
+    let double_click = clicks.double_click(group) && group.is_some();
+    if double_click {
+        entered.0 = group;
+    } else if entered.0 != group {
+        entered.0 = None;
+    }
+    let hit: Vec<Entity> = match group {
+        Some(group) if entered.0 != Some(group) => groups.tables(group),
+        _ => hit.into_iter().collect(),
+    };
+    let hit_selected = hit.first().is_some_and(|id| selected_q.contains(*id));
+
+    if !hit.is_empty() && shift && hit_selected {
+        for id in hit {
+            cmd.entity(id).remove::<Selected>();
+        }
+    } else if !hit.is_empty() && shift {
+        for dragged in selected_q.iter().chain(hit.iter().copied()) {
+            drag(&mut cmd, dragged);
+        }
+        for id in hit {
+            cmd.entity(id).insert(Selected);
+        }
+    } else if hit_selected && !double_click {
+        for dragged in &selected_q {
+            drag(&mut cmd, dragged);
+        }
+    } else {
+        for selected in &selected_q {
+            cmd.entity(selected).remove::<Selected>();
+        }
+        for id in hit {
+            drag(&mut cmd, id);
+            cmd.entity(id).insert(Selected);
+        }
+    }
+}
+
+/// Moves the dragged tables along with the cursor, snapping their outlines to guides or else to
+/// the edges and centers of other tables
+fn move_tables(
+    actions: Res<Actions>,
+    guides: Res<Guides>,
+    mut smart_guides: ResMut<SmartGuides>,
+    projection_q: Query<&OrthographicProjection>,
+    mut dragged_q: Query<(&mut Transform, &GlobalTransform, &DragOrigin, &TableHead)>,
+    others_q: Query<(&TableHead, &GlobalTransform), (Without<DragOrigin>, Pickable)>,
+) {
+    let delta = actions.to - actions.from;
+    if actions.button_push != Some(MouseButton::Left) || delta == Vec2::ZERO {
+        return;
+    }
+
+    let mut top_left = Vec2::new(f32::INFINITY, f32::NEG_INFINITY);
+    let mut bottom_right = Vec2::new(f32::NEG_INFINITY, f32::INFINITY);
+    for (transform, global_transform, DragOrigin(origin), table_head) in &dragged_q {
+        // Grouped tables are positioned relative to their group
+        let parent = global_transform.translation() - transform.translation;
+        let corner = (parent + *origin).truncate() + delta;
+        let size = table_head.size();
+        top_left = Vec2::new(top_left.x.min(corner.x), top_left.y.max(corner.y));
+        bottom_right = Vec2::new(
+            bottom_right.x.max(corner.x + size.x),
+            bottom_right.y.min(corner.y - size.y),
+        );
+    }
+    let projection = projection_q.single();
+    let mut snap = snap_bounds(&guides, (top_left, bottom_right), projection);
+
+    let others: Vec<Rect> = others_q
+        .iter()
+        .map(|(table_head, transform)| table_bounds(table_head, transform))
+        .collect();
+    let moving = Rect::from_corners(top_left + snap, bottom_right + snap);
+    let smart = smart_snap(moving, &others, world_distance(SNAP_DISTANCE, projection));
+    // Guides win over other tables
+    for axis in 0..2 {
+        if snap[axis] == 0.0 {
+            snap[axis] = smart[axis];
+        }
+    }
+    let moved = Rect::from_corners(top_left + snap, bottom_right + snap);
+    smart_guides.0 = alignment_lines(moved, &others);
+
+    for (mut transform, _, DragOrigin(origin), _) in &mut dragged_q {
+        let z = transform.translation.z;
+        transform.translation = (origin.truncate() + delta + snap).extend(z);
+    }
+}
+
+fn not_resizing(resized: Res<ResizedTable>) -> bool {
+    resized.0.is_none()
+}
+
+/// Pressing a handle of a selected table starts resizing it, instead of selecting or moving tables
+fn grab_resize_handle(
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    projection_q: Query<&OrthographicProjection>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform), (With<Selected>, Pickable)>,
+    mut resized: ResMut<ResizedTable>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let reach = world_distance(HANDLE_SIZE, projection_q.single());
+    resized.0 = selected_q.iter().find_map(|(id, table_head, transform)| {
+        let size = table_head.size();
+        let top_left = transform.translation().truncate();
+        ResizeHandle::ALL
+            .into_iter()
+            .find(|handle| {
+                let pos = top_left + handle.offset(size);
+                (actions.from - pos).abs().max_element() < reach
+            })
+            .map(|handle| (id, handle, size))
+    });
+}
+
+/// What refers to cells of tables, which has to follow them when they are renumbered
+#[derive(SystemParam)]
+struct CellReferences<'w, 's> {
+    connector_q: Query<'w, 's, &'static mut Connector>,
+    selected_cells: Option<ResMut<'w, SelectedCells>>,
+}
+
+/// Adds or removes the columns and rows of the resized table that the dragged handle passes,
+/// the contents of the cells that are kept stay as they are.
+/// Handles on the left or top move the table, so that its right and bottom stay in place.
+/// Selected cells and connectors stay on the same cells
+fn resize_tables(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    resized: Res<ResizedTable>,
+    user_q: Query<&User>,
+    mut table_head_q: Query<(&mut TableHead, &mut Transform, &Children)>,
+    cell_q: Query<(Entity, &Cell)>,
+    references: CellReferences,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    let Some((id, handle, start)) = resized.0 else {
+        return;
+    };
+    let Ok((mut table_head, mut transform, children)) = table_head_q.get_mut(id) else {
+        return;
+    };
+    let CellReferences {
+        mut connector_q,
+        selected_cells,
+    } = references;
+    let config = &user_q.single().current_config;
+    let sides = handle.sides();
+    // Dragging a handle away from the table grows it
+    let delta = (actions.to - actions.from) * Vec2::new(1.0, -1.0) * sides.as_vec2();
+    let at_start = sides.cmplt(IVec2::ZERO);
+    let wanted = table_head.size_in_cells(start + delta, at_start, config);
+    let current = UVec2::new(table_head.num_columns(), table_head.num_rows());
+    let change = (wanted.as_ivec2() - current.as_ivec2()) * sides.abs();
+    if change == IVec2::ZERO {
+        return;
+    }
+    let start_change = IVec2::select(at_start, change, IVec2::ZERO);
+    let size = current.as_ivec2() + change;
+    let span = IRect::from_corners(-start_change, size - start_change);
+    let moved = resize_table(
+        &mut cmd,
+        id,
+        &mut table_head,
+        span,
+        config,
+        cell_q.iter_many(children),
+        |table_head, cell| CellContents::empty(table_head, &cell, config),
+    );
+    transform.translation += moved.extend(0.0);
+
+    if let Some(mut selected_cells) = selected_cells.filter(|cells| cells.table == id) {
+        match selected_cells.shifted(start_change, size.as_uvec2()) {
+            Some(shifted) => *selected_cells = shifted,
+            None => cmd.remove_resource::<SelectedCells>(),
+        }
+    }
+
+    // Connectors stay attached to the same cells
+    if start_change == IVec2::ZERO {
+        return;
+    }
+    for mut connector in &mut connector_q {
+        let from = connector.from.shifted(id, start_change);
+        let to = connector.to.shifted(id, start_change);
+        if (from, to) != (connector.from, connector.to) {
+            connector.from = from;
+            connector.to = to;
+        }
+    }
+}
+
+fn drop_tables(
+    mut cmd: Commands,
+    mut smart_guides: ResMut<SmartGuides>,
+    mut resized: ResMut<ResizedTable>,
+    dragged_q: Query<Entity, With<DragOrigin>>,
+) {
+    smart_guides.0.clear();
+    resized.0 = None;
+    for id in &dragged_q {
+        cmd.entity(id).remove::<DragOrigin>();
+    }
+}
+
+/// Dragging with the right mouse button selects a range of cells in the table under the cursor
+fn select_cells(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    table_head_q: Query<(Entity, &TableHead, &GlobalTransform), Pickable>,
+    selected_q: Query<Entity, With<Selected>>,
+) {
+    if actions.button_push != Some(MouseButton::Right) {
+        return;
+    }
+    let Some((table, from)) = table_at(&table_head_q, actions.from) else {
+        return;
+    };
+    let Ok((_, table_head, transform)) = table_head_q.get(table) else {
+        return;
+    };
+
+    let local = actions.to - transform.translation().truncate();
+    let to = table_head.clamped_cell_at(local);
+
+    for selected in &selected_q {
+        if selected != table {
+            cmd.entity(selected).remove::<Selected>();
+        }
+    }
+    cmd.entity(table).insert(Selected);
+    cmd.insert_resource(SelectedCells { table, from, to });
+}
+
+fn selection_outline(
+    mut gizmos: Gizmos,
+    projection_q: Query<&OrthographicProjection>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform, Has<Locked>), With<Selected>>,
+    selected_cells: Option<Res<SelectedCells>>,
+) {
+    let color = Color::srgb(0.3, 0.5, 0.9);
+    let handle_size = projection_q.get_single().map_or(HANDLE_SIZE, |projection| {
+        world_distance(HANDLE_SIZE, projection)
+    });
+    for (_, table_head, transform, locked) in &selected_q {
+        let size = table_head.size();
+        let top_left = transform.translation().truncate();
+        gizmos.rect_2d(top_left + size * Vec2::new(0.5, -0.5), 0.0, size, color);
+        if locked {
+            continue;
+        }
+        for handle in ResizeHandle::ALL {
+            gizmos.rect_2d(
+                top_left + handle.offset(size),
+                0.0,
+                Vec2::splat(handle_size),
+                color,
+            );
+        }
+    }
+
+    let Some(selected_cells) = selected_cells else {
+        return;
+    };
+    let Ok((_, table_head, transform, _)) = selected_q.get(selected_cells.table) else {
+        return;
+    };
+    let (min, max) = (selected_cells.min(), selected_cells.max());
+    if max.row >= table_head.num_rows() || max.column >= table_head.num_columns() {
+        return;
+    }
+    let top_left = table_head.cell_center(min) - table_head.cell_size(min) * Vec2::new(0.5, -0.5);
+    let bottom_right =
+        table_head.cell_center(max) + table_head.cell_size(max) * Vec2::new(0.5, -0.5);
+    gizmos.rect_2d(
+        transform.translation().truncate() + (top_left + bottom_right) / 2.0,
+        0.0,
+        (bottom_right - top_left).abs(),
+        color.with_alpha(0.6),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(from: (u32, u32), to: (u32, u32)) -> SelectedCells {
+        SelectedCells {
+            table: Entity::PLACEHOLDER,
+            from: Cell {
+                row: from.0,
+                column: from.1,
+            },
+            to: Cell {
+                row: to.0,
+                column: to.1,
+            },
+        }
+    }
+
+    #[test]
+    fn selected_cells_follow_resized_tables() {
+        let selected = range((1, 1), (2, 3));
+        let shifted = selected
+            .shifted(IVec2::new(2, 1), UVec2::new(6, 4))
+            .unwrap();
+        assert_eq!(
+            (shifted.min(), shifted.max()),
+            (Cell { row: 2, column: 3 }, Cell { row: 3, column: 5 })
+        );
+
+        // Removed cells are dropped from the range
+        let shifted = selected
+            .shifted(IVec2::new(-2, 0), UVec2::new(2, 2))
+            .unwrap();
+        assert_eq!(
+            (shifted.min(), shifted.max()),
+            (Cell { row: 1, column: 0 }, Cell { row: 1, column: 1 })
+        );
+
+        assert!(selected
+            .shifted(IVec2::new(-4, 0), UVec2::new(2, 4))
+            .is_none());
+        assert!(selected.shifted(IVec2::ZERO, UVec2::new(1, 4)).is_none());
+    }
+}
@@ -0,0 +1,351 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+use crate::actions::{finish_actions, maintain_actions, Actions, Preview};
+use crate::paint::PaintMaterials;
+use crate::player::{Tool, User};
+use crate::status::Status;
+use crate::{ctrl_pressed, using_tool, AppState, CanvasSet, WhenActionDoneSet};
+
+pub struct ShapePlugin;
+
+/// This plugin is responsible for drawing rectangles and ellipses with `Tool::Rectangle` and
+/// `Tool::Ellipse`.
+/// `Shift` keeps squares and circles, `Alt` draws from the center.
+/// While one of them is the tool, `F` toggles the fill, `X` swaps fill and stroke colors,
+/// `[` and `]` change the stroke width, with `Shift` the corner radius of rectangles
+impl Plugin for ShapePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                make_shape
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Rectangle).or_else(using_tool(Tool::Ellipse)))
+                    .after(maintain_actions),
+                cleanup_empty_shapes
+                    .in_set(WhenActionDoneSet)
+                    .before(finish_actions),
+                (
+                    toggle_fill.run_if(input_just_pressed(KeyCode::KeyF)),
+                    swap_colors
+                        .run_if(input_just_pressed(KeyCode::KeyX))
+                        .run_if(not(ctrl_pressed)),
+                    change_stroke.run_if(not(ctrl_pressed)),
+                )
+                    .run_if(shape_tool_selected)
+                    .run_if(in_state(AppState::Running)),
+                update_shape_meshes.run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeKind {
+    Rectangle,
+    Ellipse,
+}
+
+/// A shape drawn on the canvas, positioned at its center
+#[derive(Component, Clone, Debug)]
+pub struct Shape {
+    pub kind: ShapeKind,
+    pub size: Vec2,
+    /// Shapes without a fill are only outlined
+    pub fill: Option<Color>,
+    pub stroke: Color,
+    pub stroke_width: f32,
+    /// Rounds the corners of rectangles
+    pub corner_radius: f32,
+}
+
+/// Tag for the outline drawn on top of the fill of a shape
+#[derive(Component)]
+struct ShapeStroke;
+
+fn shape_tool_selected(user_q: Query<&User>) -> bool {
+    matches!(
+        user_q.single().current_tool,
+        Tool::Rectangle | Tool::Ellipse
+    )
+}
+
+fn make_shape(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    keys: Res<ButtonInput<KeyCode>>,
+    user_q: Query<&User>,
+    mut shape_q: Query<(&mut Shape, &mut Transform), With<Preview>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: PaintMaterials,
+) {
+    let mut extent = actions.to - actions.from;
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        let side = extent.x.abs().max(extent.y.abs());
+        extent = Vec2::new(side.copysign(extent.x), side.copysign(extent.y));
+    }
+    let (center, size) = if keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        (actions.from, extent.abs() * 2.0)
+    } else {
+        (actions.from + extent / 2.0, extent.abs())
+    };
+
+    if let Ok((mut shape, mut transform)) = shape_q.get_single_mut() {
+        shape.size = size;
+        let z = transform.translation.z;
+        transform.translation = center.extend(z);
+        return;
+    }
+
+    let user = user_q.single();
+    let config = &user.current_config;
+    let shape = Shape {
+        kind: if user.current_tool == Tool::Ellipse {
+            ShapeKind::Ellipse
+        } else {
+            ShapeKind::Rectangle
+        },
+        size,
+        fill: config.shape_filled.then_some(config.paint_color),
+        stroke: config.stroke_color,
+        stroke_width: config.stroke_width,
+        corner_radius: config.corner_radius,
+    };
+    cmd.spawn((
+        shape,
+        Preview,
+        MaterialMesh2dBundle {
+            mesh: meshes.add(empty_mesh()).into(),
+            material: materials.get_or_add(Color::NONE),
+            transform: Transform::from_translation(center.extend(0.0)),
+            ..default()
+        },
+    ))
+    .with_children(|shape| {
+        shape.spawn((
+            ShapeStroke,
+            MaterialMesh2dBundle {
+                mesh: meshes.add(empty_mesh()).into(),
+                material: materials.get_or_add(Color::NONE),
+                transform: Transform::from_xyz(0.0, 0.0, 0.01),
+                ..default()
+            },
+        ));
+    });
+}
+
+/// Shapes too small to see are not kept
+fn cleanup_empty_shapes(mut cmd: Commands, shape_q: Query<(Entity, &Shape), With<Preview>>) {
+    for (id, shape) in &shape_q {
+        if shape.size.x < 1.0 || shape.size.y < 1.0 {
+            cmd.entity(id).despawn_recursive();
+        }
+    }
+}
+
+/// Rebuilds the meshes of shapes whenever they change
+fn update_shape_meshes(
+    mut shape_q: Query<
+        (&Shape, &Mesh2dHandle, &mut Handle<ColorMaterial>, &Children),
+        Changed<Shape>,
+    >,
+    mut stroke_q: Query<
+        (&Mesh2dHandle, &mut Handle<ColorMaterial>),
+        (With<ShapeStroke>, Without<Shape>),
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: PaintMaterials,
+) {
+    for (shape, Mesh2dHandle(fill_mesh), mut fill_material, children) in &mut shape_q {
+        let points = outline(shape.kind, shape.size, shape.corner_radius);
+        if let Some(mesh) = meshes.get_mut(fill_mesh) {
+            *mesh = polygon_mesh(&points);
+        }
+        let fill = shape.fill.unwrap_or(Color::NONE);
+        *fill_material = materials.get_or_add(fill);
+        for child in children {
+            let Ok((Mesh2dHandle(stroke_mesh), mut material)) = stroke_q.get_mut(*child) else {
+                continue;
+            };
+            if let Some(mesh) = meshes.get_mut(stroke_mesh) {
+                *mesh = stroke_mesh_of(&points, shape.stroke_width);
+            }
+            *material = materials.get_or_add(shape.stroke);
+        }
+    }
+}
+
+/// Points along the outline of a shape of `size` around the origin, counterclockwise.
+/// No two neighboring points are the same, the stroke would have no direction between them
+pub fn outline(kind: ShapeKind, size: Vec2, corner_radius: f32) -> Vec<Vec2> {
+    let mut points = outline_points(kind, size, corner_radius);
+    points.dedup_by(|a, b| a.distance_squared(*b) < f32::EPSILON);
+    while points.len() > 1 && points[0].distance_squared(points[points.len() - 1]) < f32::EPSILON {
+        points.pop();
+    }
+    points
+}
+
+/// Points along the outline of a shape, where arcs of rounded corners meet they can repeat
+fn outline_points(kind: ShapeKind, size: Vec2, corner_radius: f32) -> Vec<Vec2> {
+    let half = size.abs() / 2.0;
+    match kind {
+        ShapeKind::Ellipse => (0..64)
+            .map(|i| Vec2::from_angle(TAU * i as f32 / 64.0) * half)
+            .collect(),
+        ShapeKind::Rectangle => {
+            let radius = corner_radius.clamp(0.0, half.x.min(half.y));
+            let corners = [
+                Vec2::new(1.0, 1.0),
+                Vec2::new(-1.0, 1.0),
+                Vec2::new(-1.0, -1.0),
+                Vec2::new(1.0, -1.0),
+            ];
+            if radius == 0.0 {
+                return corners.iter().map(|corner| *corner * half).collect();
+            }
+            corners
+                .iter()
+                .enumerate()
+                .flat_map(|(i, corner)| {
+                    let center = *corner * (half - radius);
+                    (0..=8).map(move |step| {
+                        let angle = FRAC_PI_2 * (i as f32 + step as f32 / 8.0);
+                        center + Vec2::from_angle(angle) * radius
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+fn empty_mesh() -> Mesh {
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
+}
+
+/// A fan of triangles filling the convex polygon `points`
+fn polygon_mesh(points: &[Vec2]) -> Mesh {
+    let mut positions = vec![[0.0; 3]];
+    positions.extend(points.iter().map(|p| [p.x, p.y, 0.0]));
+    let n = points.len() as u32;
+    let indices = (0..n).flat_map(|i| [0, i + 1, (i + 1) % n + 1]).collect();
+    empty_mesh()
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_indices(Indices::U32(indices))
+}
+
+/// How far corners of outlines may reach out, relative to half their width
+const MITER_LIMIT: f32 = 4.0;
+
+/// A band of `width` centered on the closed outline `points`
+fn stroke_mesh_of(points: &[Vec2], width: f32) -> Mesh {
+    let n = points.len();
+    let mut positions = Vec::with_capacity(n * 2);
+    for i in 0..n {
+        let (prev, here, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+        let normal_of = |from: Vec2, to: Vec2| (to - from).perp().normalize_or_zero() * -1.0;
+        let normal = (normal_of(prev, here) + normal_of(here, next)).normalize_or_zero();
+        // The band keeps its width along both edges, up to a limit for sharp corners
+        let miter = normal / normal.dot(normal_of(prev, here)).max(1.0 / MITER_LIMIT);
+        for side in [1.0, -1.0] {
+            let p = here + miter * side * width / 2.0;
+            positions.push([p.x, p.y, 0.0]);
+        }
+    }
+    let n = n as u32;
+    let indices = (0..n)
+        .flat_map(|i| {
+            let (outer, inner) = (i * 2, i * 2 + 1);
+            let (next_outer, next_inner) = ((i + 1) % n * 2, (i + 1) % n * 2 + 1);
+            [outer, inner, next_outer, next_outer, inner, next_inner]
+        })
+        .collect();
+    empty_mesh()
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_indices(Indices::U32(indices))
+}
+
+fn toggle_fill(mut user_q: Query<&mut User>, mut status: EventWriter<Status>) {
+    let config = &mut user_q.single_mut().current_config;
+    config.shape_filled = !config.shape_filled;
+    status.send(Status::info(if config.shape_filled {
+        "Shapes are filled"
+    } else {
+        "Shapes are outlined"
+    }));
+}
+
+fn swap_colors(mut user_q: Query<&mut User>) {
+    let config = &mut user_q.single_mut().current_config;
+    std::mem::swap(&mut config.paint_color, &mut config.stroke_color);
+}
+
+/// `[` and `]` change the stroke width, with `Shift` the corner radius
+pub fn change_stroke(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut user_q: Query<&mut User>,
+    mut status: EventWriter<Status>,
+) {
+    let change = if keys.just_pressed(KeyCode::BracketRight) {
+        1.0
+    } else if keys.just_pressed(KeyCode::BracketLeft) {
+        -1.0
+    } else {
+        return;
+    };
+    let config = &mut user_q.single_mut().current_config;
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        config.corner_radius = (config.corner_radius + change * 2.0).max(0.0);
+        status.send(Status::info(format!(
+            "Corner radius: {}",
+            config.corner_radius
+        )));
+    } else {
+        config.stroke_width = (config.stroke_width + change).max(0.0);
+        status.send(Status::info(format!(
+            "Stroke width: {}",
+            config.stroke_width
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fully_rounded_outlines_have_no_repeated_points() {
+        for size in [Vec2::new(40.0, 20.0), Vec2::splat(20.0)] {
+            let points = outline(ShapeKind::Rectangle, size, 10.0);
+            let n = points.len();
+            for i in 0..n {
+                assert_ne!(points[i], points[(i + 1) % n], "{size} at {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn strokes_of_fully_rounded_outlines_keep_their_width() {
+        let points = outline(ShapeKind::Rectangle, Vec2::new(40.0, 20.0), 10.0);
+        let mesh = stroke_mesh_of(&points, 2.0);
+        let Some(bevy::render::mesh::VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the stroke has no positions");
+        };
+        for pair in positions.chunks(2) {
+            let (outer, inner) = (Vec3::from(pair[0]), Vec3::from(pair[1]));
+            assert!((outer.distance(inner) - 2.0).abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::layers::Pickable;
+use crate::selection::{Selected, SelectedCells};
+use crate::status::Status;
+use crate::table::{set_cell_text, Cell, CellContents, CellQueries, TableHead};
+use crate::{ctrl_just_pressed, shift_pressed, typing, AppState};
+use bevy::prelude::*;
+
+pub struct SortPlugin;
+
+/// This plugin is responsible for sorting the rows of tables by the text of one of their columns.
+/// `Ctrl+Shift+Down` sorts the selected tables ascending and `Ctrl+Shift+Up` descending, by the
+/// first column of the selected range of cells or else by the first column that is not a header.
+/// Header rows stay pinned at the top, header columns move along with their rows
+impl Plugin for SortPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            sort_rows
+                .run_if(
+                    ctrl_just_pressed(KeyCode::ArrowDown)
+                        .or_else(ctrl_just_pressed(KeyCode::ArrowUp)),
+                )
+                .run_if(shift_pressed)
+                .run_if(not(typing))
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// Orders texts like spreadsheets do: numbers by their value before any other text, which is
+/// ordered ignoring case
+fn compare_texts(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.to_lowercase().cmp(&b.to_lowercase()),
+    }
+}
+
+/// The order of `keys` when sorted, empty keys come last either way and equal keys keep their
+/// order
+fn sorted_order(keys: &[&str], descending: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (keys[*a], keys[*b]);
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) if descending => compare_texts(b, a),
+            (false, false) => compare_texts(a, b),
+        }
+    });
+    order
+}
+
+fn sort_rows(
+    keys: Res<ButtonInput<KeyCode>>,
+    selected_cells: Option<Res<SelectedCells>>,
+    selected_q: Query<(Entity, &TableHead, &Children), (With<Selected>, Pickable)>,
+    mut cell_q: CellQueries,
+    mut status: EventWriter<Status>,
+) {
+    let descending = keys.just_pressed(KeyCode::ArrowUp);
+    let direction = if descending {
+        "descending"
+    } else {
+        "ascending"
+    };
+    let range = selected_cells.filter(|range| selected_q.contains(range.table));
+
+    for (id, table_head, children) in &selected_q {
+        let column = match &range {
+            Some(range) if range.table == id => range.min().column,
+            Some(_) => continue,
+            None => table_head.header_columns,
+        };
+        if column >= table_head.num_columns() || table_head.header_rows >= table_head.num_rows() {
+            continue;
+        }
+
+        let contents: HashMap<Cell, CellContents> = cell_q.read(children).collect();
+        let rows: Vec<u32> = (table_head.header_rows..table_head.num_rows()).collect();
+        let sort_keys: Vec<&str> = rows
+            .iter()
+            .map(|row| {
+                contents
+                    .get(&Cell { row: *row, column })
+                    .map_or("", |contents| contents.text.as_str())
+            })
+            .collect();
+        // The row that ends up at each row below the headers
+        let moved_from: HashMap<u32, u32> = sorted_order(&sort_keys, descending)
+            .into_iter()
+            .enumerate()
+            .map(|(i, from)| (rows[i], rows[from]))
+            .collect();
+
+        cell_q.edit(children, |cell, mut bg_color, mut text| {
+            let Some(from) = moved_from
+                .get(&cell.row)
+                .filter(|from| **from != cell.row)
+                .and_then(|from| {
+                    contents.get(&Cell {
+                        row: *from,
+                        column: cell.column,
+                    })
+                })
+            else {
+                return;
+            };
+            if *bg_color != from.bg_color {
+                *bg_color = from.bg_color.clone();
+            }
+            if text.sections.first().is_some_and(|s| s.value != from.text) {
+                set_cell_text(&mut text, from.text.clone());
+            }
+        });
+
+        status.send(Status::info(format!(
+            "Sorted {} rows {} by column {}, keeping {} header rows in place",
+            rows.len(),
+            direction,
+            column + 1,
+            table_head.header_rows
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted<'a>(keys: &[&'a str], descending: bool) -> Vec<&'a str> {
+        sorted_order(keys, descending)
+            .into_iter()
+            .map(|i| keys[i])
+            .collect()
+    }
+
+    #[test]
+    fn numbers_before_text() {
+        let keys = ["b", "10", "A", "", "9", "c"];
+        assert_eq!(sorted(&keys, false), ["9", "10", "A", "b", "c", ""]);
+        assert_eq!(sorted(&keys, true), ["c", "b", "A", "10", "9", ""]);
+    }
+
+    #[test]
+    fn equal_keys_keep_their_order() {
+        let keys = ["x", "a", "X", "a"];
+        assert_eq!(sorted_order(&keys, false), [1, 3, 0, 2]);
+    }
+}
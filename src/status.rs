@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+
+use crate::guides::RULER_SIZE;
+use crate::AppState;
+
+pub struct StatusPlugin;
+
+/// This plugin is responsible for the status line at the bottom of the window, which shows the
+/// feedback of the last command, like the setting it changed or why it did nothing.
+/// Messages are logged as well and fade out after a few seconds
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Status>()
+            .add_systems(OnEnter(AppState::Running), spawn_status_line)
+            .add_systems(
+                Update,
+                (show_status, fade_status)
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// Feedback for the user, shown in the status line
+#[derive(Event, Clone, Debug)]
+pub struct Status {
+    pub message: String,
+    /// Warnings tell why something did not work, they are shown in another color
+    pub warning: bool,
+}
+impl Status {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            warning: false,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            warning: true,
+        }
+    }
+}
+
+/// The text of the status line, with the time its message was shown at
+#[derive(Component)]
+struct StatusLine {
+    shown_at: f32,
+}
+
+/// Messages stay for this many seconds
+const SHOWN_FOR: f32 = 4.0;
+/// and then fade out over this many seconds
+const FADE_OUT: f32 = 1.0;
+
+const INFO_COLOR: Color = Color::WHITE;
+const WARNING_COLOR: Color = Color::srgb(0.95, 0.6, 0.3);
+
+fn spawn_status_line(mut cmd: Commands) {
+    cmd.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: INFO_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.),
+            left: Val::Px(RULER_SIZE + 5.),
+            ..default()
+        }),
+        StatusLine {
+            shown_at: f32::NEG_INFINITY,
+        },
+    ));
+}
+
+fn show_status(
+    time: Res<Time>,
+    mut status_events: EventReader<Status>,
+    mut line_q: Query<(&mut Text, &mut StatusLine)>,
+) {
+    let Some(status) = status_events.read().inspect(|status| log(status)).last() else {
+        return;
+    };
+    for (mut text, mut line) in &mut line_q {
+        let section = &mut text.sections[0];
+        section.value.clone_from(&status.message);
+        section.style.color = if status.warning {
+            WARNING_COLOR
+        } else {
+            INFO_COLOR
+        };
+        line.shown_at = time.elapsed_seconds();
+    }
+}
+
+fn log(status: &Status) {
+    if status.warning {
+        warn!("{}", status.message);
+    } else {
+        info!("{}", status.message);
+    }
+}
+
+fn fade_status(time: Res<Time>, mut line_q: Query<(&mut Text, &StatusLine)>) {
+    for (mut text, line) in &mut line_q {
+        let faded = (time.elapsed_seconds() - line.shown_at - SHOWN_FOR) / FADE_OUT;
+        let alpha = 1.0 - faded.clamp(0.0, 1.0);
+        if text.sections[0].style.color.alpha() != alpha {
+            text.sections[0].style.color.set_alpha(alpha);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use bevy::ecs::system::SystemParam;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::sprite::{Anchor, MaterialMesh2dBundle};
+use bevy::text::{Text2dBounds, TextLayoutInfo};
+
+use crate::actions::{finish_actions, maintain_actions, Actions, Preview};
+use crate::editing::{TextEditing, TextLayouts};
+use crate::eraser::distance_to_segment;
+use crate::layers::Pickable;
+use crate::line::Polyline;
+use crate::paint::PaintMaterials;
+use crate::pen::Stroke;
+use crate::placed_image::PlacedImage;
+use crate::player::{Tool, User};
+use crate::shape::{Shape, ShapeKind};
+use crate::table::{table_at, TableHead};
+use crate::text_box::TextBox;
+use crate::{
+    tool_selected, typing, using_tool, AppState, CanvasSet, DoubleClicks, WhenActionDoneSet,
+};
+
+pub struct StickyNotePlugin;
+
+/// This plugin is responsible for sticky notes, colored notes whose text wraps at their width
+/// and which grow downwards with their text.
+/// Notes are placed with `Tool::Note` or by double clicking the empty canvas with `Tool::Select`,
+/// clicking a note with `Tool::Note` or double clicking it edits its text.
+/// With `Tool::Select` notes are moved by dragging them and resized by dragging their bottom right
+/// corner. `Tab` cycles the color of the edited note and of notes placed from now on
+impl Plugin for StickyNotePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                place_note
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Note))
+                    .after(maintain_actions),
+                (place_note_on_double_click, drag_notes)
+                    .chain()
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Select))
+                    .after(maintain_actions),
+                resize_handles
+                    .in_set(CanvasSet)
+                    .run_if(tool_selected(Tool::Select)),
+                edit_new_note
+                    .in_set(WhenActionDoneSet)
+                    .before(finish_actions),
+                (
+                    cycle_note_color
+                        .run_if(input_just_pressed(KeyCode::Tab))
+                        .run_if(typing),
+                    grow_notes,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// A sticky note, positioned at its top left like tables
+#[derive(Component, Debug)]
+pub struct StickyNote {
+    pub width: f32,
+    /// The note grows beyond this height when its text does not fit
+    pub min_height: f32,
+    /// Height the note is shown with
+    pub height: f32,
+    pub color: Color,
+}
+impl StickyNote {
+    pub fn bounds(&self, transform: &GlobalTransform) -> Rect {
+        let top_left = transform.translation().truncate();
+        Rect::from_corners(top_left, top_left + Vec2::new(self.width, -self.height))
+    }
+}
+
+/// Tag for the colored rectangle behind the text of a note
+#[derive(Component)]
+struct NoteBackground;
+
+/// Tag for the text of a note
+#[derive(Component)]
+pub struct NoteText;
+
+/// Colors notes can have, cycled through with `Tab`
+pub const NOTE_COLORS: [Color; 5] = [
+    Color::srgb(1.0, 0.92, 0.45),
+    Color::srgb(1.0, 0.65, 0.75),
+    Color::srgb(0.65, 0.9, 0.6),
+    Color::srgb(0.6, 0.8, 1.0),
+    Color::srgb(1.0, 0.75, 0.45),
+];
+const NOTE_SIZE: Vec2 = Vec2::new(160.0, 120.0);
+/// Space between the edge of a note and its text
+const PADDING: f32 = 8.0;
+/// Size of the corner that resizes a note when it is dragged
+const HANDLE_SIZE: f32 = 10.0;
+
+fn spawn_note(cmd: &mut Commands, pos: Vec2, user: &User, background: Handle<ColorMaterial>) {
+    let config = &user.current_config;
+    cmd.spawn((
+        StickyNote {
+            width: NOTE_SIZE.x,
+            min_height: NOTE_SIZE.y,
+            height: NOTE_SIZE.y,
+            color: config.note_color,
+        },
+        Preview,
+        SpatialBundle::from_transform(Transform::from_translation(pos.extend(0.0))),
+    ))
+    .with_children(|note| {
+        note.spawn((
+            NoteBackground,
+            MaterialMesh2dBundle {
+                mesh: config.cell_mesh.clone().into(),
+                material: background,
+                transform: Transform::from_translation(
+                    (NOTE_SIZE * Vec2::new(0.5, -0.5)).extend(0.0),
+                )
+                .with_scale(NOTE_SIZE.extend(1.0)),
+                ..default()
+            },
+        ));
+        note.spawn((
+            NoteText,
+            Text2dBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font_size: config.text_font_size,
+                        color: Color::BLACK,
+                        ..default()
+                    },
+                ),
+                text_anchor: Anchor::TopLeft,
+                text_2d_bounds: Text2dBounds {
+                    size: Vec2::new(NOTE_SIZE.x - 2.0 * PADDING, f32::INFINITY),
+                },
+                transform: Transform::from_xyz(PADDING, -PADDING, 0.1),
+                ..default()
+            },
+        ));
+    });
+}
+
+/// The topmost note at `pos`
+fn note_at<'a>(
+    notes: impl IntoIterator<Item = (Entity, &'a StickyNote, &'a GlobalTransform)>,
+    pos: Vec2,
+) -> Option<Entity> {
+    notes
+        .into_iter()
+        .filter(|(_, note, transform)| note.bounds(transform).contains(pos))
+        .max_by(|(.., a), (.., b)| a.translation().z.total_cmp(&b.translation().z))
+        .map(|(id, ..)| id)
+}
+
+/// Everything on the canvas besides notes that a double click can land on
+#[derive(SystemParam)]
+struct CanvasItems<'w, 's> {
+    table_head_q: Query<'w, 's, (Entity, &'static TableHead, &'static GlobalTransform), Pickable>,
+    shape_q: Query<'w, 's, (&'static Shape, &'static GlobalTransform), Pickable>,
+    stroke_q: Query<'w, 's, &'static Stroke, Pickable>,
+    line_q: Query<'w, 's, &'static Polyline, Pickable>,
+    text_box_q: Query<'w, 's, (Entity, &'static Text), (With<TextBox>, Pickable)>,
+    image_q: Query<'w, 's, (&'static PlacedImage, &'static GlobalTransform), Pickable>,
+    layouts: TextLayouts<'w, 's>,
+}
+impl CanvasItems<'_, '_> {
+    fn any_at(&self, pos: Vec2) -> bool {
+        let on_points = |points: &[Vec2], width: f32| match points {
+            [point] => point.distance(pos) <= width / 2.0,
+            _ => points
+                .windows(2)
+                .any(|pair| distance_to_segment(pos, pair[0], pair[1]) <= width / 2.0),
+        };
+        let on_shape = |(shape, transform): (&Shape, &GlobalTransform)| {
+            let local = (pos - transform.translation().truncate()).abs() / (shape.size / 2.0);
+            match shape.kind {
+                ShapeKind::Rectangle => local.max_element() <= 1.0,
+                ShapeKind::Ellipse => local.length() <= 1.0,
+            }
+        };
+        let on_text_box = |(id, text): (Entity, &Text)| {
+            let font_size = text.sections.first().map_or(0.0, |s| s.style.font_size);
+            self.layouts
+                .get(id, font_size)
+                .is_some_and(|(layout, size)| layout.bounds(size).contains(pos))
+        };
+        table_at(&self.table_head_q, pos).is_some()
+            || self.shape_q.iter().any(on_shape)
+            || self
+                .stroke_q
+                .iter()
+                .any(|stroke| on_points(&stroke.points, stroke.width))
+            || self
+                .line_q
+                .iter()
+                .any(|line| on_points(&line.points, line.width))
+            || self.text_box_q.iter().any(on_text_box)
+            || self
+                .image_q
+                .iter()
+                .any(|(image, transform)| image.bounds(transform).contains(pos))
+    }
+}
+
+/// The notes that are not locked, with what is needed to edit their text
+#[derive(SystemParam)]
+struct EditableNotes<'w, 's> {
+    editing: ResMut<'w, TextEditing>,
+    layouts: TextLayouts<'w, 's>,
+    note_q: Query<'w, 's, (Entity, &'static StickyNote, &'static GlobalTransform), Pickable>,
+    children_q: Query<'w, 's, &'static Children>,
+    text_q: Query<'w, 's, &'static Text, With<NoteText>>,
+}
+impl EditableNotes<'_, '_> {
+    /// The topmost note at `pos`
+    fn at(&self, pos: Vec2) -> Option<Entity> {
+        note_at(&self.note_q, pos)
+    }
+
+    /// Whether the text of `note` is being edited
+    fn is_edited(&self, note: Entity) -> bool {
+        self.children_q
+            .get(note)
+            .is_ok_and(|children| self.editing.target.is_some_and(|id| children.contains(&id)))
+    }
+
+    /// Starts editing the text of `note` with the caret closest to `pos`
+    fn edit(&mut self, note: Entity, pos: Vec2) {
+        let Some((id, text)) = self
+            .children_q
+            .get(note)
+            .into_iter()
+            .flatten()
+            .find_map(|child| self.text_q.get(*child).ok().map(|text| (*child, text)))
+        else {
+            return;
+        };
+        let Some(section) = text.sections.first() else {
+            return;
+        };
+        let caret = self
+            .layouts
+            .get(id, section.style.font_size)
+            .map_or(section.value.len(), |(layout, _)| {
+                layout.index_at(&section.value, pos)
+            });
+        self.editing.start(id, caret);
+    }
+}
+
+/// Clicking edits the note under the cursor, or places a new one
+fn place_note(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    user_q: Query<&User>,
+    mut notes: EditableNotes,
+    mut materials: PaintMaterials,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if let Some(note) = notes.at(actions.from) {
+        notes.edit(note, actions.from);
+        return;
+    }
+    notes.editing.stop();
+    let user = user_q.single();
+    let background = materials.get_or_add(user.current_config.note_color);
+    spawn_note(&mut cmd, actions.from, user, background);
+}
+
+/// Double clicking the empty canvas places a note, double clicking a note edits it
+fn place_note_on_double_click(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    mut clicks: DoubleClicks,
+    user_q: Query<&User>,
+    mut notes: EditableNotes,
+    items: CanvasItems,
+    mut materials: PaintMaterials,
+) {
+    if !clicks.just_clicked() {
+        return;
+    }
+    let double_click = clicks.double_click(None);
+    let note = notes.at(actions.from);
+    if !double_click {
+        // Clicking anywhere but the edited note stops editing it
+        if !note.is_some_and(|note| notes.is_edited(note)) {
+            notes.editing.stop();
+        }
+        return;
+    }
+
+    if let Some(note) = note {
+        notes.edit(note, actions.from);
+    } else if !items.any_at(actions.from) {
+        let user = user_q.single();
+        let background = materials.get_or_add(user.current_config.note_color);
+        spawn_note(&mut cmd, actions.from, user, background);
+    }
+}
+
+/// Notes are edited right after they are placed
+fn edit_new_note(
+    mut editing: ResMut<TextEditing>,
+    note_q: Query<&Children, (With<StickyNote>, With<Preview>)>,
+    text_q: Query<Entity, With<NoteText>>,
+) {
+    for children in &note_q {
+        if let Some(id) = text_q.iter_many(children).next() {
+            editing.start(id, 0);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum NoteDrag {
+    /// Moving the note, from where it was
+    Move(Entity, Vec3),
+    /// Resizing the note, from the size it had
+    Resize(Entity, Vec2),
+}
+
+/// Dragging a note moves it, dragging its bottom right corner resizes it
+fn drag_notes(
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mut dragged: Local<Option<NoteDrag>>,
+    mut note_q: Query<(Entity, &mut StickyNote, &mut Transform, &GlobalTransform), Pickable>,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        let notes = note_q
+            .iter()
+            .map(|(id, note, _, transform)| (id, note, transform));
+        *dragged = note_at(notes, actions.from).and_then(|id| {
+            let (_, note, transform, _) = note_q.get(id).ok()?;
+            let bottom_right =
+                transform.translation.truncate() + Vec2::new(note.width, -note.height);
+            let in_corner = (actions.from - bottom_right).abs().max_element() < HANDLE_SIZE;
+            Some(if in_corner {
+                NoteDrag::Resize(id, Vec2::new(note.width, note.height))
+            } else {
+                NoteDrag::Move(id, transform.translation)
+            })
+        });
+        return;
+    }
+
+    let delta = actions.to - actions.from;
+    match *dragged {
+        Some(NoteDrag::Move(id, origin)) => {
+            if let Ok((_, _, mut transform, _)) = note_q.get_mut(id) {
+                let z = transform.translation.z;
+                transform.translation = (origin.truncate() + delta).extend(z);
+            }
+        }
+        Some(NoteDrag::Resize(id, size)) => {
+            if let Ok((_, mut note, ..)) = note_q.get_mut(id) {
+                let min = Vec2::splat(2.0 * PADDING + HANDLE_SIZE);
+                let size = (size + delta * Vec2::new(1.0, -1.0)).max(min);
+                note.width = size.x;
+                note.min_height = size.y;
+            }
+        }
+        None => {}
+    }
+}
+
+/// Keeps the background of notes behind all of their text and wraps the text at their width
+fn grow_notes(
+    mut note_q: Query<(&mut StickyNote, &Children)>,
+    mut background_q: Query<(&mut Transform, &mut Handle<ColorMaterial>), With<NoteBackground>>,
+    mut text_q: Query<(&TextLayoutInfo, &mut Text2dBounds), With<NoteText>>,
+    mut materials: PaintMaterials,
+) {
+    for (mut note, children) in &mut note_q {
+        let mut height = note.min_height;
+        let mut text_iter = text_q.iter_many_mut(children);
+        while let Some((layout, mut bounds)) = text_iter.fetch_next() {
+            let width = note.width - 2.0 * PADDING;
+            if bounds.size.x != width {
+                bounds.size.x = width;
+            }
+            height = height.max(layout.logical_size.y + 2.0 * PADDING);
+        }
+        if note.height != height {
+            note.height = height;
+        }
+
+        let size = Vec2::new(note.width, note.height);
+        let mut background_iter = background_q.iter_many_mut(children);
+        while let Some((mut transform, mut material)) = background_iter.fetch_next() {
+            if transform.scale.truncate() != size {
+                transform.translation = (size * Vec2::new(0.5, -0.5)).extend(0.0);
+                transform.scale = size.extend(1.0);
+            }
+            let background = materials.get_or_add(note.color);
+            if *material != background {
+                *material = background;
+            }
+        }
+    }
+}
+
+/// Outlines the corners that resize notes
+fn resize_handles(mut gizmos: Gizmos, note_q: Query<(&StickyNote, &GlobalTransform), Pickable>) {
+    for (note, transform) in &note_q {
+        let bounds = note.bounds(transform);
+        let bottom_right = Vec2::new(bounds.max.x, bounds.min.y);
+        gizmos.rect_2d(
+            bottom_right + Vec2::new(-0.5, 0.5) * HANDLE_SIZE,
+            0.0,
+            Vec2::splat(HANDLE_SIZE),
+            Color::srgb(0.3, 0.3, 0.3),
+        );
+    }
+}
+
+/// Gives the edited note, and notes placed from now on, the next preset color
+fn cycle_note_color(
+    editing: Res<TextEditing>,
+    mut user_q: Query<&mut User>,
+    parent_q: Query<&Parent, With<NoteText>>,
+    mut note_q: Query<&mut StickyNote>,
+) {
+    let Some(mut note) = editing
+        .target
+        .and_then(|id| parent_q.get(id).ok())
+        .and_then(|parent| note_q.get_mut(parent.get()).ok())
+    else {
+        return;
+    };
+    let next = NOTE_COLORS
+        .iter()
+        .position(|color| *color == note.color)
+        .map_or(0, |i| (i + 1) % NOTE_COLORS.len());
+    note.color = NOTE_COLORS[next];
+    user_q.single_mut().current_config.note_color = note.color;
+}
@@ -0,0 +1,215 @@
+use bevy::color::Luminance;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+
+use crate::paint::{cell_color, PaintMaterials};
+use crate::palette::Palette;
+use crate::player::{User, UserConfig};
+use crate::selection::Selected;
+use crate::status::Status;
+use crate::table::{spawn_table, Cell, CellContents, TableHead};
+use crate::{alt_pressed, typing, AppState};
+
+pub struct SymbolPlugin;
+
+/// This plugin is responsible for printable pattern charts.
+/// `Alt+G` draws the symbol of its palette color into every painted cell of the selected tables,
+/// `Alt+L` places a legend table next to every selected table
+impl Plugin for SymbolPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                (
+                    toggle_symbols.run_if(input_just_pressed(KeyCode::KeyG)),
+                    make_legend.run_if(input_just_pressed(KeyCode::KeyL)),
+                )
+                    .run_if(alt_pressed)
+                    .run_if(not(typing)),
+                (show_symbols, hide_symbols),
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// Tables with this component show the symbols of their cells
+#[derive(Component)]
+pub struct ShowSymbols;
+
+/// Tag for the symbol entity of a [`Cell`], drawn above its text
+#[derive(Component)]
+pub struct CellSymbol;
+
+/// The palette color a cell is painted with, cells that were never painted have none
+pub fn palette_index(
+    bg_color: &Handle<ColorMaterial>,
+    config: &UserConfig,
+    palette: &Palette,
+    materials: &Assets<ColorMaterial>,
+) -> Option<usize> {
+    if *bg_color == config.table_bg_color || *bg_color == config.table_header_bg_color {
+        return None;
+    }
+    palette.position(cell_color(bg_color, materials))
+}
+
+fn toggle_symbols(
+    mut cmd: Commands,
+    selected_q: Query<(Entity, Has<ShowSymbols>), (With<TableHead>, With<Selected>)>,
+) {
+    for (id, shown) in &selected_q {
+        if shown {
+            cmd.entity(id).remove::<ShowSymbols>();
+        } else {
+            cmd.entity(id).insert(ShowSymbols);
+        }
+    }
+}
+
+/// Keeps the symbol of every cell of tables with [`ShowSymbols`] in line with its color
+fn show_symbols(
+    mut cmd: Commands,
+    palette: Res<Palette>,
+    materials: Res<Assets<ColorMaterial>>,
+    user: Query<&User>,
+    table_q: Query<&Children, (With<TableHead>, With<ShowSymbols>)>,
+    cell_q: Query<(Entity, &Handle<ColorMaterial>, &Transform, &Children), With<Cell>>,
+    mut symbol_q: Query<(Entity, &mut Text), With<CellSymbol>>,
+) {
+    let config = &user.single().current_config;
+    for children in &table_q {
+        for (id, bg_color, transform, cell_children) in cell_q.iter_many(children) {
+            let symbol = palette_index(bg_color, config, &palette, &materials)
+                .map(|i| palette.colors[i].symbol.to_string());
+            let color = if cell_color(bg_color, &materials).luminance() > 0.5 {
+                Color::BLACK
+            } else {
+                Color::WHITE
+            };
+
+            let mut symbols = symbol_q.iter_many_mut(cell_children);
+            match (symbols.fetch_next(), symbol) {
+                (Some((_, mut text)), Some(symbol)) => {
+                    let section = &text.sections[0];
+                    if section.value != symbol || section.style.color != color {
+                        let section = &mut text.sections[0];
+                        section.value = symbol;
+                        section.style.color = color;
+                    }
+                }
+                (Some((symbol_id, _)), None) => {
+                    cmd.entity(id).remove_children(&[symbol_id]);
+                    cmd.entity(symbol_id).despawn_recursive();
+                }
+                (None, Some(symbol)) => {
+                    cmd.entity(id).with_children(|s_cmd| {
+                        s_cmd.spawn((
+                            CellSymbol,
+                            Text2dBundle {
+                                text: Text::from_section(
+                                    symbol,
+                                    TextStyle {
+                                        font_size: config.table_font_size,
+                                        color,
+                                        ..default()
+                                    },
+                                ),
+                                transform: Transform {
+                                    translation: Vec3::Z * 0.2,
+                                    scale: transform.scale.truncate().recip().extend(1.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                        ));
+                    });
+                }
+                (None, None) => (),
+            }
+        }
+    }
+}
+
+fn hide_symbols(
+    mut cmd: Commands,
+    mut removed: RemovedComponents<ShowSymbols>,
+    table_q: Query<&Children, With<TableHead>>,
+    cell_q: Query<&Children, With<Cell>>,
+    symbol_q: Query<Entity, With<CellSymbol>>,
+) {
+    for table in removed.read() {
+        let Ok(children) = table_q.get(table) else {
+            continue;
+        };
+        for cell_children in cell_q.iter_many(children) {
+            for symbol in symbol_q.iter_many(cell_children) {
+                cmd.entity(symbol).despawn_recursive();
+            }
+        }
+    }
+}
+
+/// Places a table listing the symbol, color, name and number of cells of every palette color
+/// used by a selected table to its right, the legends become the selection
+fn make_legend(
+    mut cmd: Commands,
+    palette: Res<Palette>,
+    mut materials: PaintMaterials,
+    mut status: EventWriter<Status>,
+    user: Query<&User>,
+    selected_q: Query<(Entity, &TableHead, &GlobalTransform, &Children), With<Selected>>,
+    cell_q: Query<&Handle<ColorMaterial>, With<Cell>>,
+) {
+    let config = &user.single().current_config;
+    for (id, table_head, transform, children) in &selected_q {
+        let mut counts = vec![0; palette.colors.len()];
+        for bg_color in cell_q.iter_many(children) {
+            if let Some(i) = palette_index(bg_color, config, &palette, &materials) {
+                counts[i] += 1;
+            }
+        }
+        let used: Vec<usize> = (0..counts.len()).filter(|i| counts[*i] > 0).collect();
+        if used.is_empty() {
+            status.send(Status::warning("No palette colors to put into a legend"));
+            continue;
+        }
+
+        let template = TableHead::new(1, 0).with_column_widths(vec![
+            config.cell_dimensions.x * 2.5,
+            config.cell_dimensions.x * 2.0,
+            config.cell_dimensions.x * 5.0,
+            config.cell_dimensions.x * 2.5,
+        ]);
+        let translation =
+            transform.translation() + Vec3::X * (table_head.size().x + config.cell_dimensions.x);
+        let size = UVec2::new(4, used.len() as u32 + 1);
+        let legend = spawn_table(
+            &mut cmd,
+            translation,
+            &template,
+            size,
+            config,
+            |head, cell| {
+                let mut contents = CellContents::empty(head, &cell, config);
+                let Some(row) = cell.row.checked_sub(1) else {
+                    contents.text =
+                        ["Symbol", "Color", "Name", "Count"][cell.column as usize].into();
+                    return contents;
+                };
+                let i = used[row as usize];
+                let palette_color = &palette.colors[i];
+                match cell.column {
+                    0 => contents.text = palette_color.symbol.to_string(),
+                    1 => contents.bg_color = materials.get_or_add(palette_color.color),
+                    2 => contents.text = palette_color.name.clone(),
+                    _ => contents.text = counts[i].to_string(),
+                }
+                contents
+            },
+        );
+        cmd.entity(legend).insert(Selected);
+        cmd.entity(id).remove::<Selected>();
+    }
+}
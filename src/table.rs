@@ -2,7 +2,11 @@ use std::f32::consts::FRAC_PI_2;
 
 use crate::actions::{finish_actions, maintain_actions, Actions, Preview};
 use crate::player::{Tool, User, UserConfig};
-use crate::{using_tool, CanvasSet, WhenActionDoneSet};
+use crate::selection::Selected;
+use crate::status::Status;
+use crate::{typing, using_tool, AppState, CanvasSet, WhenActionDoneSet};
+use bevy::ecs::system::SystemParam;
+use bevy::input::common_conditions::input_just_pressed;
 use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 
@@ -18,6 +22,11 @@ impl Plugin for TablePlugin {
                     .in_set(CanvasSet)
                     .run_if(using_tool(Tool::Table))
                     .after(maintain_actions),
+                change_header_count
+                    .run_if(input_just_pressed(KeyCode::KeyH))
+                    .run_if(not(typing))
+                    .run_if(in_state(AppState::Running)),
+                style_header_cells.after(make_table),
                 cleanup_empty_tables
                     .in_set(WhenActionDoneSet)
                     .before(finish_actions),
@@ -33,31 +42,177 @@ pub struct TableHead {
     num_columns: u32,
     cell_heights: Vec<f32>,
     cell_widths: Vec<f32>,
+    /// The first `header_rows` rows are headers, they name the columns
+    pub header_rows: u32,
+    /// The first `header_columns` columns are headers, they name the rows
+    pub header_columns: u32,
 }
 impl TableHead {
-    pub fn with_transform(t: Transform) -> (Self, Preview, SpatialBundle) {
+    /// An empty table, grow it with [`resize_table`]
+    pub fn new(header_rows: u32, header_columns: u32) -> Self {
+        Self {
+            num_rows: 0,
+            num_columns: 0,
+            cell_heights: vec![],
+            cell_widths: vec![],
+            header_rows,
+            header_columns,
+        }
+    }
+
+    /// Sets the widths of the first columns, for tables that are spawned from this one
+    pub fn with_column_widths(mut self, widths: Vec<f32>) -> Self {
+        self.cell_widths = widths;
+        self
+    }
+
+    /// Sets the heights of the first rows, for tables that are spawned from this one
+    pub fn with_row_heights(mut self, heights: Vec<f32>) -> Self {
+        self.cell_heights = heights;
+        self
+    }
+
+    pub fn column_widths(&self) -> &[f32] {
+        &self.cell_widths
+    }
+
+    pub fn row_heights(&self) -> &[f32] {
+        &self.cell_heights
+    }
+
+    pub fn with_transform(t: Transform, config: &UserConfig) -> (Self, Preview, SpatialBundle) {
         (
-            Self {
-                num_rows: 0,
-                num_columns: 0,
-                cell_heights: vec![],
-                cell_widths: vec![],
-            },
+            Self::new(config.header_rows, config.header_columns),
             Preview,
             SpatialBundle::from_transform(t),
         )
     }
+
+    pub fn is_header(&self, cell: &Cell) -> bool {
+        cell.row < self.header_rows || cell.column < self.header_columns
+    }
+
+    pub fn num_rows(&self) -> u32 {
+        self.num_rows
+    }
+
+    pub fn num_columns(&self) -> u32 {
+        self.num_columns
+    }
+
+    /// Width and height of the whole table
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            self.cell_widths.iter().sum(),
+            self.cell_heights.iter().sum(),
+        )
+    }
+
+    pub fn cell_size(&self, cell: Cell) -> Vec2 {
+        Vec2::new(
+            self.cell_widths[cell.column as usize],
+            self.cell_heights[cell.row as usize],
+        )
+    }
+
+    /// Number of columns and rows whose far edges are closest to `size`, at least one of each.
+    /// Columns and rows beyond the table are counted with the cell dimensions of `config`.
+    /// They are counted from the right or bottom of the table instead where `from_end` is set
+    pub fn size_in_cells(&self, size: Vec2, from_end: BVec2, config: &UserConfig) -> UVec2 {
+        let count = |sizes: &[f32], from_end: bool, default: f32, length: f32| {
+            let sizes: Vec<f32> = if from_end {
+                sizes.iter().rev().copied().collect()
+            } else {
+                sizes.to_vec()
+            };
+            let mut edge = 0.0;
+            let mut count = 0;
+            loop {
+                let size = sizes.get(count).copied().unwrap_or(default);
+                if size <= 0.0 || edge + size / 2.0 > length {
+                    break;
+                }
+                edge += size;
+                count += 1;
+            }
+            (count as u32).max(1)
+        };
+        let default = config.cell_dimensions;
+        UVec2::new(
+            count(&self.cell_widths, from_end.x, default.x, size.x),
+            count(&self.cell_heights, from_end.y, default.y, size.y),
+        )
+    }
+
+    /// Offsets of the edges of the columns from the left of the table, from its left to its right
+    pub fn column_edges(&self) -> Vec<f32> {
+        edges(&self.cell_widths)
+    }
+
+    /// Offsets of the edges of the rows from the top of the table, from its top to its bottom
+    pub fn row_edges(&self) -> Vec<f32> {
+        edges(&self.cell_heights)
+    }
+
+    /// Center of `cell` relative to the table position
+    pub fn cell_center(&self, cell: Cell) -> Vec2 {
+        let x_offset: f32 = self.cell_widths[..cell.column as usize].iter().sum();
+        let y_offset: f32 = self.cell_heights[..cell.row as usize].iter().sum();
+        Vec2::new(x_offset, -y_offset) + self.cell_size(cell) * Vec2::new(0.5, -0.5)
+    }
+
+    /// The cell at `local`, a position relative to the table position
+    pub fn cell_at(&self, local: Vec2) -> Option<Cell> {
+        let index_of = |sizes: &[f32], mut offset: f32| {
+            if offset < 0.0 {
+                return None;
+            }
+            sizes.iter().position(|size| {
+                offset -= size;
+                offset < 0.0
+            })
+        };
+        Some(Cell {
+            row: index_of(&self.cell_heights, -local.y)? as u32,
+            column: index_of(&self.cell_widths, local.x)? as u32,
+        })
+    }
+
+    /// The cell closest to `local`, the table must not be empty
+    pub fn clamped_cell_at(&self, local: Vec2) -> Cell {
+        let size = self.size();
+        let clamped = Vec2::new(
+            local.x.clamp(0.0, size.x - f32::EPSILON * size.x.max(1.0)),
+            local.y.clamp(-size.y + f32::EPSILON * size.y.max(1.0), 0.0),
+        );
+        self.cell_at(clamped).unwrap_or(Cell {
+            row: self.num_rows.saturating_sub(1),
+            column: self.num_columns.saturating_sub(1),
+        })
+    }
 }
 
-#[derive(Component)]
-pub struct Cell;
+/// Running sums of `sizes`, starting at 0
+fn edges(sizes: &[f32]) -> Vec<f32> {
+    let mut edges = vec![0.0];
+    edges.extend(sizes.iter().scan(0.0, |sum, size| {
+        *sum += size;
+        Some(*sum)
+    }));
+    edges
+}
+
+/// A cell of a table, `row` and `column` are counted from the top left of the table
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub row: u32,
+    pub column: u32,
+}
 impl Cell {
     pub fn new(
         self,
         transform: Transform,
         mesh: Handle<Mesh>,
-        text: String,
-        text_color: Color,
         bg_color: Handle<ColorMaterial>,
     ) -> (Self, MaterialMesh2dBundle<ColorMaterial>) {
         (
@@ -70,23 +225,151 @@ impl Cell {
             },
         )
     }
+
+    /// The text of a cell is a child of it, `cell_scale` is undone so the text keeps its font size
+    pub fn text(
+        text: String,
+        text_color: Color,
+        font_size: f32,
+        cell_scale: Vec2,
+    ) -> (CellText, Text2dBundle) {
+        (
+            CellText,
+            Text2dBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font_size,
+                        color: text_color,
+                        ..default()
+                    },
+                ),
+                transform: Transform {
+                    translation: Vec3::Z * 0.1,
+                    scale: cell_scale.recip().extend(1.0),
+                    ..default()
+                },
+                ..default()
+            },
+        )
+    }
+}
+
+/// Tag for the text entity of a [`Cell`]
+#[derive(Component)]
+pub struct CellText;
+
+/// What is put into a cell when it gets created
+#[derive(Clone, Debug)]
+pub struct CellContents {
+    pub text: String,
+    pub bg_color: Handle<ColorMaterial>,
+}
+impl CellContents {
+    /// The contents of an empty cell, styled depending on whether it is a header
+    pub fn empty(table_head: &TableHead, cell: &Cell, config: &UserConfig) -> Self {
+        let bg_color = if table_head.is_header(cell) {
+            config.table_header_bg_color.clone()
+        } else {
+            config.table_bg_color.clone()
+        };
+        Self {
+            text: String::new(),
+            bg_color,
+        }
+    }
+}
+
+/// Queries to read and edit the contents of cells
+#[derive(SystemParam)]
+pub struct CellQueries<'w, 's> {
+    cells: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Cell,
+            &'static mut Handle<ColorMaterial>,
+            &'static Children,
+        ),
+    >,
+    texts: Query<'w, 's, &'static mut Text, With<CellText>>,
+}
+impl CellQueries<'_, '_> {
+    /// The cells among `children`, usually the children of a table
+    pub fn entities<'a>(
+        &'a self,
+        children: &'a [Entity],
+    ) -> impl Iterator<Item = (Entity, &'a Cell)> + 'a {
+        self.cells
+            .iter_many(children)
+            .map(|(id, cell, ..)| (id, cell))
+    }
+
+    /// Contents of the cells among `children`, usually the children of a table
+    pub fn read<'a>(
+        &'a self,
+        children: &'a [Entity],
+    ) -> impl Iterator<Item = (Cell, CellContents)> + 'a {
+        self.cells
+            .iter_many(children)
+            .map(|(_, cell, bg_color, cell_children)| {
+                let text = self
+                    .texts
+                    .iter_many(cell_children)
+                    .next()
+                    .and_then(|text| text.sections.first())
+                    .map(|section| section.value.clone())
+                    .unwrap_or_default();
+                (
+                    *cell,
+                    CellContents {
+                        text,
+                        bg_color: bg_color.clone(),
+                    },
+                )
+            })
+    }
+
+    /// Calls `edit` with every cell among `children`, its background and its text
+    pub fn edit(
+        &mut self,
+        children: &[Entity],
+        mut edit: impl FnMut(Cell, Mut<Handle<ColorMaterial>>, Mut<Text>),
+    ) {
+        let mut cells = self.cells.iter_many_mut(children);
+        while let Some((_, cell, bg_color, cell_children)) = cells.fetch_next() {
+            let mut texts = self.texts.iter_many_mut(cell_children);
+            if let Some(text) = texts.fetch_next() {
+                edit(*cell, bg_color, text);
+            }
+        }
+    }
+}
+
+/// Sets the text of a cell, keeping its style
+pub fn set_cell_text(text: &mut Text, value: String) {
+    if let Some(section) = text.sections.first_mut() {
+        section.value = value;
+    }
 }
 
 pub fn make_table(
     mut cmd: Commands,
-    mut table_head_q: Query<(Entity, &mut TableHead), With<Preview>>,
+    mut table_head_q: Query<(Entity, &mut TableHead, Option<&Children>), With<Preview>>,
     user_q: Query<&User>,
-    children_q: Query<&Children>,
-    transform_q: Query<&Transform>,
+    cell_q: Query<(Entity, &Cell)>,
     actions: Res<Actions>,
 ) {
     let anchor = actions.from;
     let user = user_q.single();
     let scale = user.current_config.cell_dimensions;
 
-    let Ok((id, mut table_head_mut)) = table_head_q.get_single_mut() else {
-        let table_head_bundle =
-            TableHead::with_transform(Transform::from_translation(anchor.extend(0.0)));
+    let Ok((id, mut table_head_mut, children)) = table_head_q.get_single_mut() else {
+        let table_head_bundle = TableHead::with_transform(
+            Transform::from_translation(anchor.extend(0.0)),
+            &user.current_config,
+        );
         cmd.spawn(table_head_bundle);
         return;
     };
@@ -94,72 +377,173 @@ pub fn make_table(
     let action_dimensions = actions.to - anchor;
     let num_rows = (-action_dimensions.y / scale.y).floor() as u32;
     let num_columns = (action_dimensions.x / scale.x).floor() as u32;
-    let prev_num_rows = table_head_mut.num_rows;
-    let prev_num_columns = table_head_mut.num_columns;
-
-    table_head_mut.num_rows = num_rows;
-    table_head_mut.num_columns = num_columns;
-
-    // Making new cells
-    // This will iterate over all new cells by coordinates, avoids creating dupes
-    for (row, column) in (prev_num_rows..num_rows)
-        .flat_map(|x| std::iter::repeat(x).zip(0..prev_num_columns))
-        .chain(
-            (0..prev_num_rows)
-                .flat_map(|x| std::iter::repeat(x).zip(prev_num_columns..num_columns)),
-        )
-        .chain(
-            (prev_num_rows..num_rows)
-                .flat_map(|x| std::iter::repeat(x).zip(prev_num_columns..num_columns)),
-        )
-    {
-        let cell_width = scale.x;
-        let cell_height = scale.y;
-        // TODO
-        // if cell dimensions can change while making table we have to change this to sum previous
-        // dimensions
-        let x_offset = cell_width / 2.0 + cell_width * column as f32;
-        let y_offset = -(cell_height / 2.0 + cell_width * row as f32);
-
-        let tform = Transform {
-            translation: Vec3::new(x_offset, y_offset, 0.0),
-            scale: scale.extend(1.0),
-            ..default()
-        };
 
-        // Spawn as child of table_head
-        cmd.entity(id).with_children(|c_cmd| {
-            c_cmd.spawn(Cell::new(
-                Cell,
-                tform,
-                user.current_config.cell_mesh.clone(),
-                "".to_owned(),
-                user.current_config.table_text_color,
-                user.current_config.table_bg_color.clone(),
-            ));
-        });
-    }
-
-    // Early return, no need to delete if we have the same or more cells
-    if prev_num_rows <= num_rows && prev_num_columns <= num_columns {
-        return;
+    let cells = cell_q.iter_many(children.into_iter().flatten());
+    resize_table(
+        &mut cmd,
+        id,
+        &mut table_head_mut,
+        IRect::new(0, 0, num_columns as i32, num_rows as i32),
+        &user.current_config,
+        cells,
+        |table_head, cell| CellContents::empty(table_head, &cell, &user.current_config),
+    );
+}
+
+/// Where `cell` is in its table, cells are unit squares scaled to their size
+fn cell_transform(table_head: &TableHead, cell: Cell) -> Transform {
+    Transform {
+        translation: table_head.cell_center(cell).extend(0.0),
+        scale: table_head.cell_size(cell).extend(1.0),
+        ..default()
     }
+}
 
-    // TODO
-    // if cell dimensions can change while making table we have to change this to sum previous dimensions
-    let max_x_offset = scale.x * num_columns as f32;
-    let max_y_offset = scale.y * num_rows as f32;
-    // Deleting cells
-    // This will iterate over all cells to be deleted by coordinates
-    for child in children_q.iter_descendants(id) {
-        let Ok(Transform { translation, .. }) = transform_q.get(child) else {
-            unreachable!()
-        };
-        if translation.x >= max_x_offset || -translation.y >= max_y_offset {
+/// Spawns `cell` with `contents` as a child of the table `id`
+fn spawn_cell(
+    cmd: &mut Commands,
+    id: Entity,
+    table_head: &TableHead,
+    cell: Cell,
+    config: &UserConfig,
+    CellContents { text, bg_color }: CellContents,
+) {
+    let scale = table_head.cell_size(cell);
+    let tform = cell_transform(table_head, cell);
+
+    // Spawn as child of table_head
+    cmd.entity(id).with_children(|c_cmd| {
+        c_cmd
+            .spawn(Cell::new(cell, tform, config.cell_mesh.clone(), bg_color))
+            .with_children(|t_cmd| {
+                t_cmd.spawn(Cell::text(
+                    text,
+                    config.table_text_color,
+                    config.table_font_size,
+                    scale,
+                ));
+            });
+    });
+}
+
+/// Grows or shrinks the table `id` to the columns and rows of `span`, which are counted from its
+/// current first column and row. Columns and rows before them are added at its left and top, or
+/// removed there, the others at its right and bottom.
+/// Only the cells that are new get created, filled by `fill`, and only the cells that fall outside
+/// of `span` get deleted, so the contents of all other cells are preserved.
+/// Returns how far the table has to move so that the kept cells stay in place
+pub fn resize_table<'a>(
+    cmd: &mut Commands,
+    id: Entity,
+    table_head: &mut TableHead,
+    span: IRect,
+    config: &UserConfig,
+    cells: impl IntoIterator<Item = (Entity, &'a Cell)>,
+    mut fill: impl FnMut(&TableHead, Cell) -> CellContents,
+) -> Vec2 {
+    let prev_size = IVec2::new(table_head.num_columns as i32, table_head.num_rows as i32);
+    let size = span.size().max(IVec2::ZERO);
+    let removed_start = span.min.clamp(IVec2::ZERO, prev_size);
+    let added_start = (-span.min).clamp(IVec2::ZERO, size);
+    let kept = (prev_size - removed_start)
+        .min(size - added_start)
+        .max(IVec2::ZERO);
+
+    // The sizes of a new table can be set before it has columns and rows, those are kept
+    let resize = |sizes: &mut Vec<f32>, axis: usize, default: f32| {
+        let removed = sizes.drain(..removed_start[axis] as usize).sum::<f32>();
+        sizes.truncate((size - added_start)[axis] as usize);
+        let added = std::iter::repeat_n(default, added_start[axis] as usize);
+        sizes.splice(0..0, added);
+        sizes.resize(size[axis] as usize, default);
+        removed - added_start[axis] as f32 * default
+    };
+    let moved = Vec2::new(
+        resize(&mut table_head.cell_widths, 0, config.cell_dimensions.x),
+        -resize(&mut table_head.cell_heights, 1, config.cell_dimensions.y),
+    );
+    table_head.num_columns = size.x as u32;
+    table_head.num_rows = size.y as u32;
+
+    // Deleting the cells that fall outside and renumbering the kept ones
+    let shift = added_start - removed_start;
+    for (child, cell) in cells {
+        let index = IVec2::new(cell.column as i32, cell.row as i32) - removed_start;
+        if index.cmplt(IVec2::ZERO).any() || index.cmpge(kept).any() {
             cmd.entity(id).remove_children(&[child]);
-            cmd.entity(child).despawn();
+            cmd.entity(child).despawn_recursive();
+        } else if shift != IVec2::ZERO {
+            let index = (index + added_start).as_uvec2();
+            let cell = Cell {
+                row: index.y,
+                column: index.x,
+            };
+            cmd.entity(child)
+                .insert((cell, cell_transform(table_head, cell)));
+        }
+    }
+
+    // Making new cells, the kept ones are skipped so there are no dupes
+    for row in 0..table_head.num_rows {
+        for column in 0..table_head.num_columns {
+            let index = IVec2::new(column as i32, row as i32) - added_start;
+            if index.cmplt(IVec2::ZERO).any() || index.cmpge(kept).any() {
+                let cell = Cell { row, column };
+                let contents = fill(table_head, cell);
+                spawn_cell(cmd, id, table_head, cell, config, contents);
+            }
         }
     }
+    moved
+}
+
+/// Spawns a table at `translation` with the headers and cell sizes of `table_head` and `size`
+/// columns and rows, each cell filled by `fill`
+pub fn spawn_table(
+    cmd: &mut Commands,
+    translation: Vec3,
+    table_head: &TableHead,
+    size: UVec2,
+    config: &UserConfig,
+    fill: impl FnMut(&TableHead, Cell) -> CellContents,
+) -> Entity {
+    let mut new_table_head = TableHead {
+        cell_widths: table_head.cell_widths.clone(),
+        cell_heights: table_head.cell_heights.clone(),
+        ..TableHead::new(table_head.header_rows, table_head.header_columns)
+    };
+    let id = cmd
+        .spawn(SpatialBundle::from_transform(Transform::from_translation(
+            translation,
+        )))
+        .id();
+    resize_table(
+        cmd,
+        id,
+        &mut new_table_head,
+        IRect::from_corners(IVec2::ZERO, size.as_ivec2()),
+        config,
+        std::iter::empty(),
+        fill,
+    );
+    cmd.entity(id).insert(new_table_head);
+    id
+}
+
+/// The topmost table at `pos` and the cell of it under `pos`
+pub fn table_at<'a>(
+    tables: impl IntoIterator<Item = (Entity, &'a TableHead, &'a GlobalTransform)>,
+    pos: Vec2,
+) -> Option<(Entity, Cell)> {
+    tables
+        .into_iter()
+        .filter_map(|(id, table_head, transform)| {
+            let translation = transform.translation();
+            let cell = table_head.cell_at(pos - translation.truncate())?;
+            Some((translation.z, id, cell))
+        })
+        .max_by(|(z_a, ..), (z_b, ..)| z_a.total_cmp(z_b))
+        .map(|(_, id, cell)| (id, cell))
 }
 
 fn table_outline(mut gizmos: Gizmos, action: Res<Actions>, user: Query<&User>) {
@@ -201,3 +585,81 @@ pub fn cleanup_empty_tables(
         cmd.entity(id).despawn_recursive();
     }
 }
+
+/// `H` adds a header row and `Shift+H` a header column, with `Alt` one is removed instead.
+/// Applies to the selected tables if there are any, otherwise to tables made from now on
+fn change_header_count(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut user_q: Query<&mut User>,
+    mut selected_q: Query<&mut TableHead, With<Selected>>,
+    mut status: EventWriter<Status>,
+) {
+    let columns = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let change: i32 = if keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        -1
+    } else {
+        1
+    };
+
+    if selected_q.is_empty() {
+        let config = &mut user_q.single_mut().current_config;
+        let headers = if columns {
+            &mut config.header_columns
+        } else {
+            &mut config.header_rows
+        };
+        *headers = headers.saturating_add_signed(change);
+        status.send(Status::info(format!(
+            "Header rows: {}, columns: {}",
+            config.header_rows, config.header_columns
+        )));
+    }
+    for mut table_head in &mut selected_q {
+        // Headers never outnumber the rows or columns of the table
+        let (headers, max) = if columns {
+            (table_head.header_columns, table_head.num_columns)
+        } else {
+            (table_head.header_rows, table_head.num_rows)
+        };
+        let headers = headers.saturating_add_signed(change).min(max);
+        if columns {
+            table_head.header_columns = headers;
+        } else {
+            table_head.header_rows = headers;
+        }
+    }
+}
+
+/// Gives header cells their distinct styling whenever the headers of a table change.
+/// Only cells that still have a default background are touched, so painted cells keep their color
+fn style_header_cells(
+    table_head_q: Query<(&TableHead, &Children), Changed<TableHead>>,
+    mut cell_q: Query<(&Cell, &mut Handle<ColorMaterial>)>,
+    user_q: Query<&User>,
+) {
+    let Ok(user) = user_q.get_single() else {
+        return;
+    };
+    let UserConfig {
+        table_bg_color,
+        table_header_bg_color,
+        ..
+    } = &user.current_config;
+
+    for (table_head, children) in &table_head_q {
+        let mut cells = cell_q.iter_many_mut(children);
+        while let Some((cell, mut bg_color)) = cells.fetch_next() {
+            if *bg_color != *table_bg_color && *bg_color != *table_header_bg_color {
+                continue;
+            }
+            let styled = if table_head.is_header(cell) {
+                table_header_bg_color
+            } else {
+                table_bg_color
+            };
+            if *bg_color != *styled {
+                *bg_color = styled.clone();
+            }
+        }
+    }
+}
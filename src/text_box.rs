@@ -0,0 +1,289 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::text::Text2dBounds;
+
+use crate::actions::{finish_actions, maintain_actions, Actions, Preview};
+use crate::editing::{TextEditing, TextLayouts};
+use crate::layers::Pickable;
+use crate::player::{Tool, User};
+use crate::status::Status;
+use crate::table::{table_at, Cell, CellText, TableHead};
+use crate::{ctrl_just_pressed, typing, using_tool, AppState, CanvasSet, WhenActionDoneSet};
+
+pub struct TextBoxPlugin;
+
+/// This plugin is responsible for text boxes placed anywhere on the canvas with `Tool::Text`.
+/// Clicking places a text box that grows with its text, dragging sets the width its lines wrap at.
+/// Clicking a text box or a cell of a table edits its text instead.
+/// While typing, `Ctrl+L`, `Ctrl+E` and `Ctrl+R` align the lines left, centered and right,
+/// `Ctrl+=` and `Ctrl+-` change the font size and the palette colors the text
+impl Plugin for TextBoxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                place_text_box
+                    .in_set(CanvasSet)
+                    .run_if(using_tool(Tool::Text))
+                    .after(maintain_actions),
+                edit_new_text_box
+                    .in_set(WhenActionDoneSet)
+                    .before(finish_actions),
+                (
+                    remove_empty_text_boxes,
+                    (
+                        justify_text.run_if(
+                            ctrl_just_pressed(KeyCode::KeyL)
+                                .or_else(ctrl_just_pressed(KeyCode::KeyE))
+                                .or_else(ctrl_just_pressed(KeyCode::KeyR)),
+                        ),
+                        change_font_size.run_if(
+                            ctrl_just_pressed(KeyCode::Equal)
+                                .or_else(ctrl_just_pressed(KeyCode::Minus)),
+                        ),
+                        color_text,
+                    )
+                        .run_if(typing),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            ),
+        );
+    }
+}
+
+/// Text placed on the canvas, positioned at its top left
+#[derive(Component, Debug)]
+pub struct TextBox {
+    /// Lines wrap at this width, otherwise only at line breaks
+    pub width: Option<f32>,
+}
+
+/// Drags narrower than this place a text box that grows with its text
+const MIN_WIDTH: f32 = 10.0;
+
+/// The texts of text boxes and cells that are not locked, with where they are drawn
+#[derive(SystemParam)]
+struct EditableTexts<'w, 's> {
+    layouts: TextLayouts<'w, 's>,
+    text_box_q:
+        Query<'w, 's, (Entity, &'static Text, &'static GlobalTransform), (With<TextBox>, Pickable)>,
+    table_head_q: Query<'w, 's, (Entity, &'static TableHead, &'static GlobalTransform), Pickable>,
+    children_q: Query<'w, 's, &'static Children>,
+    cell_q: Query<'w, 's, &'static Cell>,
+    text_q: Query<'w, 's, &'static Text, With<CellText>>,
+}
+
+fn place_text_box(
+    mut cmd: Commands,
+    actions: Res<Actions>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    user_q: Query<&User>,
+    mut editing: ResMut<TextEditing>,
+    texts: EditableTexts,
+    mut preview_q: Query<(&mut TextBox, &mut Text2dBounds, &mut Transform), With<Preview>>,
+) {
+    if actions.button_push != Some(MouseButton::Left) {
+        return;
+    }
+    let EditableTexts {
+        layouts,
+        text_box_q,
+        table_head_q,
+        children_q,
+        cell_q,
+        text_q,
+    } = texts;
+    let font_size = |text: &Text| text.sections.first().map_or(0.0, |s| s.style.font_size);
+    let value = |text: &Text| {
+        text.sections
+            .first()
+            .map(|s| s.value.clone())
+            .unwrap_or_default()
+    };
+
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        if let Ok((mut text_box, mut bounds, mut transform)) = preview_q.get_single_mut() {
+            let width = (actions.to.x - actions.from.x).abs();
+            let left = actions.from.x.min(actions.to.x);
+            text_box.width = (width >= MIN_WIDTH).then_some(width);
+            bounds.size.x = text_box.width.unwrap_or(f32::INFINITY);
+            transform.translation.x = if text_box.width.is_some() {
+                left
+            } else {
+                actions.from.x
+            };
+            return;
+        }
+        // Dragging over the edited text selects it
+        let Some(target) = editing.target else {
+            return;
+        };
+        let Some(text) = text_box_q
+            .get(target)
+            .map(|(_, text, _)| text)
+            .or_else(|_| text_q.get(target))
+            .ok()
+        else {
+            return;
+        };
+        if let Some((layout, _)) = layouts.get(target, font_size(text)) {
+            editing.caret = layout.index_at(&value(text), actions.to);
+        }
+        return;
+    }
+
+    // Clicking a text box edits it, the topmost one if they overlap
+    let hit = text_box_q
+        .iter()
+        .filter_map(|(id, text, transform)| {
+            let (layout, size) = layouts.get(id, font_size(text))?;
+            layout
+                .bounds(size)
+                .contains(actions.from)
+                .then(|| (id, layout.index_at(&value(text), actions.from), transform))
+        })
+        .max_by(|(.., a), (.., b)| a.translation().z.total_cmp(&b.translation().z));
+    if let Some((id, caret, _)) = hit {
+        editing.start(id, caret);
+        return;
+    }
+
+    // Clicking a cell edits the text of the cell
+    if let Some((table, cell)) = table_at(&table_head_q, actions.from) {
+        let cell_text = children_q
+            .get(table)
+            .into_iter()
+            .flatten()
+            .find(|child| cell_q.get(**child).is_ok_and(|c| *c == cell))
+            .and_then(|cell| children_q.get(*cell).ok())
+            .into_iter()
+            .flatten()
+            .find(|child| text_q.contains(**child));
+        if let Some(id) = cell_text {
+            let text = text_q.get(*id).map(value).unwrap_or_default();
+            let caret = text_q
+                .get(*id)
+                .ok()
+                .and_then(|t| layouts.get(*id, font_size(t)))
+                .map_or(text.len(), |(layout, _)| {
+                    layout.index_at(&text, actions.from)
+                });
+            editing.start(*id, caret);
+        }
+        return;
+    }
+
+    editing.stop();
+    let config = &user_q.single().current_config;
+    cmd.spawn((
+        TextBox { width: None },
+        Preview,
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: config.text_font_size,
+                    color: config.paint_color,
+                    ..default()
+                },
+            )
+            .with_justify(config.text_justify),
+            text_anchor: Anchor::TopLeft,
+            transform: Transform::from_translation(actions.from.extend(0.0)),
+            ..default()
+        },
+    ));
+}
+
+/// Text boxes are edited right after they are placed
+fn edit_new_text_box(
+    mut editing: ResMut<TextEditing>,
+    text_box_q: Query<Entity, (With<TextBox>, With<Preview>)>,
+) {
+    if let Ok(id) = text_box_q.get_single() {
+        editing.start(id, 0);
+    }
+}
+
+/// Text boxes that were left without any text are removed
+fn remove_empty_text_boxes(
+    mut cmd: Commands,
+    editing: Res<TextEditing>,
+    text_box_q: Query<(Entity, &Text), (With<TextBox>, Without<Preview>)>,
+) {
+    if !editing.is_changed() {
+        return;
+    }
+    for (id, text) in &text_box_q {
+        let empty = text.sections.iter().all(|section| section.value.is_empty());
+        if empty && editing.target != Some(id) {
+            cmd.entity(id).despawn();
+        }
+    }
+}
+
+/// Aligns the lines of the edited text box, and of text boxes placed from now on
+fn justify_text(
+    keys: Res<ButtonInput<KeyCode>>,
+    editing: Res<TextEditing>,
+    mut user_q: Query<&mut User>,
+    mut text_box_q: Query<&mut Text, With<TextBox>>,
+) {
+    let justify = if keys.just_pressed(KeyCode::KeyL) {
+        JustifyText::Left
+    } else if keys.just_pressed(KeyCode::KeyE) {
+        JustifyText::Center
+    } else {
+        JustifyText::Right
+    };
+    user_q.single_mut().current_config.text_justify = justify;
+    if let Some(mut text) = editing.target.and_then(|id| text_box_q.get_mut(id).ok()) {
+        text.justify = justify;
+    }
+}
+
+fn change_font_size(
+    keys: Res<ButtonInput<KeyCode>>,
+    editing: Res<TextEditing>,
+    mut user_q: Query<&mut User>,
+    mut text_box_q: Query<&mut Text, With<TextBox>>,
+    mut status: EventWriter<Status>,
+) {
+    let change = if keys.just_pressed(KeyCode::Equal) {
+        2.0
+    } else {
+        -2.0
+    };
+    let config = &mut user_q.single_mut().current_config;
+    let mut font_size = config.text_font_size;
+    if let Some(mut text) = editing.target.and_then(|id| text_box_q.get_mut(id).ok()) {
+        for section in &mut text.sections {
+            section.style.font_size = (section.style.font_size + change).clamp(6.0, 200.0);
+            font_size = section.style.font_size;
+        }
+    } else {
+        font_size = (font_size + change).clamp(6.0, 200.0);
+    }
+    config.text_font_size = font_size;
+    status.send(Status::info(format!("Font size: {font_size}")));
+}
+
+/// Colors the edited text box with the paint color when another one is chosen
+fn color_text(
+    editing: Res<TextEditing>,
+    user_q: Query<&User>,
+    mut last_color: Local<Option<Color>>,
+    mut text_box_q: Query<&mut Text, With<TextBox>>,
+) {
+    let color = user_q.single().current_config.paint_color;
+    if last_color.replace(color).is_none_or(|last| last == color) {
+        return;
+    }
+    if let Some(mut text) = editing.target.and_then(|id| text_box_q.get_mut(id).ok()) {
+        for section in &mut text.sections {
+            section.style.color = color;
+        }
+    }
+}
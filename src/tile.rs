@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use crate::layers::Pickable;
+use crate::selection::{Selected, SelectedCells};
+use crate::status::Status;
+use crate::table::{set_cell_text, Cell, CellContents, CellQueries, TableHead};
+use crate::{alt_pressed, ctrl_just_pressed, shift_pressed, typing, AppState};
+use bevy::prelude::*;
+
+pub struct TilePlugin;
+
+/// This plugin is responsible for repeating a motif across tables. `Ctrl+Shift+T` takes the
+/// selected range of cells as the motif, `Ctrl+T` stamps it across the selected tables, leaving
+/// their header rows and columns alone. The table the motif was taken from is tiled so that the
+/// motif stays in place, other tables start with it at their first cell that is not a header.
+/// `Alt+O`, `Alt+M` and `Alt+R` cycle through the offset, mirroring and rotation of the repeats
+impl Plugin for TilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileSettings>()
+            .init_resource::<TileMotif>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        capture_motif.run_if(shift_pressed),
+                        tile_motif.run_if(not(shift_pressed)),
+                    )
+                        .run_if(ctrl_just_pressed(KeyCode::KeyT)),
+                    cycle_tile_settings.run_if(alt_pressed),
+                )
+                    .run_if(not(typing))
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// How every other repeat of the motif is shifted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOffset {
+    #[default]
+    None,
+    /// Every other row of repeats is shifted right by half the motif width
+    Brick,
+    /// Every other column of repeats is shifted down by half the motif height
+    HalfDrop,
+}
+
+/// How much each repeat is rotated relative to the previous one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileRotation {
+    #[default]
+    None,
+    /// Quarter turns only apply to square motifs, others are rotated by half turns instead
+    Quarter,
+    Half,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct TileSettings {
+    pub offset: TileOffset,
+    /// Mirror every other column of repeats horizontally
+    pub mirror_x: bool,
+    /// Mirror every other row of repeats vertically
+    pub mirror_y: bool,
+    pub rotation: TileRotation,
+}
+
+/// The repeat unit, its cells are relative to its top left cell
+#[derive(Resource, Default)]
+pub struct TileMotif {
+    size: UVec2,
+    cells: HashMap<Cell, CellContents>,
+    /// Where the motif was taken from, its own table is tiled so that the motif stays in place
+    origin: Option<(Entity, Cell)>,
+}
+impl TileMotif {
+    /// The cell of the motif that is stamped onto `cell` of a table, if the motif starts at `origin`
+    fn source(&self, settings: &TileSettings, origin: Cell, cell: Cell) -> Option<Cell> {
+        if self.size.cmpeq(UVec2::ZERO).any() {
+            return None;
+        }
+        let size = self.size.as_ivec2();
+        let mut offset = IVec2::new(
+            cell.column as i32 - origin.column as i32,
+            cell.row as i32 - origin.row as i32,
+        );
+
+        // Index of the repeat that `cell` lies in
+        let repeat = match settings.offset {
+            TileOffset::None => offset.div_euclid(size),
+            TileOffset::Brick => {
+                let row = offset.y.div_euclid(size.y);
+                offset.x -= row.rem_euclid(2) * size.x / 2;
+                IVec2::new(offset.x.div_euclid(size.x), row)
+            }
+            TileOffset::HalfDrop => {
+                let column = offset.x.div_euclid(size.x);
+                offset.y -= column.rem_euclid(2) * size.y / 2;
+                IVec2::new(column, offset.y.div_euclid(size.y))
+            }
+        };
+        let mut local = offset.rem_euclid(size);
+
+        if settings.mirror_x && repeat.x.rem_euclid(2) == 1 {
+            local.x = size.x - 1 - local.x;
+        }
+        if settings.mirror_y && repeat.y.rem_euclid(2) == 1 {
+            local.y = size.y - 1 - local.y;
+        }
+
+        let turns = match settings.rotation {
+            TileRotation::None => 0,
+            TileRotation::Quarter if size.x == size.y => (repeat.x + repeat.y).rem_euclid(4),
+            TileRotation::Quarter | TileRotation::Half => (repeat.x + repeat.y).rem_euclid(2) * 2,
+        };
+        local = match turns {
+            0 => local,
+            2 => size - IVec2::ONE - local,
+            // Quarter turns only happen for square motifs, so the size stays the same
+            1 => IVec2::new(local.y, size.x - 1 - local.x),
+            _ => IVec2::new(size.y - 1 - local.y, local.x),
+        };
+
+        Some(Cell {
+            row: local.y as u32,
+            column: local.x as u32,
+        })
+    }
+}
+
+/// Takes the selected range of cells as the motif
+fn capture_motif(
+    selected_cells: Option<Res<SelectedCells>>,
+    table_head_q: Query<&Children, With<TableHead>>,
+    cell_q: CellQueries,
+    mut motif: ResMut<TileMotif>,
+    mut status: EventWriter<Status>,
+) {
+    let Some(range) = selected_cells else {
+        status.send(Status::warning("Select the cells of the motif first"));
+        return;
+    };
+    let Ok(children) = table_head_q.get(range.table) else {
+        return;
+    };
+    let min = range.min();
+
+    motif.size = range.size();
+    motif.origin = Some((range.table, min));
+    motif.cells = cell_q
+        .read(children)
+        .filter(|(cell, _)| range.contains(*cell))
+        .map(|(cell, contents)| {
+            let local = Cell {
+                row: cell.row - min.row,
+                column: cell.column - min.column,
+            };
+            (local, contents)
+        })
+        .collect();
+    status.send(Status::info(format!(
+        "Captured a motif of {} by {} cells, stamp it with Ctrl+T",
+        motif.size.x, motif.size.y
+    )));
+}
+
+/// Stamps the motif across every selected table, except for their header cells
+fn tile_motif(
+    settings: Res<TileSettings>,
+    motif: Res<TileMotif>,
+    selected_q: Query<(Entity, &TableHead, &Children), (With<Selected>, Pickable)>,
+    mut cell_q: CellQueries,
+    mut status: EventWriter<Status>,
+) {
+    if motif.cells.is_empty() {
+        status.send(Status::warning(
+            "There is no motif yet, capture the selected cells with Ctrl+Shift+T",
+        ));
+        return;
+    }
+    for (id, table_head, children) in &selected_q {
+        let origin = match motif.origin {
+            Some((table, origin)) if table == id => origin,
+            _ => Cell {
+                row: table_head.header_rows,
+                column: table_head.header_columns,
+            },
+        };
+        cell_q.edit(children, |cell, mut bg_color, mut text| {
+            if table_head.is_header(&cell) {
+                return;
+            }
+            let Some(contents) = motif
+                .source(&settings, origin, cell)
+                .and_then(|source| motif.cells.get(&source))
+            else {
+                return;
+            };
+            if *bg_color != contents.bg_color {
+                *bg_color = contents.bg_color.clone();
+            }
+            if text.sections.first().map(|section| &section.value) != Some(&contents.text) {
+                set_cell_text(&mut text, contents.text.clone());
+            }
+        });
+    }
+}
+
+fn cycle_tile_settings(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<TileSettings>,
+    mut status: EventWriter<Status>,
+) {
+    if keys.just_pressed(KeyCode::KeyO) {
+        settings.offset = match settings.offset {
+            TileOffset::None => TileOffset::Brick,
+            TileOffset::Brick => TileOffset::HalfDrop,
+            TileOffset::HalfDrop => TileOffset::None,
+        };
+    } else if keys.just_pressed(KeyCode::KeyM) {
+        (settings.mirror_x, settings.mirror_y) = match (settings.mirror_x, settings.mirror_y) {
+            (false, false) => (true, false),
+            (true, false) => (false, true),
+            (false, true) => (true, true),
+            (true, true) => (false, false),
+        };
+    } else if keys.just_pressed(KeyCode::KeyR) {
+        settings.rotation = match settings.rotation {
+            TileRotation::None => TileRotation::Quarter,
+            TileRotation::Quarter => TileRotation::Half,
+            TileRotation::Half => TileRotation::None,
+        };
+    } else {
+        return;
+    }
+    let mirrored = match (settings.mirror_x, settings.mirror_y) {
+        (false, false) => "not mirrored",
+        (true, false) => "mirrored horizontally",
+        (false, true) => "mirrored vertically",
+        (true, true) => "mirrored both ways",
+    };
+    status.send(Status::info(format!(
+        "Tiling with {:?} offset, {mirrored}, {:?} rotation",
+        settings.offset, settings.rotation
+    )));
+}